@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use harvester::Analyzer;
+
+fn analyze(c: &mut Criterion) {
+    let analyzer = Analyzer::new();
+    let names = [
+        ("season_episode", "Show.Name.S01E05.1080p.WEB.x264-GRP.mkv"),
+        ("episode_dash", "[Group] Show Name - 05 [1080p].mkv"),
+        ("episode_quoted_name", "Show Name E05 'Episode Title'.mkv"),
+        ("episode", "Show Name 05.mp4"),
+        ("movie_year", "Movie.Name.1999.1080p.BluRay.x264.mkv"),
+        ("garbage", "Show Name - 05.srt"),
+    ];
+
+    let mut group = c.benchmark_group("analyze");
+    for (rule, name) in names {
+        let path = std::path::PathBuf::from(name);
+        group.bench_function(rule, |b| b.iter(|| analyzer.analyze(std::hint::black_box(&path))));
+    }
+    let pack = (1..=24)
+        .map(|e| std::path::PathBuf::from(format!("[Group] Show Name S01 1080p/[Group] Show Name - {:02} [1080p][ABCD1234].mkv", e)))
+        .collect::<Vec<_>>();
+    group.bench_function("season_pack", |b| b.iter(|| pack.iter().for_each(|p| { analyzer.analyze(std::hint::black_box(p)); })));
+    group.finish();
+}
+
+fn scan(c: &mut Criterion) {
+    let root = std::env::temp_dir().join(format!("harvester-bench-{}", std::process::id()));
+    harvester::synthetic::generate_tree(&root, 50, 24, 200).unwrap();
+
+    let analyzer = Analyzer::new();
+    c.bench_function("analyze_directory", |b| b.iter(|| analyzer.analyze_directory(&root)));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+criterion_group!(benches, analyze, scan);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+//! Generates a synthetic incoming tree to reproduce performance problems:
+//!
+//!     cargo run --features bench --example synthetic_tree -- <dir> [shows] [episodes] [movies]
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let Some(root) = args.first() else {
+        eprintln!("usage: synthetic_tree <dir> [shows] [episodes] [movies]");
+        std::process::exit(1);
+    };
+
+    let number = |i: usize, default: usize| args.get(i).and_then(|n| n.parse().ok()).unwrap_or(default);
+
+    let count = harvester::synthetic::generate_tree(std::path::Path::new(root), number(1, 50), number(2, 24), number(3, 200)).unwrap();
+    println!("created {} files under {:?}", count, root);
+}
@@ -0,0 +1,977 @@
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::MetadataExt;
+use std::sync::LazyLock;
+
+use crate::camera;
+use crate::config::{BrokenFilesConfig, Config, CustomRule, PartPolicy, ShortEpisodesConfig};
+use crate::find_all_files;
+use crate::lru::Cache;
+
+/// Below this many bytes per second of the minimum duration a file without a duration is
+/// considered too short, that's a lower bound on even heavily compressed video.
+const MIN_BYTES_PER_SECOND: u64 = 32 * 1024;
+
+/// The built-in rules compiled once and shared, cloning a `Regex` is cheap.
+static BUILTIN: LazyLock<Analyzer> = LazyLock::new(|| Analyzer::compile(&Rule::DEFAULT_PRIORITY, &[]));
+
+/// Names with nothing but the episode (`01`, `Episode 2`, `S02E03`), see `Analyzer::directory_episode`.
+static NAMELESS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:s(\d+) ?)?(?:episode |ep ?|e)?(\d{1,3})(?:v\d)?$").unwrap());
+static SEASON_DIR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:(?:season|series|s) ?(\d+)|specials?)$").unwrap());
+/// Season packs, `Show S02 1080p` or `Show Season 2`.
+static SHOW_DIR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(.+?) (?:s(\d+)|season (\d+))(?: .*)?$").unwrap());
+/// Enough for the stems and directory names of a few season packs in a row, the cache only helps
+/// across files named alike.
+const CACHE_SIZE: usize = 4096;
+
+/// Words telling `Performer - Special (2021)` apart from `Franchise - Subtitle (2021)` movies.
+static PERFORMANCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:stand[ -]?up|comedy special|live (?:at|from|in)|in concert|concert film|unplugged)\b").unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MediaData {
+    Movie { title: String, year: Option<u32> },
+    /// One of the files a movie is split into, see `PartPolicy`.
+    MoviePart { title: String, year: Option<u32>, part: u32 },
+    ShowEpisode { name: String, season: u32, episode: u32 },
+    ShowSpecial { name: String, label: String },
+    ShowMovie { name: String, title: String, year: Option<u32> },
+    /// Camera footage, filed by the date it was taken.
+    HomeVideo { year: u32, month: u32, day: u32 },
+    /// Filed by the date it was taken, like home videos.
+    Photo { year: u32, month: u32, day: u32 },
+    Book { author: Option<String>, title: String },
+    Comic { series: String, volume: Option<u32>, issue: Option<u32> },
+    /// Stand-up specials and concerts.
+    Performance { performer: String, title: String, year: Option<u32> },
+    /// `event` is what tells it apart within the season, like `round 10 race` or `300`.
+    SportsEvent { league: String, season: Option<u32>, event: String },
+    Garbage,
+}
+
+impl std::fmt::Display for MediaData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let year = |year: &Option<u32>| year.map_or(String::new(), |y| format!(" ({})", y));
+
+        match self {
+            MediaData::Movie { title, year: y } => write!(f, "movie {}{}", title, year(y)),
+            MediaData::MoviePart { title, year: y, part } => write!(f, "movie {}{} part {}", title, year(y), part),
+            MediaData::ShowEpisode { name, season, episode } => write!(f, "episode {} S{:02}E{:02}", name, season, episode),
+            MediaData::ShowSpecial { name, label } => write!(f, "special {} {}", name, label),
+            MediaData::ShowMovie { name, title, year: y } => write!(f, "show movie {} - {}{}", name, title, year(y)),
+            MediaData::Performance { performer, title, year: y } => write!(f, "performance {} - {}{}", performer, title, year(y)),
+            MediaData::SportsEvent { league, season: Some(season), event } => write!(f, "sports {} {} {}", league, season, event),
+            MediaData::SportsEvent { league, season: None, event } => write!(f, "sports {} {}", league, event),
+            MediaData::HomeVideo { year, month, day } => write!(f, "home video {}-{:02}-{:02}", year, month, day),
+            MediaData::Photo { year, month, day } => write!(f, "photo {}-{:02}-{:02}", year, month, day),
+            MediaData::Book { author: Some(author), title } => write!(f, "book {} - {}", author, title),
+            MediaData::Book { author: None, title } => write!(f, "book {}", title),
+            MediaData::Comic { series, volume, issue } => {
+                write!(f, "comic {}", series)?;
+                if let Some(volume) = volume { write!(f, " v{:02}", volume)?; }
+                if let Some(issue) = issue { write!(f, " #{:03}", issue)?; }
+                Ok(())
+            }
+            MediaData::Garbage => write!(f, "garbage"),
+        }
+    }
+}
+
+impl MediaData {
+    /// The show, movie, performer, league or series it's filed under, if it has one.
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            MediaData::ShowEpisode { name, .. } | MediaData::ShowSpecial { name, .. } | MediaData::ShowMovie { name, .. } => Some(name),
+            MediaData::Movie { title, .. } | MediaData::MoviePart { title, .. } => Some(title),
+            MediaData::Performance { performer, .. } => Some(performer),
+            MediaData::SportsEvent { league, .. } => Some(league),
+            MediaData::Book { title, .. } => Some(title),
+            MediaData::Comic { series, .. } => Some(series),
+            MediaData::HomeVideo { .. } | MediaData::Photo { .. } | MediaData::Garbage => None,
+        }
+    }
+
+    /// Whether a name that ends up in its link path has nothing but separators and punctuation
+    /// in it, like the ones a rule captures from ` - 01` or `.S01E02`. Linking those would make
+    /// folders like `shows//Season 1`.
+    pub fn is_untitled(&self) -> bool {
+        let blank = |name: &str| !name.chars().any(char::is_alphanumeric);
+
+        match self {
+            MediaData::ShowMovie { title, .. } | MediaData::Performance { title, .. } if blank(title) => true,
+            MediaData::Book { author: Some(author), .. } if blank(author) => true,
+            media => media.title().is_some_and(blank),
+        }
+    }
+}
+
+pub struct ScannedFile {
+    pub path: std::path::PathBuf,
+    pub metadata: Option<MediaData>,
+    pub group: Option<String>,
+    pub version: Option<String>,
+    pub inode: u64,
+    pub size: u64,
+    /// Hard link count when scanned.
+    pub links: u64,
+    /// The last episode in the file, for files holding several (`S01E01-E02`).
+    pub last_episode: Option<u32>,
+    /// Why the file couldn't be recognized, when it couldn't.
+    pub warning: Option<String>,
+    /// Why a video looks like a failed download, it's left out when it does.
+    pub broken: Option<&'static str>,
+}
+
+/// Filename rules, tried in priority order against the cleaned up file stem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    TitleSeasonEpisode,
+    TitleSeasonSpecial,
+    TitleShowMovie,
+    TitleSpecial,
+    TitleEpisodeDash,
+    TitleEpisodeQuotedName,
+    /// Spanish `Cap.103`, season and episode run together.
+    TitleEpisodeCap,
+    /// `Episodio 5`, `Folge 3` and the like.
+    TitleEpisodeWord,
+    /// `第5話`.
+    TitleEpisodeCjk,
+    /// Daily shows, the year as the season and the date as the episode (`0315`).
+    TitleDate,
+    /// `F1 2024 Round 10 Race`, the year as the season.
+    SportsRound,
+    /// Numbered events of known leagues, like `UFC 300`.
+    SportsNumbered,
+    /// `Comedian - Special Name (2021)`, for configured performers or names saying it's a
+    /// performance (`Band - Live at Wembley (1986)`), it's a movie otherwise.
+    PerformerSpecial,
+    /// `Title Part 1 (2021)`, a movie's part or a miniseries episode depending on `PartPolicy`.
+    TitlePart,
+    TitleEpisode,
+    /// `Heat (1995)`, the year in parentheses like the library names movies.
+    MovieYearParens,
+    MovieYear,
+    /// A user provided pattern, by its position in `custom_rules`.
+    #[serde(skip)]
+    Custom(usize),
+}
+
+impl Rule {
+    pub const DEFAULT_PRIORITY: [Rule; 17] = [
+        Rule::TitleSeasonEpisode,
+        Rule::TitleSeasonSpecial,
+        Rule::TitleShowMovie,
+        Rule::TitleSpecial,
+        Rule::TitleEpisodeDash,
+        Rule::TitleEpisodeQuotedName,
+        Rule::TitleEpisodeCap,
+        Rule::TitleEpisodeWord,
+        Rule::TitleEpisodeCjk,
+        Rule::TitleDate,
+        Rule::SportsRound,
+        Rule::SportsNumbered,
+        Rule::PerformerSpecial,
+        Rule::TitlePart,
+        Rule::TitleEpisode,
+        Rule::MovieYearParens,
+        Rule::MovieYear,
+    ];
+
+    /// `None` for custom rules, their pattern lives in the config.
+    pub fn pattern(&self) -> Option<&'static str> {
+        Some(match self {
+            Rule::TitleSeasonEpisode => r"(.*) [sS](\d+)[eE](\d+)(?:-?[eE]\d+|-\d{1,3})*(?: (.*)|$)",
+            Rule::TitleSeasonSpecial => r"^(.+?) s(\d+) (specials?)(?: (\d+))?$",
+            Rule::TitleShowMovie => r"^(.+?) - (?:the )?(movie(?: \d+)?)(?: \(?(\d{4})\)?)?$",
+            Rule::TitleSpecial => r"^(.+?)(?: -)? ((?:nc)?op|(?:nc)?ed|ova|oad|sp|special) ?(\d+)?(v\d)?$",
+            Rule::TitleEpisodeDash => r"^(.*) - (\d+)(v\d)?( END)?( .*)?$",
+            Rule::TitleEpisodeQuotedName => r"^(.*) [eE](\d+)( END)? '.*'?$",
+            Rule::TitleEpisodeCap => r"^(.+?) cap ?(\d{1,2})(\d{2})(?: .*)?$",
+            Rule::TitleEpisodeWord => r"^(.+?)(?: -)? (?:episodio|épisode|folge|cap[ií]tulo|odcinek|aflevering|avsnitt|jakso|серия) ?(\d+)(?: .*)?$",
+            Rule::TitleEpisodeCjk => r"^(.+?) ?第(\d+)[話话集回](?: .*)?$",
+            Rule::TitleDate => r"^(.+?) (?:(\d{4})[ -](\d{2})[ -](\d{2})|(\d{2})[ -](\d{2})[ -](\d{4}))(?: .*)?$",
+            Rule::SportsRound => r"^(.+?) (\d{4}) (?:round|rd|r) ?(\d+)(?: (race|qualifying|sprint(?: qualifying| shootout)?|practice \d|fp\d|highlights))?(?: .*)?$",
+            Rule::SportsNumbered => r"^(ufc|bellator|pfl|ksw|cage warriors|invicta fc|one fight night) (\d+)(?: .*)?$",
+            Rule::PerformerSpecial => r"^(.+?) - (.+?) \((\d{4})\)(?: .*)?$",
+            Rule::TitlePart => r"^(.+?)(?: \(?(\d{4})\)?)? (?:part|pt) ?(\d{1,2}|one|two|three|four|five|six|seven|eight|nine|ten)(?: \(?(\d{4})\)?)?(?: .*)?$",
+            Rule::TitleEpisode => r"^(.*) (\d+)( END)?( \((.*)\))?( v2)?$",
+            Rule::MovieYearParens => r"^(.+?) \((\d{4})\)(?: .*)?$",
+            Rule::MovieYear => r"(.*[^-]) (\d{4})( [^-]|$)",
+            Rule::Custom(_) => return None,
+        })
+    }
+
+    fn extract(&self, x: &regex::Captures) -> Option<MediaData> {
+        if let Rule::Custom(_) = self {
+            return extract_named(x);
+        }
+
+        let text = |i: usize| x.get(i).unwrap().as_str().to_string();
+        let number = |i: usize| x.get(i).unwrap().as_str().parse::<u32>().unwrap();
+
+        Some(match self {
+            Rule::TitleSeasonEpisode => MediaData::ShowEpisode { name: text(1), season: number(2), episode: number(3) },
+            Rule::TitleSeasonSpecial => MediaData::ShowSpecial {
+                name: text(1),
+                label: format!("s{:02} special{}", number(2), x.get(4).map_or(String::new(), |n| format!(" {}", n.as_str()))),
+            },
+            Rule::TitleShowMovie => MediaData::ShowMovie {
+                name: text(1),
+                title: text(2),
+                year: x.get(3).map(|y| y.as_str().parse::<u32>().unwrap()),
+            },
+            Rule::TitleSpecial => MediaData::ShowSpecial {
+                name: text(1),
+                label: format!("{}{}", text(2), x.get(3).map_or("", |n| n.as_str())),
+            },
+            // `Blade Runner 2049 (2017)`: a year in parentheses after the number makes it part of a
+            // movie's title rather than an episode.
+            Rule::TitleEpisode if x.get(5).and_then(|y| y.as_str().parse::<u32>().ok()).is_some_and(plausible_year) => {
+                MediaData::Movie { title: format!("{} {}", text(1), text(2)), year: Some(number(5)) }
+            }
+            // `Show 05 1080`: the number before takes its place, without one it's not an episode.
+            Rule::TitleEpisode if is_technical(x.get(2).unwrap().as_str()) => {
+                let (name, episode) = x.get(1).unwrap().as_str().rsplit_once(' ')?;
+                MediaData::ShowEpisode { name: name.to_string(), season: 1, episode: episode.parse().ok()? }
+            }
+            Rule::TitleEpisodeDash | Rule::TitleEpisodeQuotedName | Rule::TitleEpisodeWord | Rule::TitleEpisodeCjk | Rule::TitleEpisode => {
+                MediaData::ShowEpisode { name: text(1), season: 1, episode: number(2) }
+            }
+            Rule::TitleEpisodeCap => MediaData::ShowEpisode { name: text(1), season: number(2), episode: number(3) },
+            Rule::TitleDate => {
+                let (year, month, day) = match x.get(2) {
+                    Some(_) => (number(2), number(3), number(4)),
+                    None => (number(7), number(6), number(5)),
+                };
+                if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+                    return None;
+                }
+
+                MediaData::ShowEpisode { name: text(1), season: gregorian_year(year), episode: month * 100 + day }
+            }
+            Rule::SportsRound => MediaData::SportsEvent {
+                league: text(1),
+                season: Some(number(2)),
+                event: format!("round {}{}", number(3), x.get(4).map_or(String::new(), |s| format!(" {}", s.as_str()))),
+            },
+            Rule::SportsNumbered => MediaData::SportsEvent { league: text(1), season: None, event: number(2).to_string() },
+            Rule::PerformerSpecial => MediaData::Performance { performer: text(1), title: text(2), year: Some(number(3)) },
+            Rule::TitlePart => {
+                const WORDS: [&str; 10] = ["one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten"];
+                let part = x.get(3).unwrap().as_str();
+                let part = WORDS.iter().position(|w| *w == part).map_or_else(|| part.parse().unwrap(), |i| i as u32 + 1);
+                let year = x.get(2).or(x.get(4)).map(|y| y.as_str().parse::<u32>().unwrap());
+
+                MediaData::MoviePart { title: text(1), year, part }
+            }
+            // A year no release could be from is a number in the title, like `Blade Runner 2049`.
+            Rule::MovieYear | Rule::MovieYearParens if !plausible_year(number(2)) => {
+                MediaData::Movie { title: format!("{} {}", text(1), text(2)), year: None }
+            }
+            Rule::MovieYear | Rule::MovieYearParens => MediaData::Movie { title: text(1), year: Some(number(2)) },
+            Rule::Custom(_) => unreachable!(),
+        })
+    }
+}
+
+/// The names `rules` takes in the config.
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Rule::TitleSeasonEpisode => write!(f, "title_season_episode"),
+            Rule::TitleSeasonSpecial => write!(f, "title_season_special"),
+            Rule::TitleShowMovie => write!(f, "title_show_movie"),
+            Rule::TitleSpecial => write!(f, "title_special"),
+            Rule::TitleEpisodeDash => write!(f, "title_episode_dash"),
+            Rule::TitleEpisodeQuotedName => write!(f, "title_episode_quoted_name"),
+            Rule::TitleEpisodeCap => write!(f, "title_episode_cap"),
+            Rule::TitleEpisodeWord => write!(f, "title_episode_word"),
+            Rule::TitleEpisodeCjk => write!(f, "title_episode_cjk"),
+            Rule::TitleDate => write!(f, "title_date"),
+            Rule::SportsRound => write!(f, "sports_round"),
+            Rule::SportsNumbered => write!(f, "sports_numbered"),
+            Rule::PerformerSpecial => write!(f, "performer_special"),
+            Rule::TitlePart => write!(f, "title_part"),
+            Rule::TitleEpisode => write!(f, "title_episode"),
+            Rule::MovieYearParens => write!(f, "movie_year_parens"),
+            Rule::MovieYear => write!(f, "movie_year"),
+            Rule::Custom(i) => write!(f, "custom rule {}", i),
+        }
+    }
+}
+
+/// Rules matching a name, and what each of them extracts from it.
+pub type Candidates = Vec<(Rule, Option<MediaData>)>;
+
+/// Full-width letters, digits and spaces as their ASCII counterparts, and CJK brackets as the
+/// ones the rules expect: `【Group】` is a tag like `[Group]`, `「Title」` is left as words.
+pub fn normalize_width(name: &str) -> String {
+    let normalized = name
+        .chars()
+        .map(|c| match c {
+            '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap(),
+            '\u{3000}' | '・' | '「' | '」' | '『' | '』' => ' ',
+            '【' | '〔' | '〖' => '[',
+            '】' | '〕' | '〗' => ']',
+            c => c,
+        })
+        .collect::<String>();
+
+    match normalized == name {
+        true => normalized,
+        false => normalized.split(' ').filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// More parts than this is a miniseries, for `PartPolicy::Auto`.
+const MAX_MOVIE_PARTS: u32 = 3;
+
+/// Bare numbers releases give the resolution or the codec with, `1080` or `264`.
+fn is_technical(token: &str) -> bool {
+    matches!(token, "480" | "576" | "720" | "1080" | "1440" | "2160" | "4320" | "264" | "265")
+}
+
+/// From the first films to next year's, for releases ahead of their premiere.
+fn plausible_year(year: u32) -> bool {
+    (1888..=camera::civil_date(crate::state::now()).0 + 1).contains(&year)
+}
+
+/// Years in the Thai solar calendar (2567 is 2024) as Gregorian ones, nothing airs that far ahead.
+fn gregorian_year(year: u32) -> u32 {
+    if year >= 2400 { year - 543 } else { year }
+}
+
+/// Custom rules name their captures: `name` with `episode` (and maybe `season`) for episodes,
+/// `title` (and maybe `year`) for movies.
+fn extract_named(x: &regex::Captures) -> Option<MediaData> {
+    let text = |group: &str| x.name(group).map(|m| m.as_str().trim().to_string());
+    let number = |group: &str| x.name(group).map(|m| m.as_str().parse::<u32>().ok());
+
+    match (text("name"), number("episode"), text("title")) {
+        (Some(name), Some(episode), _) => Some(MediaData::ShowEpisode {
+            name,
+            season: number("season").unwrap_or(Some(1))?,
+            episode: episode?,
+        }),
+        (_, _, Some(title)) => Some(MediaData::Movie { title, year: number("year").unwrap_or(None) }),
+        _ => None,
+    }
+}
+
+fn compile_custom_rule(index: usize, rule: &CustomRule) -> Result<Regex, String> {
+    let regex = Regex::new(&rule.pattern).map_err(|e| format!("custom rule {} has an invalid pattern: {}", index, e))?;
+    let groups = regex.capture_names().flatten().collect::<Vec<_>>();
+
+    let episodes = groups.contains(&"name") && groups.contains(&"episode");
+    if !episodes && !groups.contains(&"title") {
+        return Err(format!(
+            "custom rule {} ({:?}) needs named groups (?P<name>..) and (?P<episode>..), or (?P<title>..)",
+            index, rule.pattern,
+        ));
+    }
+
+    Ok(regex)
+}
+
+#[derive(Clone)]
+pub struct Analyzer {
+    cleaner: Regex,
+    release_group: Regex,
+    version_tags: Regex,
+    rules: Vec<Rule>,
+    rule_set: RegexSet,
+    rule_regexes: Vec<Regex>,
+    multi_episode: Regex,
+    episode_chapter: Regex,
+    /// Whether chapter names are read to tell multi-episode files apart.
+    chapters: bool,
+    short_episodes: ShortEpisodesConfig,
+    broken_files: BrokenFilesConfig,
+    /// Whether camera footage is told apart from releases, see `camera`.
+    home_videos: bool,
+    /// Whether dated images are photos rather than release cruft.
+    photos: bool,
+    /// Whether ebooks and comics are recognized, see `books`.
+    books: bool,
+    /// Lowercased, see `Config::performers`.
+    performers: Vec<String>,
+    max_episode: Option<u32>,
+    parts: PartPolicy,
+    /// The directory being scanned, whose name (and those above it) isn't a show's.
+    root: Option<std::path::PathBuf>,
+    /// `clean_name` results, by the name cleaned.
+    cleaned: Cache<String, String>,
+    /// The show and season episodes in a directory get from its and its parents' names.
+    directories: Cache<std::path::PathBuf, Option<(String, Option<u32>)>>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        BUILTIN.clone()
+    }
+
+    pub fn with_rules(rules: &[Rule]) -> Self {
+        if rules == Rule::DEFAULT_PRIORITY {
+            return Self::new();
+        }
+
+        Self::compile(rules, &[])
+    }
+
+    /// Custom rules are tried before the built-in ones. Their patterns and examples are checked here,
+    /// so a bad config is reported up front instead of misbehaving mid-run.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        Ok(Self::from_rules(&config.custom_rules, config.rules())?.configure(config))
+    }
+
+    pub fn from_rules(custom_rules: &[CustomRule], rules: &[Rule]) -> Result<Self, String> {
+        let custom = custom_rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| compile_custom_rule(i, rule))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if custom.is_empty() {
+            return Ok(Self::with_rules(rules));
+        }
+
+        let rules = (0..custom.len()).map(Rule::Custom).chain(rules.iter().copied()).collect::<Vec<_>>();
+        let analyzer = Self::compile(&rules, &custom);
+
+        for (i, rule) in custom_rules.iter().enumerate() {
+            for example in &rule.examples {
+                let name = analyzer.clean(std::path::Path::new(example));
+                match analyzer.matching_rule(&name) {
+                    Some((Rule::Custom(j), x)) if i == j && extract_named(&x).is_some() => {}
+                    Some((Rule::Custom(j), _)) if i == j => {
+                        return Err(format!("custom rule {} matches its example {:?} (cleaned up as {:?}), but its episode, season or year aren't numbers", i, example, name));
+                    }
+                    Some((other, _)) => {
+                        return Err(format!("custom rule {}'s example {:?} (cleaned up as {:?}) is matched by {:?} first", i, example, name, other));
+                    }
+                    None => {
+                        return Err(format!("custom rule {} doesn't match its example {:?} (cleaned up as {:?})", i, example, name));
+                    }
+                }
+            }
+        }
+
+        Ok(analyzer)
+    }
+
+    fn compile(rules: &[Rule], custom: &[Regex]) -> Self {
+        let cleaner = Regex::new(r"([. _]*)\[[^]]+\]([. _]*)").unwrap();
+        let release_group = Regex::new(r"^\[([^]]+)\]").unwrap();
+        let version_tags = Regex::new(r"(?i)\b(2160p|1080p|720p|576p|480p|4k|hevc|x265|dual[ ._-]?audio|dubbed|dub|subbed|sub)\b").unwrap();
+        let rule_regexes = rules
+            .iter()
+            .map(|r| match r {
+                Rule::Custom(i) => custom[*i].clone(),
+                r => Regex::new(r.pattern().unwrap()).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        let rule_set = RegexSet::new(rule_regexes.iter().map(Regex::as_str)).unwrap();
+        let multi_episode = Regex::new(r"\bs\d+e\d+(?:-?e\d+)*(?:-?e|-)(\d{1,3})\b").unwrap();
+        let episode_chapter = Regex::new(r"(?i)^(?:episode|ep\.?) ?(\d+)\b").unwrap();
+
+        Self {
+            cleaner,
+            release_group,
+            version_tags,
+            rules: rules.to_vec(),
+            rule_set,
+            rule_regexes,
+            multi_episode,
+            episode_chapter,
+            chapters: false,
+            short_episodes: ShortEpisodesConfig::default(),
+            broken_files: BrokenFilesConfig::default(),
+            home_videos: false,
+            photos: false,
+            books: false,
+            performers: vec![],
+            max_episode: None,
+            parts: PartPolicy::default(),
+            root: None,
+            cleaned: Cache::new(CACHE_SIZE),
+            directories: Cache::new(CACHE_SIZE),
+        }
+    }
+
+    pub fn with_chapters(mut self, chapters: bool) -> Self {
+        self.chapters = chapters;
+        self
+    }
+
+    pub fn with_short_episodes(mut self, short_episodes: ShortEpisodesConfig) -> Self {
+        self.short_episodes = short_episodes;
+        self
+    }
+
+    pub fn with_broken_files(mut self, broken_files: BrokenFilesConfig) -> Self {
+        self.broken_files = broken_files;
+        self
+    }
+
+    pub fn with_home_videos(mut self, home_videos: bool) -> Self {
+        self.home_videos = home_videos;
+        self
+    }
+
+    pub fn with_photos(mut self, photos: bool) -> Self {
+        self.photos = photos;
+        self
+    }
+
+    pub fn with_books(mut self, books: bool) -> Self {
+        self.books = books;
+        self
+    }
+
+    pub fn with_performers(mut self, performers: &[String]) -> Self {
+        self.performers = performers.iter().map(|p| p.to_lowercase()).collect();
+        self
+    }
+
+    pub fn with_max_episode(mut self, max_episode: Option<u32>) -> Self {
+        self.max_episode = max_episode;
+        self
+    }
+
+    pub fn with_parts(mut self, parts: PartPolicy) -> Self {
+        self.parts = parts;
+        self
+    }
+
+    pub fn with_root(mut self, root: &std::path::Path) -> Self {
+        self.root = Some(root.to_path_buf());
+        self.directories.clear();
+        self
+    }
+
+    /// The options besides rules, which directory overrides don't change.
+    pub fn configure(self, config: &Config) -> Self {
+        self.with_chapters(config.multi_episode_chapters)
+            .with_short_episodes(config.short_episodes)
+            .with_broken_files(config.broken_files)
+            .with_home_videos(config.home_videos)
+            .with_photos(config.photos)
+            .with_books(config.books)
+            .with_performers(&config.performers)
+            .with_max_episode(config.max_episode)
+            .with_parts(config.parts)
+    }
+
+    /// Empty, mostly unallocated or tiny videos, which usually are downloads that didn't finish.
+    pub fn broken(&self, path: &std::path::Path, stat: &Stat) -> Option<&'static str> {
+        if !self.broken_files.check && self.broken_files.minimum_size.is_none() || !is_video(path) {
+            return None;
+        }
+
+        if self.broken_files.check && stat.size == 0 {
+            Some("empty")
+        } else if self.broken_files.check && stat.blocks * 512 < stat.size / 2 {
+            Some("mostly unallocated")
+        } else if self.broken_files.minimum_size.is_some_and(|minimum| stat.size < minimum) {
+            Some("suspiciously small")
+        } else {
+            None
+        }
+    }
+
+    /// Files with unknown extensions are considered release cruft when every video next to them was
+    /// recognized. Warnings about the rest are left in `ScannedFile::warning` for the caller to report.
+    pub fn analyze_directory(&self, path: &std::path::Path) -> Vec<ScannedFile> {
+        println!("scanning {:?}...", path);
+        let analyzer = self.clone().with_root(path);
+        let files = analyze_files(find_all_files(path), &|_| &analyzer);
+        println!("found {} files.", files.len());
+        files
+    }
+
+    pub fn release_group(&self, path: &std::path::Path) -> Option<String> {
+        let name = normalize_width(path.file_stem()?.to_str()?);
+
+        self.release_group.captures(&name).map(|x| x.get(1).unwrap().as_str().to_string())
+    }
+
+    pub fn version(&self, path: &std::path::Path) -> Option<String> {
+        let name = normalize_width(path.file_stem()?.to_str()?).to_lowercase();
+        let tags = self
+            .version_tags
+            .find_iter(&name)
+            .map(|m| m.as_str().replace(['.', '_'], " "))
+            .collect::<Vec<_>>();
+
+        if tags.is_empty() { None } else { Some(tags.join(" ")) }
+    }
+
+    /// The last episode of a file holding more than one, from the name (`S01E01-E02`) or, when
+    /// enabled, from chapters named after episodes (`Episode 1`, `Episode 2`).
+    pub fn last_episode(&self, path: &std::path::Path, media: Option<&MediaData>) -> Option<u32> {
+        let Some(MediaData::ShowEpisode { episode, .. }) = media else { return None; };
+
+        let from_name = self
+            .multi_episode
+            .captures(&self.clean(path))
+            .and_then(|x| x.get(1).unwrap().as_str().parse::<u32>().ok());
+
+        let last = from_name.or_else(|| {
+            if !self.chapters {
+                return None;
+            }
+
+            let numbers = crate::container::chapters(path)
+                .iter()
+                .filter_map(|c| self.episode_chapter.captures(c.trim()))
+                .filter_map(|x| x.get(1).unwrap().as_str().parse::<u32>().ok())
+                .collect::<std::collections::BTreeSet<_>>();
+
+            if numbers.len() < 2 { None } else { numbers.last().copied() }
+        })?;
+
+        if last > *episode { Some(last) } else { None }
+    }
+
+    /// Whether an episode is shorter than the configured minimum duration.
+    fn is_short(&self, path: &std::path::Path, size: u64) -> bool {
+        let Some(minimum) = self.short_episodes.minimum_duration else { return false; };
+
+        match crate::container::duration(path) {
+            Some(duration) => duration.as_secs() < minimum,
+            None => size < minimum * MIN_BYTES_PER_SECOND,
+        }
+    }
+
+    /// Short episodes as specials, or left out with a warning.
+    fn check_duration(&self, path: &std::path::Path, size: u64, media: MediaData) -> Result<MediaData, String> {
+        let MediaData::ShowEpisode { name, season, episode } = &media else { return Ok(media); };
+        if !self.is_short(path, size) {
+            return Ok(media);
+        }
+
+        if self.short_episodes.as_specials {
+            Ok(MediaData::ShowSpecial { name: name.clone(), label: format!("s{:02}e{:02} extra", season, episode) })
+        } else {
+            Err(format!("{:?} is shorter than the minimum episode duration, not linking it", path))
+        }
+    }
+
+    /// `Rule::extract`, with bare episode numbers above `max_episode` left out and parts made
+    /// episodes as `parts` says.
+    fn extract(&self, rule: Rule, x: &regex::Captures) -> Option<MediaData> {
+        match (rule, rule.extract(x)?) {
+            (Rule::TitleEpisode, MediaData::ShowEpisode { episode, .. }) if self.max_episode.is_some_and(|max| episode > max) => None,
+            (_, MediaData::MoviePart { title, year, part }) => {
+                let movie = match self.parts {
+                    PartPolicy::Auto => year.is_some() && part <= MAX_MOVIE_PARTS,
+                    policy => policy == PartPolicy::Movie,
+                };
+
+                Some(match movie {
+                    true => MediaData::MoviePart { title, year, part },
+                    false => MediaData::ShowEpisode { name: title, season: 1, episode: part },
+                })
+            }
+            (_, media) => Some(media),
+        }
+    }
+
+    /// Whether a rule matching a name applies to it: `PerformerSpecial` needs a known performer or
+    /// a word saying it's a performance, the pattern alone matches any dashed movie title.
+    fn applies(&self, rule: Rule, x: &regex::Captures) -> bool {
+        match rule {
+            Rule::PerformerSpecial => {
+                self.performers.iter().any(|p| p == x.get(1).unwrap().as_str()) || PERFORMANCE.is_match(x.get(0).unwrap().as_str())
+            }
+            _ => true,
+        }
+    }
+
+    /// The rules matching the cleaned up name that apply to it in priority order, with their captures.
+    fn matching_rules<'a, 'n: 'a>(&'a self, name: &'n str) -> impl Iterator<Item = (Rule, regex::Captures<'n>)> + 'a {
+        self.rule_set
+            .matches(name)
+            .into_iter()
+            .map(move |i| (self.rules[i], self.rule_regexes[i].captures(name).unwrap()))
+            .filter(|(rule, x)| self.applies(*rule, x))
+    }
+
+    /// The highest priority rule matching the cleaned up name, along with its captures.
+    pub fn matching_rule<'n>(&self, name: &'n str) -> Option<(Rule, regex::Captures<'n>)> {
+        self.matching_rules(name).next()
+    }
+
+    /// Every rule matching the cleaned up name in priority order, with what it'd parse it as.
+    pub fn candidates(&self, path: &std::path::Path) -> Candidates {
+        let name = self.clean(path);
+
+        self.matching_rules(&name).map(|(rule, x)| (rule, self.extract(rule, &x))).collect()
+    }
+
+    /// The candidates, when they don't all agree: names where the rule order decides the result.
+    pub fn conflicts(&self, path: &std::path::Path) -> Option<Candidates> {
+        let candidates = self.candidates(path);
+        let first = &candidates.first()?.1;
+
+        candidates.iter().any(|(_, media)| media != first).then_some(candidates)
+    }
+
+    /// Which rule matched the cleaned up name and what each of its groups captured, for `--explain`.
+    pub fn explain(&self, path: &std::path::Path) -> String {
+        let name = self.clean(path);
+        let Some((rule, x)) = self.matching_rule(&name) else { return format!("no rule matches {:?}", name); };
+
+        let names = self.rule_regexes[self.rules.iter().position(|r| *r == rule).unwrap()].capture_names();
+        let groups = names
+            .zip(x.iter())
+            .enumerate()
+            .skip(1)
+            .map(|(i, (group, capture))| {
+                let value = capture.map_or("(none)".to_string(), |c| format!("{:?}", c.as_str()));
+                format!("{}={}", group.map_or(i.to_string(), str::to_string), value)
+            })
+            .collect::<Vec<_>>();
+
+        format!("{} on {:?}: {}", rule, name, groups.join(" "))
+    }
+
+    /// The lowercased file stem, without bracketed tags and with separators turned into spaces.
+    pub fn clean(&self, path: &std::path::Path) -> String {
+        self.clean_name(&path.file_stem().unwrap().to_string_lossy())
+    }
+
+    fn clean_name(&self, name: &str) -> String {
+        self.cleaned.get_or_insert_with(name.to_string(), || {
+            let name = normalize_width(name).to_lowercase();
+            let name = self.cleaner.replace_all(&name, "");
+            let name = name.replace("_", " ");
+            name.replace(".", " ")
+        })
+    }
+
+    /// The container's title tag run through the same rules, or taken as a movie title as is.
+    fn container_fallback(&self, path: &std::path::Path) -> Option<MediaData> {
+        let name = self.clean_name(&crate::container::title(path)?);
+
+        match self.matching_rule(&name) {
+            Some((rule, x)) => self.extract(rule, &x),
+            None if name.chars().any(char::is_alphabetic) => Some(MediaData::Movie { title: name.trim().to_string(), year: None }),
+            None => None,
+        }
+    }
+
+    /// For names without a title, the show (and season) from the directories it's in, like
+    /// `Breaking Bad/Season 2/01.mkv` or `Breaking Bad S02 1080p/E01.mkv`. None for names with a
+    /// title, an error when there's no show directory: the rules would make `Ep 3` a show named `ep`.
+    fn directory_episode(&self, path: &std::path::Path, name: &str) -> Option<Result<MediaData, String>> {
+        let x = NAMELESS.captures(name)?;
+        let number = |m: Option<regex::Match>| m.map(|m| m.as_str().parse::<u32>().unwrap());
+        let episode = number(x.get(2))?;
+
+        let show = path.parent().and_then(|parent| self.directories.get_or_insert_with(parent.to_path_buf(), || self.directory_show(parent)));
+        let Some((name, season)) = show else {
+            return Some(Err(format!("{:?} has nothing but the episode in its name and isn't in a show's directory, not linking it", path)));
+        };
+        let season = number(x.get(1)).or(season);
+
+        Some(Ok(MediaData::ShowEpisode { name, season: season.unwrap_or(1), episode }))
+    }
+
+    /// The show a directory of nameless episodes is for, and the season if its name has one.
+    fn directory_show(&self, dir: &std::path::Path) -> Option<(String, Option<u32>)> {
+        let number = |m: Option<regex::Match>| m.map(|m| m.as_str().parse::<u32>().unwrap());
+        let mut dirs = dir
+            .ancestors()
+            .take_while(|d| self.root.as_ref().is_none_or(|r| d.starts_with(r) && d != r))
+            .filter_map(|d| Some(self.clean_name(d.file_name()?.to_str()?).trim().to_string()));
+
+        let mut dir = dirs.next()?;
+        let mut season = None;
+        if let Some(s) = SEASON_DIR.captures(&dir) {
+            season = number(s.get(1)).or(Some(0));
+            dir = dirs.next()?;
+        }
+
+        let name = match SHOW_DIR.captures(&dir) {
+            Some(s) => {
+                season = season.or(number(s.get(2).or(s.get(3))));
+                s.get(1).unwrap().as_str().to_string()
+            }
+            None => dir,
+        };
+
+        (!name.is_empty()).then_some((name, season))
+    }
+
+    /// What the matching rule extracts, unless there's no title left in it.
+    fn media(&self, rule: Rule, x: &regex::Captures, name: &str) -> Result<MediaData, String> {
+        match self.extract(rule, x) {
+            Some(media) if media.is_untitled() => Err(format!("{:?} has an empty title with {:?}, not linking it", name, rule)),
+            Some(media) => Ok(media),
+            None => Err(format!("unable to extract media data from {:?} with {:?}", name, rule)),
+        }
+    }
+
+    /// What a file parses as going only by its name, and which rule matched it (none for files
+    /// recognized by their extension). Unlike `analyze`, nothing is read from the file.
+    pub fn parse(&self, path: &std::path::Path) -> Result<(Option<Rule>, MediaData), String> {
+        if !is_video(path) {
+            return self.classify(path).map(|media| (None, media)).map_err(|_| format!("unknown extension: {:?}", path));
+        }
+
+        let stem = path.file_stem().unwrap().to_string_lossy();
+        if let Some((year, month, day)) = self.home_videos.then(|| camera::name_date(&stem)).flatten() {
+            return Ok((None, MediaData::HomeVideo { year, month, day }));
+        }
+
+        let name = self.clean(path);
+        if let Some(media) = self.directory_episode(path, name.trim()) {
+            return media.map(|media| (None, media));
+        }
+        let (rule, x) = self.matching_rule(&name).ok_or_else(|| format!("unknown filename pattern: {:?}", name))?;
+        Ok((Some(rule), self.media(rule, &x, &name)?))
+    }
+
+    fn classify(&self, path: &std::path::Path) -> Result<MediaData, Unrecognized> {
+        match extension(path).as_deref() {
+            Some("mkv" | "mp4") => {
+                if let Some((year, month, day)) = self.home_videos.then(|| camera::date(path)).flatten() {
+                    return Ok(MediaData::HomeVideo { year, month, day });
+                }
+
+                let name = self.clean(path);
+                if let Some(media) = self.directory_episode(path, name.trim()) {
+                    return media.map_err(Unrecognized::Pattern);
+                }
+
+                match self.matching_rule(&name) {
+                    Some((rule, x)) => self.media(rule, &x, &name).map_err(Unrecognized::Pattern),
+                    None => self
+                        .container_fallback(path)
+                        .filter(|media| !media.is_untitled())
+                        .ok_or_else(|| Unrecognized::Pattern(format!("unknown filename pattern: {:?}", name))),
+                }
+            }
+            Some("srt" | "sub") => { Ok(MediaData::Garbage) }
+            Some("idx")         => { Ok(MediaData::Garbage) }
+            Some("ogg" | "mp3") => { Ok(MediaData::Garbage) }
+            Some("jpg" | "jpeg" | "png") if self.photos => match camera::photo_date(path) {
+                Some((year, month, day)) => Ok(MediaData::Photo { year, month, day }),
+                None => Ok(MediaData::Garbage),
+            },
+            Some("jpg" | "png") => { Ok(MediaData::Garbage) }
+            Some("epub" | "pdf" | "mobi" | "azw3" | "cbz" | "cbr" | "cb7") if self.books => {
+                crate::books::parse(path).ok_or_else(|| Unrecognized::Pattern(format!("unknown book name: {:?}", path)))
+            }
+            Some("ts" | "bdjo" | "clpi" | "mpls" | "m2ts" | "bdmv") => { Ok(MediaData::Garbage) }
+            Some("torrent" | "meta" | "exe" | "nfo" | "txt" | "md5") => { Ok(MediaData::Garbage) }
+            _ => Err(Unrecognized::Extension),
+        }
+    }
+
+    pub fn analyze(&self, path: &std::path::Path) -> Option<MediaData> {
+        match self.classify(path) {
+            Ok(media) => Some(media),
+            Err(Unrecognized::Pattern(warning)) => { eprintln!("{}", warning); None },
+            Err(Unrecognized::Extension) => { eprintln!("unknown extension: {:?}", path); None },
+        }
+    }
+}
+
+enum Unrecognized {
+    Pattern(String),
+    Extension,
+}
+
+/// Lowercased, so files from release groups naming them `.MKV` are recognized all the same.
+pub fn extension(path: &std::path::Path) -> Option<String> {
+    path.extension().and_then(std::ffi::OsStr::to_str).map(str::to_ascii_lowercase)
+}
+
+pub fn is_video(path: &std::path::Path) -> bool {
+    matches!(extension(path).as_deref(), Some("mkv" | "mp4"))
+}
+
+/// What scanning needs from a file's metadata, so a recording can stand in for the disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stat {
+    pub inode: u64,
+    pub size: u64,
+    pub links: u64,
+    /// In 512 byte units, like `st_blocks`.
+    pub blocks: u64,
+}
+
+impl From<&std::fs::Metadata> for Stat {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        Self { inode: metadata.ino(), size: metadata.len(), links: metadata.nlink(), blocks: metadata.blocks() }
+    }
+}
+
+/// The metadata of each file, leaving out dangling symlinks and files removed since they were listed.
+pub fn stat_files(paths: Vec<std::path::PathBuf>) -> Vec<(std::path::PathBuf, Stat)> {
+    paths
+        .into_iter()
+        .filter_map(|p| { let stat = Stat::from(&std::fs::metadata(&p).ok()?); Some((p, stat)) })
+        .collect()
+}
+
+/// Classifies every file with the analyzer `pick` returns for it, leaving out directory configs.
+pub fn analyze_files<'a>(paths: Vec<std::path::PathBuf>, pick: &dyn Fn(&std::path::Path) -> &'a Analyzer) -> Vec<ScannedFile> {
+    analyze_stats(stat_files(paths), pick)
+}
+
+/// `analyze_files` with the metadata already at hand.
+pub fn analyze_stats<'a>(files: Vec<(std::path::PathBuf, Stat)>, pick: &dyn Fn(&std::path::Path) -> &'a Analyzer) -> Vec<ScannedFile> {
+    let files = files.into_iter().filter(|(p, _)| !crate::overrides::is_override(p)).collect::<Vec<_>>();
+    let results = files.iter().map(|(f, _)| pick(f).classify(f)).collect::<Vec<_>>();
+
+    let mut recognized: std::collections::BTreeMap<_, bool> = std::collections::BTreeMap::new();
+    for ((f, _), result) in files.iter().zip(&results).filter(|((f, _), _)| is_video(f)) {
+        *recognized.entry(f.parent()).or_insert(true) &= result.is_ok();
+    }
+    let cruft = |f: &std::path::Path| recognized.get(&f.parent()).copied().unwrap_or(false);
+
+    files
+        .iter()
+        .zip(results)
+        .map(|((f, stat), result)| {
+            let broken = pick(f).broken(f, stat);
+            let result = match broken {
+                Some(reason) => Err(Unrecognized::Pattern(format!("{:?} looks like a failed download ({}), not linking it", f, reason))),
+                None => result,
+            };
+            let result = result.and_then(|media| pick(f).check_duration(f, stat.size, media).map_err(Unrecognized::Pattern));
+            let (media, warning) = match result {
+                Ok(media) => (Some(media), None),
+                Err(Unrecognized::Extension) if cruft(f) => (Some(MediaData::Garbage), None),
+                Err(Unrecognized::Extension) => (None, Some(format!("unknown extension: {:?}", f))),
+                Err(Unrecognized::Pattern(warning)) => (None, Some(warning)),
+            };
+            let last_episode = pick(f).last_episode(f, media.as_ref());
+
+            ScannedFile {
+                path: f.clone(),
+                metadata: media,
+                group: pick(f).release_group(f),
+                version: pick(f).version(f),
+                last_episode,
+                inode: stat.inode,
+                size: stat.size,
+                links: stat.links,
+                warning,
+                broken,
+            }
+        })
+        .collect()
+}
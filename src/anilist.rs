@@ -0,0 +1,113 @@
+//! Anime titles from AniList, so releases named in romaji and in english end up in the same
+//! folder, using whichever title language the config prefers.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use crate::config::TitleLanguage;
+
+const API_URL: &str = "https://graphql.anilist.co";
+
+const QUERY: &str = "query ($search: String) { Media(search: $search, type: ANIME) { title { romaji english native } } }";
+
+#[derive(Deserialize)]
+struct Response {
+    data: ResponseData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ResponseData {
+    media: Option<Media>,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    title: Titles,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Titles {
+    pub romaji: Option<String>,
+    pub english: Option<String>,
+    pub native: Option<String>,
+}
+
+impl Titles {
+    /// Not every entry has every language, romaji is the one AniList always has.
+    pub fn preferred(&self, language: TitleLanguage) -> Option<&str> {
+        let preferred = match language {
+            TitleLanguage::Romaji => &self.romaji,
+            TitleLanguage::English => &self.english,
+            TitleLanguage::Native => &self.native,
+        };
+
+        preferred.as_deref().or(self.romaji.as_deref()).filter(|t| !t.trim().is_empty())
+    }
+}
+
+pub struct AniList {
+    language: TitleLanguage,
+    /// By parsed name, kept in the state like TMDB's lookups.
+    titles: RefCell<BTreeMap<String, Option<Titles>>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl AniList {
+    pub fn new(language: TitleLanguage) -> Self {
+        Self { language, titles: RefCell::new(BTreeMap::new()), errors: RefCell::new(vec![]) }
+    }
+
+    /// Starting from what earlier runs found out.
+    pub fn with_titles(self, titles: BTreeMap<String, Option<Titles>>) -> Self {
+        *self.titles.borrow_mut() = titles;
+        self
+    }
+
+    pub fn titles(&self) -> BTreeMap<String, Option<Titles>> {
+        self.titles.borrow().clone()
+    }
+
+    /// Searches that failed with nothing known from earlier runs, like `Tmdb::errors`.
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.borrow().clone()
+    }
+
+    /// `None` when nothing matches, errors are for when AniList can't tell.
+    fn search(&self, name: &str) -> Result<Option<Titles>, String> {
+        let request = ureq::post(API_URL).send_json(serde_json::json!({ "query": QUERY, "variables": { "search": name } }));
+
+        match request {
+            Ok(response) => match response.into_json::<Response>() {
+                Ok(body) => Ok(body.data.media.map(|m| m.title)),
+                Err(e) => Err(format!("invalid anilist response for {:?}: {}", name, e)),
+            },
+            // AniList answers 404 when nothing matches.
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(format!("anilist request for {:?} failed: {}", name, e)),
+        }
+    }
+
+    /// The show's title in the preferred language, `None` when AniList doesn't know it.
+    pub fn title(&self, name: &str) -> Option<String> {
+        if let Some(cached) = self.titles.borrow().get(name) {
+            return cached.as_ref().and_then(|t| t.preferred(self.language)).map(str::to_string);
+        }
+
+        let titles = match self.search(name) {
+            Ok(titles) => titles,
+            Err(e) => { self.errors.borrow_mut().push(e); return None; },
+        };
+        self.titles.borrow_mut().insert(name.to_string(), titles.clone());
+        titles.as_ref().and_then(|t| t.preferred(self.language)).map(str::to_string)
+    }
+
+    pub fn ping(&self) -> Result<(), String> {
+        ureq::post(API_URL)
+            .send_json(serde_json::json!({ "query": "{ SiteStatistics { __typename } }" }))
+            .map_err(|e| format!("anilist request failed: {}", e))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::{ArrConfig, ArrMode, Config};
+use crate::MediaData;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrKind {
+    Sonarr,
+    Radarr,
+}
+
+impl std::fmt::Display for ArrKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ArrKind::Sonarr => write!(f, "sonarr"),
+            ArrKind::Radarr => write!(f, "radarr"),
+        }
+    }
+}
+
+pub struct Arr<'a> {
+    pub kind: ArrKind,
+    pub config: &'a ArrConfig,
+}
+
+impl<'a> Arr<'a> {
+    pub fn for_media(config: &'a Config, metadata: &MediaData) -> Option<Self> {
+        match metadata {
+            MediaData::ShowEpisode { .. } | MediaData::ShowSpecial { .. } | MediaData::ShowMovie { .. } => {
+                config.sonarr.as_ref().map(|c| Arr { kind: ArrKind::Sonarr, config: c })
+            }
+            MediaData::Movie { .. } | MediaData::MoviePart { .. } | MediaData::Performance { .. } => config.radarr.as_ref().map(|c| Arr { kind: ArrKind::Radarr, config: c }),
+            MediaData::SportsEvent { .. } | MediaData::HomeVideo { .. } | MediaData::Photo { .. } | MediaData::Book { .. } | MediaData::Comic { .. } | MediaData::Garbage => None,
+        }
+    }
+
+    pub fn all(config: &'a Config) -> Vec<Self> {
+        let sonarr = config.sonarr.as_ref().map(|c| Arr { kind: ArrKind::Sonarr, config: c });
+        let radarr = config.radarr.as_ref().map(|c| Arr { kind: ArrKind::Radarr, config: c });
+
+        sonarr.into_iter().chain(radarr).collect()
+    }
+
+    pub fn ping(&self) -> Result<(), String> {
+        ureq::get(&format!("{}/api/v3/system/status", self.config.url.trim_end_matches('/')))
+            .set("X-Api-Key", &self.config.api_key)
+            .call()
+            .map_err(|e| format!("{} request failed: {}", self.kind, e))?;
+
+        Ok(())
+    }
+
+    fn command(&self, body: serde_json::Value) -> Result<(), String> {
+        ureq::post(&format!("{}/api/v3/command", self.config.url.trim_end_matches('/')))
+            .set("X-Api-Key", &self.config.api_key)
+            .send_json(body)
+            .map_err(|e| format!("{} command failed: {}", self.kind, e))?;
+
+        Ok(())
+    }
+
+    /// Asks the service to import a downloaded file itself.
+    pub fn import(&self, path: &std::path::Path) -> Result<(), String> {
+        let name = match self.kind {
+            ArrKind::Sonarr => "DownloadedEpisodesScan",
+            ArrKind::Radarr => "DownloadedMoviesScan",
+        };
+
+        let path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+        self.command(serde_json::json!({ "name": name, "path": path, "importMode": "Copy" }))
+    }
+
+    /// Asks the service to rescan its library so it picks up what harvester linked.
+    pub fn rescan(&self) -> Result<(), String> {
+        let name = match self.kind {
+            ArrKind::Sonarr => "RescanSeries",
+            ArrKind::Radarr => "RescanMovie",
+        };
+
+        self.command(serde_json::json!({ "name": name }))
+    }
+
+    pub fn imports_instead(&self) -> bool {
+        self.config.mode == ArrMode::Instead
+    }
+}
@@ -0,0 +1,108 @@
+//! Copies of the state and of the plan, taken before runs that remove links, so `harvester
+//! rollback` can put the library and the state back the way they were before the run.
+
+use serde::{Deserialize, Serialize};
+
+use crate::plan::{self, Create, LinkKind, Plan};
+use crate::state::State;
+use crate::Runner;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub taken_at: u64,
+    /// With absolute paths, like the journal's.
+    pub plan: Plan,
+    /// How to make the links the plan removes again, for those the state knows the source of.
+    pub removed: Vec<Create>,
+    pub state: State,
+}
+
+/// Next to the state.
+pub fn dir(state_path: &std::path::Path) -> std::path::PathBuf {
+    state_path.with_file_name("backups")
+}
+
+fn path(dir: &std::path::Path, name: u64) -> std::path::PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+/// Whether a run needs one: only when links go away for good, links recreated in place can be
+/// made again by any later run.
+pub fn is_needed(plan: &Plan) -> bool {
+    plan.removed().next().is_some()
+}
+
+/// The backups in `dir` by name, which is when they were taken, oldest first.
+pub fn list(dir: &std::path::Path) -> Vec<u64> {
+    let entries = dir.read_dir().into_iter().flatten().filter_map(Result::ok);
+    let mut names = entries
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(".json")?.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+
+    names.sort();
+    names
+}
+
+impl Backup {
+    pub fn new(plan: &Plan, state: &State, runner: &dyn Runner, now: u64) -> Self {
+        let plan = plan.absolute();
+        let removed = plan
+            .deletes
+            .iter()
+            .filter_map(|link| {
+                let record = state.links.iter().find(|r| r.link == *link)?;
+                let kind = match crate::analyzer::extension(link).as_deref() {
+                    Some("strm") => LinkKind::Strm(runner.read_to_string(link)?),
+                    _ => LinkKind::Hardlink,
+                };
+
+                Some(Create { source: record.source.clone(), link: link.clone(), kind })
+            })
+            .collect();
+
+        Self { taken_at: now, plan, removed, state: state.clone() }
+    }
+
+    /// Writes it to `dir`, removing the oldest ones past `keep`.
+    pub fn save(&self, dir: &std::path::Path, keep: usize) -> Result<std::path::PathBuf, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("unable to create {:?}: {}", dir, e))?;
+
+        let path = path(dir, self.taken_at);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(self).unwrap()).map_err(|e| format!("unable to write backup {:?}: {}", tmp, e))?;
+        std::fs::rename(&tmp, &path).map_err(|e| format!("unable to write backup {:?}: {}", path, e))?;
+
+        let names = list(dir);
+        for name in names.iter().take(names.len().saturating_sub(keep)) {
+            std::fs::remove_file(self::path(dir, *name)).map_err(|e| format!("unable to remove old backup {:?}: {}", self::path(dir, *name), e))?;
+        }
+
+        Ok(path)
+    }
+
+    pub fn load(dir: &std::path::Path, name: u64) -> Result<Self, String> {
+        let path = path(dir, name);
+        let contents = std::fs::read_to_string(&path).map_err(|e| format!("unable to read backup {:?}: {}", path, e))?;
+
+        serde_json::from_str(&contents).map_err(|e| format!("invalid backup {:?}: {}", path, e))
+    }
+
+    /// What undoing the run takes, as far as it still can be done: the links it made that are
+    /// still there are removed, and the ones it removed are made again from sources still there.
+    pub fn undo(&self, runner: &dyn Runner) -> Plan {
+        let made = |c: &&Create| match &c.kind {
+            LinkKind::Hardlink => plan::is_linked(runner, &c.source, &c.link),
+            LinkKind::Strm(contents) => runner.read_to_string(&c.link).as_ref() == Some(contents),
+        };
+        let deletes = self.plan.creates.iter().filter(made).map(|c| c.link.clone()).collect::<Vec<_>>();
+
+        let creates = self
+            .removed
+            .iter()
+            .filter(|c| runner.exists(&c.source) && (!runner.exists(&c.link) || deletes.contains(&c.link)))
+            .cloned()
+            .collect();
+
+        Plan { deletes, extra: vec![], creates, prunes: vec![] }
+    }
+}
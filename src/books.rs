@@ -0,0 +1,67 @@
+//! Ebooks (`Author - Title.epub`) and comics (`Series v03.cbz`, `Series #012.cbr`), for Kavita or
+//! Calibre-web libraries fed from the same incoming directory.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::MediaData;
+
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[[^]]*\]").unwrap());
+static COMIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(.+?)(?: (?:vol\.?|volume|v) ?(\d+))?(?: (?:#|ch\.? ?|chapter )(\d+))?(?: \(\d{4}\))?$").unwrap()
+});
+
+pub fn is_book(path: &std::path::Path) -> bool {
+    matches!(crate::analyzer::extension(path).as_deref(), Some("epub" | "pdf" | "mobi" | "azw3"))
+}
+
+pub fn is_comic(path: &std::path::Path) -> bool {
+    matches!(crate::analyzer::extension(path).as_deref(), Some("cbz" | "cbr" | "cb7"))
+}
+
+/// Like `Analyzer::clean`, but keeping the case and dots: titles are shown as they're named,
+/// and initials (`J.R.R. Tolkien`) are common.
+fn clean(stem: &str) -> String {
+    let name = crate::analyzer::normalize_width(stem).replace('_', " ");
+    let name = TAGS.replace_all(&name, "");
+
+    name.split(' ').filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+pub fn parse(path: &std::path::Path) -> Option<MediaData> {
+    let name = clean(path.file_stem()?.to_str()?);
+    if name.is_empty() {
+        return None;
+    }
+
+    if is_comic(path) {
+        let x = COMIC.captures(&name)?;
+        let number = |i: usize| x.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+
+        return Some(MediaData::Comic { series: x.get(1).unwrap().as_str().to_string(), volume: number(2), issue: number(3) });
+    }
+
+    if !is_book(path) {
+        return None;
+    }
+
+    Some(match name.split_once(" - ") {
+        Some((author, title)) => MediaData::Book { author: Some(author.to_string()), title: title.to_string() },
+        None => MediaData::Book { author: None, title: name },
+    })
+}
+
+/// `<author>/<title>` for books, `<series>/<series> v03 #012` for comics, relative to their library.
+pub fn link_path(media: &MediaData, extension: &str) -> Option<std::path::PathBuf> {
+    match media {
+        MediaData::Book { author: Some(author), title } => Some(std::path::Path::new(author).join(format!("{}.{}", title, extension))),
+        MediaData::Book { author: None, title } => Some(format!("{}.{}", title, extension).into()),
+        MediaData::Comic { series, volume, issue } => {
+            let volume = volume.map_or(String::new(), |v| format!(" v{:02}", v));
+            let issue = issue.map_or(String::new(), |i| format!(" #{:03}", i));
+            Some(std::path::Path::new(series).join(format!("{}{}{}.{}", series, volume, issue, extension)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,142 @@
+//! Dates of camera footage, from names like `IMG_20230115_1234` or `VID-2022-07-01`, or from the
+//! container's creation time for camera names without one (`GOPR0012`). Photos' come from their
+//! EXIF data.
+
+use std::io::Read;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static DATED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:img|vid|pxl|mvimg|video|signal|mov)?[_ -]?(\d{4})-?(\d{2})-?(\d{2})(?:[_ .-]|$)").unwrap()
+});
+static CAMERA: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)^(?:img|vid|pxl|mvimg|mov|mvi|dsc|dji|gopr|gp|gh|gx)[_-]?\d{3,}").unwrap());
+
+/// Year, month and day.
+pub type Date = (u32, u32, u32);
+
+/// The date in a camera file name, if it's a plausible one.
+pub fn name_date(stem: &str) -> Option<Date> {
+    let x = DATED.captures(stem)?;
+    let number = |i: usize| x.get(i).unwrap().as_str().parse::<u32>().unwrap();
+    let (year, month, day) = (number(1), number(2), number(3));
+
+    if !(1990..=2100).contains(&year) || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some((year, month, day))
+}
+
+/// Whether the name looks like a camera's, dated or not.
+pub fn is_camera_name(stem: &str) -> bool {
+    name_date(stem).is_some() || CAMERA.is_match(stem)
+}
+
+/// From the name when it has a date, from the container otherwise. Names that don't look like
+/// a camera's don't get one, every video has a creation time.
+pub fn date(path: &std::path::Path) -> Option<Date> {
+    let stem = path.file_stem()?.to_str()?;
+
+    match name_date(stem) {
+        Some(date) => Some(date),
+        None if CAMERA.is_match(stem) => Some(civil_date(crate::container::creation_time(path)?)),
+        None => None,
+    }
+}
+
+/// How much of an image is read looking for its EXIF data, it comes before the pixels.
+const IMAGE_HEAD: u64 = 256 << 10;
+
+/// When the photo was taken: EXIF `DateTimeOriginal` (`2023:01:15 12:34:56`), or a date in the
+/// name. Images with neither, like posters and screenshots, don't get one.
+pub fn photo_date(path: &std::path::Path) -> Option<Date> {
+    let exif = || {
+        let mut head = vec![];
+        std::fs::File::open(path).ok()?.take(IMAGE_HEAD).read_to_end(&mut head).ok()?;
+        let original = exif_date_time_original(exif_tiff(&head)?)?;
+        let number = |range: std::ops::Range<usize>| original.get(range)?.parse::<u32>().ok();
+
+        Some((number(0..4)?, number(5..7)?, number(8..10)?)).filter(|(_, m, d)| (1..=12).contains(m) && (1..=31).contains(d))
+    };
+
+    exif().or_else(|| name_date(path.file_stem()?.to_str()?))
+}
+
+/// The TIFF structure holding the EXIF tags, from a JPEG `APP1` segment or a PNG `eXIf` chunk.
+fn exif_tiff(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let mut position = 8;
+        while position + 8 <= data.len() {
+            let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+            let body = data.get(position + 8..position + 8 + length)?;
+            match &data[position + 4..position + 8] {
+                b"eXIf" => return Some(body),
+                b"IDAT" | b"IEND" => return None,
+                _ => position += 12 + length,
+            }
+        }
+        return None;
+    }
+
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut position = 2;
+    while position + 4 <= data.len() && data[position] == 0xFF {
+        let marker = data[position + 1];
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        let body = data.get(position + 4..position + 2 + length)?;
+        match marker {
+            0xE1 if body.starts_with(b"Exif\0\0") => return Some(&body[6..]),
+            // Start of scan, the image data follows.
+            0xDA => return None,
+            _ => position += 2 + length,
+        }
+    }
+
+    None
+}
+
+/// Follows IFD0's pointer to the EXIF IFD and reads `DateTimeOriginal` from it.
+fn exif_date_time_original(tiff: &[u8]) -> Option<&str> {
+    const EXIF_IFD: u16 = 0x8769;
+    const DATE_TIME_ORIGINAL: u16 = 0x9003;
+
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| tiff.get(at..at + 2).map(|b| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) });
+    let u32_at = |at: usize| {
+        tiff.get(at..at + 4).map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+        })
+    };
+    // Each entry is a tag, a type, a count and a value or an offset to it.
+    let find = |ifd: usize, tag: u16| (0..u16_at(ifd)? as usize).map(|i| ifd + 2 + i * 12).find(|e| u16_at(*e) == Some(tag));
+
+    let exif = u32_at(find(u32_at(4)? as usize, EXIF_IFD)? + 8)? as usize;
+    let entry = find(exif, DATE_TIME_ORIGINAL)?;
+    let offset = u32_at(entry + 8)? as usize;
+
+    std::str::from_utf8(tiff.get(offset..offset + 19)?).ok()
+}
+
+/// The UTC date of a Unix timestamp.
+pub fn civil_date(timestamp: u64) -> Date {
+    // Howard Hinnant's days to civil algorithm, with eras starting on March 1st.
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as u32, month as u32, day as u32)
+}
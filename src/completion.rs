@@ -0,0 +1,102 @@
+//! Which library seasons have every episode, going by the metadata provider's episode counts.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Layout, MediaData, ScannedFile};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Season {
+    pub show: String,
+    pub season: u32,
+    pub have: BTreeSet<u32>,
+    /// Episodes the provider knows about, `None` when it doesn't know the season.
+    pub total: Option<u32>,
+}
+
+impl Season {
+    pub fn missing(&self) -> Vec<u32> {
+        (1..=self.total.unwrap_or(0)).filter(|e| !self.have.contains(e)).collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total.is_some() && self.missing().is_empty()
+    }
+}
+
+impl std::fmt::Display for Season {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let total = self.total.map_or("?".to_string(), |t| t.to_string());
+        write!(f, "{} S{:02}: {}/{}", self.show, self.season, self.have.len(), total)?;
+
+        let missing = self.missing();
+        if !missing.is_empty() {
+            let missing = missing.iter().map(u32::to_string).collect::<Vec<_>>();
+            write!(f, " (missing {})", missing.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Linked episodes grouped into seasons using the library numbering, specials left out. `counts`
+/// returns the episode count of each season of a show. With `all_seasons`, the seasons without
+/// anything linked are there too.
+pub fn seasons(layout: &Layout, links: &[(&ScannedFile, std::path::PathBuf)], counts: &dyn Fn(&str) -> Option<BTreeMap<u32, u32>>, all_seasons: bool) -> Vec<Season> {
+    let mut have: BTreeMap<(String, u32), BTreeSet<u32>> = BTreeMap::new();
+
+    for (file, _) in links {
+        if let Some(MediaData::ShowEpisode { name, season, episode }) = &file.metadata {
+            let (season, episode) = layout.episode_number(name, *season, *episode);
+            if season > 0 {
+                have.entry((name.clone(), season)).or_default().insert(episode);
+            }
+        }
+    }
+
+    let shows = have.keys().map(|(show, _)| show.clone()).collect::<BTreeSet<_>>();
+    let counts = shows.into_iter().map(|show| (show.clone(), counts(&show))).collect::<BTreeMap<_, _>>();
+
+    if all_seasons {
+        for (show, counts) in counts.iter() {
+            for season in counts.iter().flat_map(|c| c.keys()).filter(|s| **s > 0) {
+                have.entry((show.clone(), *season)).or_default();
+            }
+        }
+    }
+
+    have.into_iter()
+        .map(|((show, season), have)| {
+            let total = counts[&show].as_ref().and_then(|c| c.get(&season).copied());
+            Season { show, season, have, total }
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct MissingSeason {
+    pub season: u32,
+    pub total: u32,
+    pub missing: Vec<u32>,
+}
+
+/// `harvester missing` output, a show's seasons with missing episodes.
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Missing {
+    pub show: String,
+    pub seasons: Vec<MissingSeason>,
+}
+
+/// Seasons the provider doesn't know are left out.
+pub fn missing(seasons: &[Season]) -> Vec<Missing> {
+    let mut shows: Vec<Missing> = vec![];
+
+    for season in seasons.iter().filter(|s| s.total.is_some() && !s.is_complete()) {
+        let entry = MissingSeason { season: season.season, total: season.total.unwrap(), missing: season.missing() };
+        match shows.last_mut() {
+            Some(show) if show.show == season.show => show.seasons.push(entry),
+            _ => shows.push(Missing { show: season.show.clone(), seasons: vec![entry] }),
+        }
+    }
+
+    shows
+}
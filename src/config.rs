@@ -0,0 +1,710 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::analyzer::Rule;
+use crate::MediaData;
+use crate::scene::{Offset, SceneExceptions};
+use crate::store::{JsonStore, SqliteStore, StateStore};
+use crate::template::{Naming, Template};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Collection folder name to the (case insensitive) movie titles that belong to it.
+    pub collections: BTreeMap<String, Vec<String>>,
+    pub tmdb: Option<TmdbConfig>,
+    pub anilist: Option<AnilistConfig>,
+    pub routes: Vec<Route>,
+    /// Separate library roots for parts of a shared incoming directory, first match wins.
+    pub users: Vec<UserRoute>,
+    /// Layout profile for files no route matches.
+    pub layout: LayoutProfile,
+    /// Filename rule priority, highest first. Rules left out are disabled.
+    pub rules: Option<Vec<Rule>>,
+    /// Extra filename patterns, tried before the built-in rules.
+    pub custom_rules: Vec<CustomRule>,
+    /// Read Matroska chapters to spot files holding several episodes (chapters named `Episode 1`,
+    /// `Episode 2`), besides names like `S01E01-E02`.
+    pub multi_episode_chapters: bool,
+    /// File camera footage (`IMG_20230115_1234.mp4`) under `libraries.home_videos`, by year and month.
+    pub home_videos: bool,
+    /// Link images with an EXIF or name date under `libraries.photos` by year and month, instead
+    /// of treating them as release cruft.
+    pub photos: bool,
+    /// Link ebooks (`Author - Title.epub`) and comics (`Series v03.cbz`) under `libraries.books`
+    /// and `libraries.comics`.
+    pub books: bool,
+    /// Comedians and musicians (case insensitive) whose `Performer - Special (2021)` releases are
+    /// performances, besides those saying so with words like `stand-up` or `live at`.
+    pub performers: Vec<String>,
+    /// Bare numbers after a title above it (`Show 1500`) aren't taken as episode numbers.
+    pub max_episode: Option<u32>,
+    pub short_episodes: ShortEpisodesConfig,
+    pub broken_files: BrokenFilesConfig,
+    pub naming: NamingConfig,
+    pub libraries: LibrariesConfig,
+    pub movie_layout: MovieLayout,
+    #[serde(skip)]
+    pub templates: Naming,
+    /// Per-show overrides, keyed by the (case insensitive) parsed show name.
+    pub shows: BTreeMap<String, ShowConfig>,
+    /// What to do when several incoming files map to the same library entry.
+    pub versions: VersionPolicy,
+    /// What to do with new files identical to something already linked, but with a different inode.
+    pub content_duplicates: ContentDuplicatePolicy,
+    pub link_mode: LinkMode,
+    /// Fsync the library directories after each batch of changes, and write `.strm` files through
+    /// a temporary file, so a power loss right after a big import doesn't lose directory entries.
+    pub durable: bool,
+    /// Never change anything under the incoming directory, whatever else is enabled: purging garbage
+    /// and deleting retired torrents' data are refused up front.
+    pub read_only_incoming: bool,
+    /// File and directory names skipped when scanning, besides dotfiles and `IGNORED` ones.
+    pub ignore: Vec<String>,
+    /// What `.strm` files point at instead of the local incoming directory (`smb://nas/incoming`).
+    pub strm_base: Option<String>,
+    /// Where releases like `Show - Movie (2020)` end up.
+    pub show_movies: ShowMoviePolicy,
+    /// Whether `Title Part 1` is part of a movie or an episode of a miniseries.
+    pub parts: PartPolicy,
+    /// Treats shows TMDB lists with a single season (specials aside) as mini-series.
+    pub flatten_single_season: bool,
+    /// Per-show scene numbering exceptions file, relative to the config file.
+    pub scene_exceptions: Option<std::path::PathBuf>,
+    #[serde(skip)]
+    pub scene: SceneExceptions,
+    pub torrent: Option<TorrentConfig>,
+    pub remote: Option<RemoteConfig>,
+    pub retire: RetireConfig,
+    /// What happens with links deleted from the library (say, through Jellyfin) while their source
+    /// is still there, on runs and on `verify --repair`.
+    pub deleted_links: DeletedLinkPolicy,
+    /// Only remove links and directories when the target has a `.harvester-root` file, new links
+    /// are created regardless.
+    pub require_sentinel: bool,
+    pub blocklist: BlocklistConfig,
+    pub schedule: ScheduleConfig,
+    pub hooks: HooksConfig,
+    pub sonarr: Option<ArrConfig>,
+    pub radarr: Option<ArrConfig>,
+    pub subtitles: Option<SubtitlesConfig>,
+    pub trakt: Option<TrackerConfig>,
+    pub simkl: Option<TrackerConfig>,
+    /// Where harvester keeps track of what it linked, defaults to `$XDG_STATE_HOME/harvester`.
+    pub state_dir: Option<std::path::PathBuf>,
+    /// How the state is kept in `state_dir`.
+    pub state_backend: StateBackend,
+    pub backups: BackupConfig,
+    /// Used when `<incoming> <jellyfin>` aren't given, usually set per profile.
+    pub incoming: Option<std::path::PathBuf>,
+    pub target: Option<std::path::PathBuf>,
+    /// Named sets of keys applied on top of the rest with `--profile`, so one config can drive
+    /// unrelated libraries. Give them their own `state_dir`.
+    pub profile: BTreeMap<String, toml::Table>,
+}
+
+/// A regex matched against the cleaned up (lowercased, tags removed, `.` and `_` as spaces) file stem.
+/// It needs `name` and `episode` named groups, and optionally `season`, or a `title` and optionally `year`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomRule {
+    pub pattern: String,
+    /// Filenames the pattern must match, checked on startup.
+    #[serde(default)]
+    pub examples: Vec<String>,
+}
+
+/// Where each media type goes, relative to the target directory or absolute.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibrariesConfig {
+    pub shows: std::path::PathBuf,
+    pub movies: std::path::PathBuf,
+    pub sports: std::path::PathBuf,
+    /// Stand-up specials and concerts go with the movies unless set.
+    pub performances: Option<std::path::PathBuf>,
+    pub home_videos: std::path::PathBuf,
+    pub photos: std::path::PathBuf,
+    pub books: std::path::PathBuf,
+    pub comics: std::path::PathBuf,
+}
+
+impl Default for LibrariesConfig {
+    fn default() -> Self {
+        Self { shows: "shows".into(), movies: "movies".into(), sports: "sports".into(), performances: None, home_videos: "home videos".into(), photos: "photos".into(), books: "books".into(), comics: "comics".into() }
+    }
+}
+
+/// Templates for the default layout profile's folder and file names, without extension.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConfig {
+    pub show_folder: String,
+    pub season_folder: String,
+    pub episode: String,
+    pub movie_folder: String,
+    pub movie: String,
+    /// Lowercase the extension of links (`.MKV` files linked as `.mkv`).
+    pub lowercase_extensions: bool,
+    /// Link extensions by (case insensitive) source extension, like `{ m4v = "mp4" }`. It's also
+    /// what `{container}` renders as in the episode and movie templates.
+    pub extensions: BTreeMap<String, String>,
+    /// In bytes, for filesystems like eCryptfs (143) that take shorter names than the usual 255.
+    /// Longer folder and file names are cut.
+    pub max_name_length: Option<usize>,
+    /// In bytes, the whole link path. The longest names below the library are cut to fit it.
+    pub max_path_length: Option<usize>,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self {
+            show_folder: "{name}".to_string(),
+            season_folder: "Season {season}".to_string(),
+            episode: "episode {episode}".to_string(),
+            movie_folder: "{title}< ({year})>".to_string(),
+            movie: "movie".to_string(),
+            lowercase_extensions: false,
+            extensions: BTreeMap::new(),
+            max_name_length: None,
+            max_path_length: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShowConfig {
+    /// Added to the parsed season number, after scene exceptions are applied.
+    pub season_offset: i32,
+    /// Applied in order after `season_offset`, like `["season 1: season += 1", "season 1: episode -= 12"]`.
+    pub offsets: Vec<Offset>,
+    /// Takes precedence over the `[[routes]]` one.
+    pub layout: Option<LayoutProfile>,
+    /// Library folder instead of `libraries.shows`, relative to the target directory or absolute.
+    pub library: Option<std::path::PathBuf>,
+    /// Episode file name template, like `naming.episode`.
+    pub episode: Option<String>,
+    #[serde(skip)]
+    pub episode_template: Option<Template>,
+    /// Whether specials (and show movies kept as specials) get linked at all.
+    pub specials: bool,
+    /// Episodes go straight into the show folder, with no season folder.
+    pub mini_series: bool,
+}
+
+impl Default for ShowConfig {
+    fn default() -> Self {
+        Self { season_offset: 0, offsets: vec![], layout: None, library: None, episode: None, episode_template: None, specials: true, mini_series: false }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrMode {
+    /// Link as usual and ask the service to rescan its library afterwards.
+    #[default]
+    Also,
+    /// Don't link, let the service import the file itself.
+    Instead,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArrConfig {
+    pub url: String,
+    pub api_key: String,
+    #[serde(default)]
+    pub mode: ArrMode,
+}
+
+/// OpenSubtitles API access, and the languages new links should have subtitles in (`["en", "pt"]`).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubtitlesConfig {
+    pub api_key: String,
+    pub languages: Vec<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// OAuth application client ID and a user access token for trakt or simkl.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackerConfig {
+    pub client_id: String,
+    pub access_token: String,
+}
+
+/// Episodes too short to be one, like creditless openings named as if they were.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShortEpisodesConfig {
+    /// In seconds, from the container or guessed from the size when it has no duration.
+    pub minimum_duration: Option<u64>,
+    /// File them in the show's `Specials` folder instead of leaving them out.
+    pub as_specials: bool,
+}
+
+/// Videos that look like failed downloads are reported instead of linked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrokenFilesConfig {
+    /// Empty videos, and videos with less than half of their size allocated on disk.
+    pub check: bool,
+    /// In bytes, smaller videos are considered broken too.
+    pub minimum_size: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetireConfig {
+    pub ratio: Option<f64>,
+    pub seed_days: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedLinkPolicy {
+    /// Link it again, it was probably deleted by accident.
+    #[default]
+    Recreate,
+    /// Take it as intentional: forget the link and don't link the source again until
+    /// `harvester restore`.
+    Tombstone,
+}
+
+/// Content that's never linked, like someone else's downloads on a shared seedbox.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlocklistConfig {
+    /// Parsed show or movie names, case insensitive.
+    pub names: Vec<String>,
+    /// Matched against the path relative to the incoming directory (`^alice/`).
+    pub patterns: Vec<PathPattern>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PathPattern(pub regex::Regex);
+
+impl TryFrom<String> for PathPattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        regex::Regex::new(&s).map(PathPattern).map_err(|e| format!("invalid pattern {:?}: {}", s, e))
+    }
+}
+
+impl BlocklistConfig {
+    pub fn blocks(&self, media: Option<&MediaData>, relative: &std::path::Path) -> bool {
+        media.and_then(MediaData::title).is_some_and(|name| self.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+            || self.patterns.iter().any(|p| p.0.is_match(&relative.to_string_lossy()))
+    }
+}
+
+/// Shell commands, see `hooks::Event` for what each one gets. Not run on dry runs.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub run_start: Option<String>,
+    pub run_end: Option<String>,
+    pub linked: Option<String>,
+    pub removed: Option<String>,
+    pub imported: Option<String>,
+}
+
+/// Copies of the state and plan taken before runs that remove links, for `harvester rollback`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// How many are kept in `<state_dir>/backups`, the oldest go first. 0 disables them.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self { keep: 5 }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// When links and directories may be removed and garbage purged, in local time. New links are
+    /// created on every run regardless. Empty means any time.
+    pub deletions: Vec<crate::schedule::Cron>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TorrentClientKind {
+    Qbittorrent,
+    Transmission,
+    Deluge,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentConfig {
+    pub client: TorrentClientKind,
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Path prefixes as seen by the torrent client, mapped to the local ones.
+    #[serde(default)]
+    pub paths: BTreeMap<String, String>,
+}
+
+/// A seedbox reached over SFTP, mirrored into the incoming directory before each run. Uses the
+/// `sftp` command, so keys and host aliases come from the ssh configuration.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteConfig {
+    /// `user@host`, or a `Host` from `~/.ssh/config`.
+    pub host: String,
+    pub port: Option<u16>,
+    /// The remote incoming directory.
+    pub path: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    #[default]
+    Hardlink,
+    /// Kodi style `.strm` files containing the source location.
+    Strm,
+}
+
+impl std::str::FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hardlink" => Ok(LinkMode::Hardlink),
+            "strm" => Ok(LinkMode::Strm),
+            _ => Err(format!("unknown link mode: {}", s)),
+        }
+    }
+}
+
+/// See `store`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateBackend {
+    /// `state.json`.
+    #[default]
+    Json,
+    /// `state.sqlite`, needs the `sqlite3` command.
+    Sqlite,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShowMoviePolicy {
+    /// The show's `Specials` folder.
+    #[default]
+    Specials,
+    /// The movies library, as `<show> movie (<year>)`.
+    Movies,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartPolicy {
+    /// Parts of a movie when there's a year (`Title 2021 Part 1`) and few of them, episodes of a
+    /// miniseries otherwise.
+    #[default]
+    Auto,
+    /// Stacked in the movie's folder as `<movie> - part1`.
+    Movie,
+    /// Episodes of the first season.
+    Episode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentDuplicatePolicy {
+    #[default]
+    Skip,
+    /// Next to the existing link, using Jellyfin's multiple-version naming.
+    Version,
+    /// As if it was a different file.
+    Link,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MovieLayout {
+    /// `movies/Title (Year)/movie.mkv`, with room for extras and sidecars.
+    #[default]
+    Folders,
+    /// `movies/Title (Year).mkv`, using the `naming.movie_folder` template as the file name.
+    Flat,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionPolicy {
+    #[default]
+    FirstWins,
+    /// Link every file using Jellyfin's multiple-version naming (`episode 5 - 1080p.mkv`).
+    KeepAll,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutProfile {
+    #[default]
+    Default,
+    /// Absolute episode numbers, NC/OVA releases under `Specials`, fansub group kept in the filename.
+    Anime,
+    /// The incoming directory structure with cleaned up names, garbage and unrecognized files left out.
+    Mirror,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    /// Glob matched against the path relative to the incoming directory (`anime/**`).
+    pub source: String,
+    #[serde(default)]
+    pub layout: LayoutProfile,
+}
+
+impl Route {
+    pub fn matcher(&self) -> regex::Regex {
+        glob_to_regex(&self.source)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserRoute {
+    pub name: String,
+    /// Glob matched against the path relative to the incoming directory (`downloads/alice/**`).
+    pub source: String,
+    /// Used instead of the target directory, relative to it or absolute. Libraries are relative to it.
+    pub target: std::path::PathBuf,
+}
+
+impl UserRoute {
+    pub fn matcher(&self) -> regex::Regex {
+        glob_to_regex(&self.source)
+    }
+}
+
+/// Variables that aren't config keys, besides the ones hooks get.
+const ENV_RESERVED: [&str; 2] = ["CONFIG", "EVENT"];
+
+/// The process environment for `env_table`. Variables that aren't valid UTF-8 can't be config
+/// values, they're skipped, with a warning when they look like they were meant to be.
+fn env_vars() -> Vec<(String, String)> {
+    std::env::vars_os()
+        .filter_map(|(key, value)| match (key.into_string(), value.into_string()) {
+            (Ok(key), Ok(value)) => Some((key, value)),
+            (key, _) => {
+                let key = key.unwrap_or_else(|k| k.to_string_lossy().to_string());
+                if key.starts_with("HARVESTER_") {
+                    eprintln!("ignoring {}, it's not valid UTF-8", key);
+                }
+                None
+            }
+        })
+        .collect()
+}
+
+/// A key for each `HARVESTER_*` variable: the rest of the name lowercased, with `__` between a
+/// table and its keys (`HARVESTER_TMDB__API_KEY`). Values are read as TOML when they parse as one
+/// (`true`, `2.5`, `["movie_year"]`) and as strings otherwise, quote them to force a string.
+fn env_table(env: impl IntoIterator<Item = (String, String)>) -> toml::Table {
+    let mut table = toml::Table::new();
+
+    for (name, value) in env {
+        let Some(key) = name.strip_prefix("HARVESTER_") else { continue; };
+        if ENV_RESERVED.contains(&key) || crate::hooks::VARIABLES.contains(&key) {
+            continue;
+        }
+
+        let value = toml::from_str::<toml::Table>(&format!("v = {}", value))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or(toml::Value::String(value));
+
+        let key = key.to_lowercase();
+        let mut path = key.rsplit("__").map(str::to_string);
+        let last = path.next().unwrap();
+        let nested = path.fold(toml::Table::from_iter([(last, value)]), |inner, part| toml::Table::from_iter([(part, toml::Value::Table(inner))]));
+        merge(&mut table, nested);
+    }
+
+    table
+}
+
+/// Tables are merged key by key, anything else in `overlay` replaces what was there.
+fn merge(table: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => merge(existing, value),
+            (_, value) => { table.insert(key, value); },
+        }
+    }
+}
+
+pub fn glob_to_regex(glob: &str) -> regex::Regex {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => { chars.next(); pattern.push_str(".*"); },
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+    regex::Regex::new(&pattern).unwrap()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TmdbConfig {
+    pub api_key: String,
+    /// Group movies using TMDB collection data when they're not in the local mapping.
+    #[serde(default)]
+    pub collections: bool,
+    /// Append provider IDs (`[tmdbid-603]`, `[tvdbid-81189]`) to created folder names.
+    #[serde(default)]
+    pub ids: bool,
+}
+
+/// Anime show folders (and file names) are named after the AniList title instead of the parsed one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnilistConfig {
+    pub language: TitleLanguage,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleLanguage {
+    #[default]
+    Romaji,
+    English,
+    Native,
+}
+
+impl Config {
+    pub fn load(path: &std::path::Path, profile: Option<&str>) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read config {:?}: {}", path, e))?;
+
+        let mut config = Self::parse_profile(&contents, env_vars(), profile).map_err(|e| format!("invalid config {:?}: {}", path, e))?;
+        config.load_scene_exceptions(path.parent().unwrap_or(std::path::Path::new(".")))?;
+
+        Ok(config)
+    }
+
+    /// The defaults plus whatever the `HARVESTER_*` environment variables set, for running without
+    /// a config file.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = Self::parse_with_env("", env_vars()).map_err(|e| format!("invalid environment config: {}", e))?;
+        config.load_scene_exceptions(std::path::Path::new("."))?;
+
+        Ok(config)
+    }
+
+    fn load_scene_exceptions(&mut self, base: &std::path::Path) -> Result<(), String> {
+        if let Some(scene_exceptions) = &self.scene_exceptions {
+            self.scene = SceneExceptions::load(&base.join(scene_exceptions))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        Self::parse_with_env(contents, std::iter::empty())
+    }
+
+    pub fn parse_with_env(contents: &str, env: impl IntoIterator<Item = (String, String)>) -> Result<Self, String> {
+        Self::parse_profile(contents, env, None)
+    }
+
+    /// The profile's keys take precedence over the rest of the file, and `HARVESTER_*` variables
+    /// (see `env_table`) over both.
+    pub fn parse_profile(contents: &str, env: impl IntoIterator<Item = (String, String)>, profile: Option<&str>) -> Result<Self, String> {
+        let mut table: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+
+        if let Some(name) = profile {
+            let profiles = table.get("profile").and_then(toml::Value::as_table);
+            let overlay = profiles.and_then(|p| p.get(name)).and_then(toml::Value::as_table).cloned().ok_or_else(|| {
+                let names = profiles.map_or(vec![], |p| p.keys().cloned().collect::<Vec<_>>());
+                format!("unknown profile {:?}, the config has {:?}", name, names)
+            })?;
+
+            if overlay.contains_key("profile") {
+                return Err(format!("profile {:?} can't have profiles of its own", name));
+            }
+            merge(&mut table, overlay);
+        }
+        merge(&mut table, env_table(env));
+
+        let mut config: Self = table.try_into().map_err(|e: toml::de::Error| e.to_string())?;
+
+        config.templates = Naming::compile(&config.naming)?;
+        for (name, show) in config.shows.iter_mut() {
+            if let Some(episode) = &show.episode {
+                let template = crate::template::compile(&format!("shows.{:?}.episode", name), episode, &NamingConfig::default().episode, &crate::template::episode_variables())?;
+                show.episode_template = Some(template);
+            }
+        }
+        crate::Analyzer::from_config(&config)?;
+
+        Ok(config)
+    }
+
+    pub fn default_path() -> Option<std::path::PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(base.join("harvester").join("config.toml"))
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        self.rules.as_deref().unwrap_or(&Rule::DEFAULT_PRIORITY)
+    }
+
+    pub fn state_path(&self) -> Option<std::path::PathBuf> {
+        let dir = self.state_dir.clone().or_else(crate::state::State::default_dir)?;
+
+        Some(match self.state_backend {
+            StateBackend::Json => dir.join("state.json"),
+            StateBackend::Sqlite => dir.join("state.sqlite"),
+        })
+    }
+
+    pub fn state_store(&self) -> Option<Box<dyn StateStore>> {
+        let path = self.state_path()?;
+
+        Some(match self.state_backend {
+            StateBackend::Json => Box::new(JsonStore { path }),
+            StateBackend::Sqlite => Box::new(SqliteStore { path }),
+        })
+    }
+
+    pub fn show(&self, name: &str) -> Option<&ShowConfig> {
+        self.shows.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, show)| show)
+    }
+
+    pub fn local_collection(&self, title: &str) -> Option<&str> {
+        self.collections
+            .iter()
+            .find(|(_, titles)| titles.iter().any(|t| t.eq_ignore_ascii_case(title)))
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -0,0 +1,308 @@
+//! Just enough Matroska and MP4 parsing to read the title tag encoders leave in the container,
+//! its duration and creation time, and Matroska chapter names.
+
+use std::io::{Read, Seek, SeekFrom};
+
+/// How much of a Matroska file is read looking for the segment info, it comes before any clusters.
+const MATROSKA_HEAD: u64 = 1 << 20;
+/// Larger `moov` boxes are skipped instead of read into memory.
+const MP4_MOOV_LIMIT: u64 = 64 << 20;
+
+pub fn title(path: &std::path::Path) -> Option<String> {
+    let title = match crate::analyzer::extension(path).as_deref() {
+        Some("mkv") => matroska_info(&matroska_head(path)?).title,
+        Some("mp4") => mp4_info(&mp4_moov(path)?).title,
+        _ => None,
+    }?;
+
+    let title = title.trim().to_string();
+    if title.is_empty() { None } else { Some(title) }
+}
+
+/// How long the video plays according to the container.
+pub fn duration(path: &std::path::Path) -> Option<std::time::Duration> {
+    match crate::analyzer::extension(path).as_deref() {
+        Some("mkv") => matroska_info(&matroska_head(path)?).duration,
+        Some("mp4") => mp4_info(&mp4_moov(path)?).duration,
+        _ => None,
+    }
+}
+
+/// When the container says the video was made, as a Unix timestamp.
+pub fn creation_time(path: &std::path::Path) -> Option<u64> {
+    match crate::analyzer::extension(path).as_deref() {
+        Some("mkv") => matroska_info(&matroska_head(path)?).created,
+        Some("mp4") => mp4_info(&mp4_moov(path)?).created,
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Info {
+    title: Option<String>,
+    duration: Option<std::time::Duration>,
+    created: Option<u64>,
+}
+
+fn matroska_head(path: &std::path::Path) -> Option<Vec<u8>> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut head = vec![];
+    file.take(MATROSKA_HEAD).read_to_end(&mut head).ok()?;
+    Some(head)
+}
+
+/// Chapter names of a Matroska file, in order. Empty for anything else.
+pub fn chapters(path: &std::path::Path) -> Vec<String> {
+    if crate::analyzer::extension(path).as_deref() != Some("mkv") {
+        return vec![];
+    }
+    let Some(head) = matroska_head(path) else { return vec![]; };
+
+    let mut names = vec![];
+    matroska_chapters(&head, 0, &mut names);
+    names
+}
+
+/// An EBML variable length integer, returning its value (with the length marker removed unless
+/// `keep_marker`) and how many bytes it took.
+fn vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 || data.len() < length {
+        return None;
+    }
+
+    let mut value = if keep_marker { first as u64 } else { (first as u64) & (0xff >> length) };
+    for b in &data[1..length] {
+        value = (value << 8) | *b as u64;
+    }
+
+    Some((value, length))
+}
+
+fn matroska_info(data: &[u8]) -> Info {
+    const EBML: u64 = 0x1A45DFA3;
+    const SEGMENT: u64 = 0x18538067;
+    const INFO: u64 = 0x1549A966;
+    const TITLE: u64 = 0x7BA9;
+    const TIMECODE_SCALE: u64 = 0x2AD7B1;
+    const DURATION: u64 = 0x4489;
+    const DATE_UTC: u64 = 0x4461;
+    const CLUSTER: u64 = 0x1F43B675;
+
+    let mut info = Info::default();
+    // Nanoseconds per tick, a millisecond unless the file says otherwise.
+    let mut scale = 1_000_000u64;
+    let mut ticks = None;
+
+    let mut position = 0;
+    let mut end = data.len();
+
+    while position < end {
+        let Some((id, id_length)) = vint(&data[position..], true) else { break; };
+        let Some((size, size_length)) = vint(&data[position + id_length..], false) else { break; };
+        let body = position + id_length + size_length;
+        let unknown_size = size == (1 << (7 * size_length)) - 1;
+        let Some(body_end) = (if unknown_size { Some(end) } else { body.checked_add(size as usize) }) else { break; };
+        let body_end = body_end.min(end);
+        if body > body_end {
+            break;
+        }
+        let bytes = &data[body..body_end];
+
+        match id {
+            EBML if position == 0 => position = body_end,
+            SEGMENT | INFO => {
+                position = body;
+                end = body_end;
+            }
+            TITLE => {
+                info.title = String::from_utf8(bytes.to_vec()).ok();
+                position = body_end;
+            }
+            TIMECODE_SCALE => {
+                scale = bytes.iter().fold(0, |n, b| (n << 8) | *b as u64);
+                position = body_end;
+            }
+            DURATION => {
+                ticks = match bytes.len() {
+                    4 => Some(f32::from_be_bytes(bytes.try_into().unwrap()) as f64),
+                    8 => Some(f64::from_be_bytes(bytes.try_into().unwrap())),
+                    _ => None,
+                };
+                position = body_end;
+            }
+            DATE_UTC => {
+                // Nanoseconds since 2001, which is 978307200 in Unix time.
+                let nanoseconds = <[u8; 8]>::try_from(bytes).ok().map(i64::from_be_bytes);
+                info.created = nanoseconds.and_then(|n| u64::try_from(n / 1_000_000_000 + 978307200).ok());
+                position = body_end;
+            }
+            CLUSTER => break,
+            _ if position == 0 => break,
+            _ => position = body_end,
+        }
+    }
+
+    info.duration = ticks
+        .filter(|t| t.is_finite() && *t >= 0.0)
+        .map(|t| std::time::Duration::from_nanos((t * scale as f64) as u64));
+
+    info
+}
+
+/// Walks down into the chapter elements, collecting every display string. Returns false once
+/// the clusters start, nothing after them is read.
+fn matroska_chapters(data: &[u8], mut position: usize, names: &mut Vec<String>) -> bool {
+    const EBML: u64 = 0x1A45DFA3;
+    const SEGMENT: u64 = 0x18538067;
+    const CHAPTERS: u64 = 0x1043A770;
+    const EDITION_ENTRY: u64 = 0x45B9;
+    const CHAPTER_ATOM: u64 = 0xB6;
+    const CHAPTER_DISPLAY: u64 = 0x80;
+    const CHAP_STRING: u64 = 0x85;
+    const CLUSTER: u64 = 0x1F43B675;
+
+    let start = position;
+    while position < data.len() {
+        let Some((id, id_length)) = vint(&data[position..], true) else { return false; };
+        let Some((size, size_length)) = vint(&data[position + id_length..], false) else { return false; };
+        let body = position + id_length + size_length;
+        let unknown_size = size == (1 << (7 * size_length)) - 1;
+        let Some(body_end) = (if unknown_size { Some(data.len()) } else { body.checked_add(size as usize) }) else { return false; };
+        let body_end = body_end.min(data.len());
+        if body > body_end {
+            return false;
+        }
+
+        match id {
+            EBML if position == start => {}
+            SEGMENT | CHAPTERS | EDITION_ENTRY | CHAPTER_ATOM | CHAPTER_DISPLAY if !matroska_chapters(&data[..body_end], body, names) => {
+                return false;
+            }
+            SEGMENT | CHAPTERS | EDITION_ENTRY | CHAPTER_ATOM | CHAPTER_DISPLAY => {}
+            CHAP_STRING => names.extend(String::from_utf8(data[body..body_end].to_vec())),
+            CLUSTER => return false,
+            _ => {}
+        }
+
+        position = body_end;
+    }
+
+    true
+}
+
+/// Box type and body range within `data`.
+fn mp4_boxes(data: &[u8]) -> Vec<([u8; 4], std::ops::Range<usize>)> {
+    let mut boxes = vec![];
+    let mut position = 0;
+
+    while position + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = data[position + 4..position + 8].try_into().unwrap();
+        let end = if size == 0 { data.len() } else { position + size };
+
+        if size != 0 && size < 8 || end > data.len() {
+            break;
+        }
+
+        boxes.push((kind, position + 8..end));
+        position = end;
+    }
+
+    boxes
+}
+
+fn mp4_child<'d>(data: &'d [u8], kind: &[u8; 4]) -> Option<&'d [u8]> {
+    mp4_boxes(data).into_iter().find(|(k, _)| k == kind).map(|(_, range)| &data[range])
+}
+
+/// The `moov` box body, unless it's unreasonably large.
+fn mp4_moov(path: &std::path::Path) -> Option<Vec<u8>> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let length = file.metadata().ok()?.len();
+    let mut position = 0;
+
+    // Top level boxes are walked with seeks, `mdat` can be gigabytes long and `moov` is often last.
+    while position + 8 <= length {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(position)).ok()?;
+        file.read_exact(&mut header[..8]).ok()?;
+
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        let (size, header_length) = match u32::from_be_bytes(header[..4].try_into().unwrap()) as u64 {
+            0 => (length - position, 8),
+            1 => {
+                file.read_exact(&mut header[8..]).ok()?;
+                (u64::from_be_bytes(header[8..].try_into().unwrap()), 16)
+            }
+            size => (size, 8),
+        };
+
+        if size < header_length {
+            return None;
+        }
+
+        if &kind == b"moov" {
+            if size > MP4_MOOV_LIMIT {
+                return None;
+            }
+
+            let mut moov = vec![0u8; (size - header_length) as usize];
+            file.read_exact(&mut moov).ok()?;
+            return Some(moov);
+        }
+
+        position += size;
+    }
+
+    None
+}
+
+fn mp4_title(moov: &[u8]) -> Option<String> {
+    let udta = mp4_child(moov, b"udta")?;
+    // `meta` is a full box, its children come after the version and flags.
+    let meta = mp4_child(udta, b"meta")?.get(4..)?;
+    let ilst = mp4_child(meta, b"ilst")?;
+    let name = mp4_child(ilst, b"\xa9nam")?;
+    // `data` starts with its type and locale.
+    let value = mp4_child(name, b"data")?.get(8..)?;
+
+    String::from_utf8(value.to_vec()).ok()
+}
+
+fn mp4_duration(moov: &[u8]) -> Option<std::time::Duration> {
+    let mvhd = mp4_child(moov, b"mvhd")?;
+    let number = |range: std::ops::Range<usize>| mvhd.get(range).map(|b| b.iter().fold(0u64, |n, b| (n << 8) | *b as u64));
+
+    // Version 1 has 64 bit creation and modification times and duration.
+    let (timescale, duration) = match mvhd.first()? {
+        0 => (number(12..16)?, number(16..20)?),
+        1 => (number(20..24)?, number(24..32)?),
+        _ => return None,
+    };
+
+    if timescale == 0 {
+        return None;
+    }
+
+    Some(std::time::Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
+/// Seconds since 1904, zero when the muxer didn't set it.
+fn mp4_created(moov: &[u8]) -> Option<u64> {
+    let mvhd = mp4_child(moov, b"mvhd")?;
+    let number = |range: std::ops::Range<usize>| mvhd.get(range).map(|b| b.iter().fold(0u64, |n, b| (n << 8) | *b as u64));
+
+    let seconds = match mvhd.first()? {
+        0 => number(4..8)?,
+        1 => number(4..12)?,
+        _ => return None,
+    };
+
+    seconds.checked_sub(2082844800).filter(|_| seconds != 0)
+}
+
+fn mp4_info(moov: &[u8]) -> Info {
+    Info { title: mp4_title(moov), duration: mp4_duration(moov), created: mp4_created(moov) }
+}
@@ -0,0 +1,183 @@
+//! `harvester daemon` bookkeeping: what the runs are up to, the HTTP health endpoints
+//! orchestrators and uptime monitors poll, and the local control socket scripts poke it through.
+
+use std::io::{BufRead, Read, Write};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8383";
+
+#[derive(Clone, Debug, Default)]
+pub struct Status {
+    pub runs: u64,
+    pub running: bool,
+    /// Unix timestamp of when the last run finished.
+    pub last_run: Option<u64>,
+    /// Why the last run failed, if it did.
+    pub last_error: Option<String>,
+    pub paused: bool,
+    rescan: bool,
+}
+
+#[derive(Default)]
+pub struct Daemon {
+    status: std::sync::Mutex<Status>,
+    wake: std::sync::Condvar,
+}
+
+impl Daemon {
+    pub fn run_started(&self) {
+        self.status.lock().unwrap().running = true;
+    }
+
+    pub fn run_finished(&self, now: u64) {
+        let mut status = self.status.lock().unwrap();
+        status.running = false;
+        status.runs += 1;
+        status.last_run = Some(now);
+        status.last_error = None;
+    }
+
+    /// The daemon keeps going, later runs may well succeed, but it's not ready until one does.
+    pub fn run_failed(&self, now: u64, error: &str) {
+        self.run_finished(now);
+        self.status.lock().unwrap().last_error = Some(error.to_string());
+    }
+
+    pub fn status(&self) -> Status {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Sleeps until the next run is due, or a rescan was asked for (even during the last run).
+    pub fn wait(&self, interval: std::time::Duration) {
+        let status = self.status.lock().unwrap();
+        let (mut status, _) = self.wake.wait_timeout_while(status, interval, |s| !s.rescan).unwrap();
+        status.rescan = false;
+    }
+
+    /// The response to a control socket command.
+    pub fn command(&self, command: &str, store: Option<&dyn crate::store::StateStore>) -> String {
+        let mut status = self.status.lock().unwrap();
+
+        match command {
+            "status" => format!(
+                "runs: {}\nrunning: {}\npaused: {}\nlast run: {}\nlast error: {}\n",
+                status.runs,
+                status.running,
+                status.paused,
+                status.last_run.map_or("never".to_string(), |at| at.to_string()),
+                status.last_error.as_deref().unwrap_or("none"),
+            ),
+            "rescan" if status.paused => "paused, resume first\n".to_string(),
+            "rescan" => {
+                status.rescan = true;
+                self.wake.notify_all();
+                "rescan requested\n".to_string()
+            }
+            "pause" => {
+                status.paused = true;
+                "paused\n".to_string()
+            }
+            "resume" => {
+                status.paused = false;
+                "resumed\n".to_string()
+            }
+            "dump-state" => match store.map(|s| s.load()) {
+                Some(Ok(state)) => serde_json::to_string_pretty(&state).unwrap() + "\n",
+                Some(Err(e)) => format!("unable to read state: {}\n", e),
+                None => "unable to determine state directory, set state_dir\n".to_string(),
+            },
+            _ => format!("unknown command {:?}, expected status, rescan, pause, resume or dump-state\n", command),
+        }
+    }
+
+    /// Status code and body for a request path. Live as long as the process answers, ready once
+    /// a run finished, and as long as the last one didn't fail.
+    pub fn respond(&self, path: &str) -> (u16, String) {
+        let status = self.status();
+
+        match path {
+            "/healthz" => (200, "ok\n".to_string()),
+            "/readyz" => match (status.last_run, status.last_error) {
+                (Some(at), Some(error)) => (503, format!("last run failed at {}: {}\n", at, error)),
+                (Some(at), None) => (200, format!("ready, last run finished at {}\n", at)),
+                (None, _) => (503, "waiting for the first run\n".to_string()),
+            },
+            _ => (404, "not found\n".to_string()),
+        }
+    }
+}
+
+/// Answers requests one at a time, they're tiny.
+pub fn serve(listener: std::net::TcpListener, daemon: std::sync::Arc<Daemon>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue; };
+
+        let mut request = String::new();
+        if std::io::BufReader::new(&stream).read_line(&mut request).is_err() {
+            continue;
+        }
+
+        let path = request.split_whitespace().nth(1).unwrap_or("/");
+        let (code, body) = daemon.respond(path);
+        let reason = match code {
+            200 => "OK",
+            404 => "Not Found",
+            _ => "Service Unavailable",
+        };
+
+        let _ = write!(
+            stream,
+            "HTTP/1.0 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+            code,
+            reason,
+            body.len(),
+            body,
+        );
+    }
+}
+
+/// Asks a running daemon, for `harvester health`.
+pub fn check(address: &str, path: &str) -> Result<String, String> {
+    let url = format!("http://{}{}", address, path);
+
+    match ureq::get(&url).timeout(std::time::Duration::from_secs(5)).call() {
+        Ok(response) => response.into_string().map_err(|e| format!("{}: {}", url, e)),
+        Err(ureq::Error::Status(code, response)) => {
+            Err(format!("{}: {} {}", url, code, response.into_string().unwrap_or_default().trim()))
+        }
+        Err(e) => Err(format!("{}: {}", url, e)),
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/harvester.sock`, or next to the state when there's no runtime directory.
+pub fn default_socket() -> Option<std::path::PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Some(std::path::PathBuf::from(dir).join("harvester.sock")),
+        None => Some(crate::state::State::default_dir()?.join("harvester.sock")),
+    }
+}
+
+/// One command per connection: a line in, the response until the socket closes.
+pub fn control(listener: std::os::unix::net::UnixListener, daemon: std::sync::Arc<Daemon>, store: Option<Box<dyn crate::store::StateStore>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue; };
+
+        let mut command = String::new();
+        if std::io::BufReader::new(&stream).read_line(&mut command).is_err() {
+            continue;
+        }
+
+        let _ = stream.write_all(daemon.command(command.trim(), store.as_deref()).as_bytes());
+    }
+}
+
+/// Sends a control command to a running daemon, for `harvester ctl`.
+pub fn send(socket: &std::path::Path, command: &str) -> Result<String, String> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)
+        .map_err(|e| format!("unable to connect to {:?}: {}, is the daemon running?", socket, e))?;
+
+    writeln!(stream, "{}", command).map_err(|e| format!("{:?}: {}", socket, e))?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| format!("{:?}: {}", socket, e))?;
+    Ok(response)
+}
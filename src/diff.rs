@@ -0,0 +1,63 @@
+use std::collections::{HashMap, HashSet};
+
+/// What makes a library file "the same" as what harvester would create.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Identity {
+    Inode(u64),
+    Strm(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Missing(std::path::PathBuf),
+    Extra(std::path::PathBuf),
+    Misnamed { actual: std::path::PathBuf, expected: std::path::PathBuf },
+    /// Something else is taking the place where the link should be.
+    Conflict(std::path::PathBuf),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Change::Missing(path) => write!(f, "+ {:?}", path),
+            Change::Extra(path) => write!(f, "- {:?}", path),
+            Change::Misnamed { actual, expected } => write!(f, "~ {:?} -> {:?}", actual, expected),
+            Change::Conflict(path) => write!(f, "! {:?}", path),
+        }
+    }
+}
+
+pub fn diff(expected: &[(std::path::PathBuf, Identity)], actual: &[(std::path::PathBuf, Identity)]) -> Vec<Change> {
+    let actual_by_path = actual.iter().cloned().collect::<HashMap<_, _>>();
+    let expected_paths = expected.iter().map(|(p, _)| p).collect::<HashSet<_>>();
+
+    let mut misplaced = HashMap::new();
+    for (path, identity) in actual.iter() {
+        if !expected_paths.contains(path) {
+            misplaced.entry(identity).or_insert(path);
+        }
+    }
+
+    let mut changes = vec![];
+    let mut renamed = HashSet::new();
+    for (path, identity) in expected.iter() {
+        match actual_by_path.get(path) {
+            Some(existing) if existing == identity => {}
+            Some(_) => changes.push(Change::Conflict(path.clone())),
+            None => match misplaced.get(identity) {
+                Some(actual) if renamed.insert(*actual) => {
+                    changes.push(Change::Misnamed { actual: (*actual).clone(), expected: path.clone() });
+                }
+                _ => changes.push(Change::Missing(path.clone())),
+            },
+        }
+    }
+
+    for (path, _) in actual.iter() {
+        if !expected_paths.contains(path) && !renamed.contains(path) {
+            changes.push(Change::Extra(path.clone()));
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,170 @@
+use std::os::unix::fs::MetadataExt;
+
+use crate::arr::Arr;
+use crate::config::{Config, LinkMode};
+use crate::tmdb::Tmdb;
+use crate::tracker::Tracker;
+
+pub struct Finding {
+    pub check: String,
+    pub problem: Option<String>,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.problem {
+            None => write!(f, "ok     {}", self.check),
+            Some(problem) => write!(f, "error  {}: {}", self.check, problem),
+        }
+    }
+}
+
+fn check(findings: &mut Vec<Finding>, check: &str, result: Result<(), String>) {
+    findings.push(Finding { check: check.to_string(), problem: result.err() });
+}
+
+fn writable(path: &std::path::Path) -> Result<(), String> {
+    let probe = path.join(".harvester-doctor");
+    std::fs::write(&probe, "")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("unable to write to {:?} ({}), check its owner and permissions", path, e))
+}
+
+/// Links a probe file inside `dir`, since some filesystems (SMB shares, exFAT) take writes but
+/// not hard links, and finding out on the first file of a run is too late.
+pub fn hard_links(dir: &std::path::Path) -> Result<(), String> {
+    let (probe, link) = (dir.join(".harvester-probe"), dir.join(".harvester-probe-link"));
+    std::fs::write(&probe, "").map_err(|e| format!("unable to write to {:?} ({}), check its owner and permissions", dir, e))?;
+
+    let linked = std::fs::hard_link(&probe, &link);
+    let _ = std::fs::remove_file(&link);
+    let _ = std::fs::remove_file(&probe);
+
+    linked.map_err(|e| format!("{:?} doesn't support hard links ({}), use --link-mode strm or a library on the same local filesystem as incoming", dir, e))
+}
+
+/// Whether incoming and any of the library roots (see `Layout::roots`) are inside one another:
+/// the run would scan its own links as new downloads, and cleanup would remove what it just made.
+pub fn overlap(incoming: &std::path::Path, roots: &[std::path::PathBuf]) -> Result<(), String> {
+    // Libraries may not exist yet, their closest existing parent is what symlinks get resolved on.
+    let resolve = |p: &std::path::Path| {
+        let absolute = std::path::absolute(p).unwrap_or(p.to_path_buf());
+        let existing = absolute.ancestors().find_map(|a| Some((a, std::fs::canonicalize(a).ok()?)));
+        match existing {
+            Some((ancestor, resolved)) => resolved.join(absolute.strip_prefix(ancestor).unwrap()),
+            None => absolute,
+        }
+    };
+    let source = resolve(incoming);
+
+    for root in roots.iter() {
+        let resolved = resolve(root);
+        if resolved.starts_with(&source) || source.starts_with(&resolved) {
+            return Err(format!("incoming {:?} and library {:?} overlap, keep them in separate directories", incoming, root));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn config(config_path: Option<&std::path::Path>, profile: Option<&str>) -> (Vec<Finding>, Option<Config>) {
+    match config_path {
+        Some(path) => match Config::load(path, profile) {
+            Ok(config) => (vec![Finding { check: format!("config {:?}", path), problem: None }], Some(config)),
+            Err(e) => (vec![Finding { check: format!("config {:?}", path), problem: Some(e) }], None),
+        },
+        None => match Config::from_env() {
+            Ok(config) => (vec![Finding { check: "config (none, using defaults and environment)".to_string(), problem: None }], Some(config)),
+            Err(e) => (vec![Finding { check: "config (none, using defaults and environment)".to_string(), problem: Some(e) }], None),
+        },
+    }
+}
+
+pub fn paths(config: &Config, incoming: &std::path::Path, target: &std::path::Path) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    let incoming_metadata = std::fs::metadata(incoming);
+    let target_metadata = std::fs::metadata(target);
+
+    check(&mut findings, &format!("incoming {:?} exists", incoming), incoming_metadata.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+    check(&mut findings, &format!("target {:?} exists", target), target_metadata.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+
+    let mounts = crate::mounts::mounts();
+    for (name, path) in [("incoming", incoming), ("target", target)] {
+        if let Some(mount) = crate::mounts::mount_of(&mounts, path).filter(|m| m.is_network()) {
+            check(&mut findings, &format!("{} is on {} at {:?}, transient errors are retried", name, mount.fstype, mount.point), Ok(()));
+        }
+    }
+
+    let roots = crate::Layout::new(config, incoming).roots(target);
+    check(&mut findings, "incoming and target don't overlap", overlap(incoming, &roots));
+
+    if let Ok(target_metadata) = &target_metadata {
+        check(&mut findings, &format!("target {:?} is writable", target), writable(target));
+        if config.link_mode == LinkMode::Hardlink {
+            check(&mut findings, &format!("target {:?} supports hard links", target), hard_links(target));
+        }
+
+        if let (Ok(incoming_metadata), LinkMode::Hardlink) = (&incoming_metadata, config.link_mode) {
+            let same = if incoming_metadata.dev() == target_metadata.dev() {
+                Ok(())
+            } else {
+                Err("hard links can't cross filesystems, move them to the same filesystem or use --link-mode strm".to_string())
+            };
+            check(&mut findings, "incoming and target on the same filesystem", same);
+        }
+    }
+
+    findings
+}
+
+pub fn state(config: &Config) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    match config.state_store() {
+        Some(store) => {
+            let dir = store.path().parent().unwrap();
+            let writable = std::fs::create_dir_all(dir).map_err(|e| e.to_string()).and_then(|_| writable(dir));
+            check(&mut findings, &format!("state directory {:?} is writable", dir), writable);
+            check(&mut findings, &format!("state {:?} is readable", store.path()), store.load().map(|_| ()));
+        }
+        None => check(&mut findings, "state directory", Err("unable to determine it, set state_dir".to_string())),
+    }
+
+    findings
+}
+
+pub fn rules(config: &Config) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    check(&mut findings, "analyzer rules compile", crate::Analyzer::from_config(config).map(|_| ()));
+
+    findings
+}
+
+pub fn integrations(config: &Config) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    if let Some(tmdb) = &config.tmdb {
+        check(&mut findings, "tmdb reachable", Tmdb::new(&tmdb.api_key).ping());
+    }
+
+    if let Some(anilist) = &config.anilist {
+        check(&mut findings, "anilist reachable", crate::anilist::AniList::new(anilist.language).ping());
+    }
+
+    if let Some(torrent) = &config.torrent {
+        let result = crate::torrent::connect(torrent).and_then(|c| c.torrents()).map(|_| ());
+        check(&mut findings, &format!("torrent client {:?} reachable", torrent.url), result);
+    }
+
+    for arr in Arr::all(config) {
+        check(&mut findings, &format!("{} reachable", arr.kind), arr.ping());
+    }
+
+    for tracker in Tracker::all(config) {
+        check(&mut findings, &format!("{} reachable", tracker.kind), tracker.ping());
+    }
+
+    findings
+}
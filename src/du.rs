@@ -0,0 +1,102 @@
+//! `harvester du`: how much space each show and movie takes in the library, telling apart bytes
+//! shared with the incoming directory through hard links (removing the link frees nothing) from
+//! bytes only the library holds and from copies of the same file under another inode.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::os::unix::fs::MetadataExt;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Counting each inode once.
+    pub total: u64,
+    /// Also linked from outside the library, like the incoming directory.
+    pub shared: u64,
+    /// Same contents as another file in the library with a different inode.
+    pub duplicated: u64,
+}
+
+impl Usage {
+    /// What removing it from the library (and only from the library) frees.
+    pub fn exclusive(&self) -> u64 {
+        self.total - self.shared
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.total += other.total;
+        self.shared += other.shared;
+        self.duplicated += other.duplicated;
+    }
+}
+
+fn walk(path: &std::path::Path, files: &mut Vec<(std::path::PathBuf, std::fs::Metadata)>) {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else { return; };
+
+    if metadata.is_dir() {
+        for entry in path.read_dir().into_iter().flatten().filter_map(Result::ok) {
+            walk(&entry.path(), files);
+        }
+    } else if metadata.is_file() {
+        files.push((path.to_path_buf(), metadata));
+    }
+}
+
+/// The entry a file counts towards: the folder right under its library (`shows/Breaking Bad`),
+/// or the file itself for flat movie layouts. For the target root, that's two levels down.
+fn item(root: &std::path::Path, path: &std::path::Path, depth: usize) -> std::path::PathBuf {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.iter().take(depth).collect()
+}
+
+/// Per entry of the library roots (see `Layout::roots`), the first one being the target directory.
+pub fn usage(roots: &[std::path::PathBuf]) -> BTreeMap<std::path::PathBuf, Usage> {
+    let mut files = vec![];
+    for (i, root) in roots.iter().enumerate() {
+        let mut found = vec![];
+        walk(root, &mut found);
+        files.extend(found.into_iter().map(|(path, metadata)| (item(root, &path, if i == 0 { 2 } else { 1 }), path, metadata)));
+    }
+
+    let in_library = files.iter().fold(BTreeMap::<(u64, u64), u64>::new(), |mut counts, (_, _, m)| {
+        *counts.entry((m.dev(), m.ino())).or_default() += 1;
+        counts
+    });
+
+    let mut usage = BTreeMap::<std::path::PathBuf, Usage>::new();
+    let mut seen = BTreeSet::new();
+    let mut fingerprints = BTreeSet::new();
+    let same_size = files.iter().fold(BTreeMap::<u64, usize>::new(), |mut counts, (_, _, m)| {
+        *counts.entry(m.len()).or_default() += 1;
+        counts
+    });
+
+    for (item, path, metadata) in files.iter() {
+        let inode = (metadata.dev(), metadata.ino());
+        if !seen.insert(inode) {
+            continue;
+        }
+
+        let entry = usage.entry(item.clone()).or_default();
+        entry.total += metadata.len();
+        if metadata.nlink() > in_library[&inode] {
+            entry.shared += metadata.len();
+        }
+
+        // Only files with a same sized one are read, most have none.
+        if metadata.len() > 0 && same_size[&metadata.len()] > 1 {
+            if let Ok(fingerprint) = crate::state::fingerprint(path) {
+                if !fingerprints.insert(fingerprint) {
+                    entry.duplicated += metadata.len();
+                }
+            }
+        }
+    }
+
+    usage
+}
+
+pub fn total(usage: &BTreeMap<std::path::PathBuf, Usage>) -> Usage {
+    usage.values().fold(Usage::default(), |mut total, u| {
+        total.add(u);
+        total
+    })
+}
@@ -0,0 +1,118 @@
+//! User commands run around a run and for every link created or removed, described through
+//! `HARVESTER_*` environment variables.
+
+use crate::config::HooksConfig;
+
+pub enum Event<'a> {
+    RunStart,
+    /// `HARVESTER_OUTCOME`, and `HARVESTER_LINKED` and `HARVESTER_REMOVED` have the counts.
+    RunEnd { outcome: Outcome, linked: usize, removed: usize },
+    /// `HARVESTER_SOURCE` and `HARVESTER_LINK`.
+    Linked { source: &'a std::path::Path, link: &'a std::path::Path },
+    /// `HARVESTER_LINK`.
+    Removed { link: &'a std::path::Path },
+    /// Once per top-level incoming entry something was linked from, after its `linked` events:
+    /// `HARVESTER_TORRENT`, `HARVESTER_SUMMARY` ("12 episodes, 3 subtitles") and
+    /// `HARVESTER_COMPLETE`, `false` when some of its videos couldn't be linked.
+    Imported { torrent: &'a str, summary: &'a str, complete: bool },
+}
+
+/// How a run ended, `finished`, `interrupted` (with changes left for `harvester resume`) or `failed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Finished,
+    Interrupted,
+    Failed,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Finished => write!(f, "finished"),
+            Outcome::Interrupted => write!(f, "interrupted"),
+            Outcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Names of the variables hooks get, besides `HARVESTER_EVENT`.
+pub const VARIABLES: [&str; 8] = ["SOURCE", "LINK", "LINKED", "REMOVED", "OUTCOME", "TORRENT", "SUMMARY", "COMPLETE"];
+
+impl Event<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::RunStart => "run_start",
+            Event::RunEnd { .. } => "run_end",
+            Event::Linked { .. } => "linked",
+            Event::Removed { .. } => "removed",
+            Event::Imported { .. } => "imported",
+        }
+    }
+
+    fn variables(&self) -> Vec<(&'static str, std::ffi::OsString)> {
+        match self {
+            Event::RunStart => vec![],
+            Event::RunEnd { outcome, linked, removed } => vec![
+                ("OUTCOME", outcome.to_string().into()),
+                ("LINKED", linked.to_string().into()),
+                ("REMOVED", removed.to_string().into()),
+            ],
+            Event::Linked { source, link } => vec![("SOURCE", source.into()), ("LINK", link.into())],
+            Event::Removed { link } => vec![("LINK", link.into())],
+            Event::Imported { torrent, summary, complete } => {
+                vec![("TORRENT", torrent.into()), ("SUMMARY", summary.into()), ("COMPLETE", complete.to_string().into())]
+            }
+        }
+    }
+}
+
+/// Runs the event's command through `sh -c`, if there's one. A failing hook doesn't stop the run.
+pub fn run(hooks: &HooksConfig, event: Event) -> Result<(), String> {
+    let command = match event {
+        Event::RunStart => &hooks.run_start,
+        Event::RunEnd { .. } => &hooks.run_end,
+        Event::Linked { .. } => &hooks.linked,
+        Event::Removed { .. } => &hooks.removed,
+        Event::Imported { .. } => &hooks.imported,
+    };
+    let Some(command) = command else { return Ok(()); };
+
+    let mut child = std::process::Command::new("sh");
+    child.arg("-c").arg(command).env("HARVESTER_EVENT", event.name());
+    for (name, value) in event.variables() {
+        child.env(format!("HARVESTER_{}", name), value);
+    }
+
+    let status = child.status().map_err(|e| format!("unable to run {} hook: {}", event.name(), e))?;
+    if !status.success() {
+        return Err(format!("{} hook failed: {}", event.name(), status));
+    }
+
+    Ok(())
+}
+
+/// Runs the `run_end` hook when dropped, so every run that got to `run_start` gets to it too,
+/// as failed unless it's told how it `ended` before.
+pub struct RunEnd<'a> {
+    hooks: &'a HooksConfig,
+    outcome: Outcome,
+    linked: usize,
+    removed: usize,
+}
+
+impl<'a> RunEnd<'a> {
+    pub fn new(hooks: &'a HooksConfig) -> Self {
+        Self { hooks, outcome: Outcome::Failed, linked: 0, removed: 0 }
+    }
+
+    pub fn ended(&mut self, outcome: Outcome, linked: usize, removed: usize) {
+        (self.outcome, self.linked, self.removed) = (outcome, linked, removed);
+    }
+}
+
+impl Drop for RunEnd<'_> {
+    fn drop(&mut self) {
+        let event = Event::RunEnd { outcome: self.outcome, linked: self.linked, removed: self.removed };
+        run(self.hooks, event).unwrap_or_else(|e| eprintln!("{}", e));
+    }
+}
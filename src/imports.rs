@@ -0,0 +1,86 @@
+//! Each top-level entry of the incoming directory taken as a unit, which usually is a torrent (not
+//! to be confused with the torrent client's, see `torrent`):
+//! what it brought into the library as a whole, for the report and the `imported` hook, and
+//! whether all of its videos made it in.
+
+use std::collections::BTreeMap;
+
+use crate::{MediaData, ScannedFile};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Import {
+    /// The entry's name in the incoming directory, a file for single file torrents.
+    pub name: String,
+    /// What was linked from it this run, by kind (`episodes`, `movies`).
+    pub linked: BTreeMap<&'static str, usize>,
+    /// Subtitle files in it.
+    pub subtitles: usize,
+    /// Videos in it that aren't recognized or look broken, which the library doesn't have.
+    pub missing: Vec<std::path::PathBuf>,
+}
+
+impl Import {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// "12 episodes, 3 subtitles".
+    pub fn describe(&self) -> String {
+        let subtitles = (self.subtitles > 0).then_some((&"subtitles", &self.subtitles));
+        let parts = self
+            .linked
+            .iter()
+            .chain(subtitles)
+            .map(|(kind, count)| match count {
+                1 => format!("1 {}", kind.strip_suffix('s').unwrap_or(kind)),
+                n => format!("{} {}", n, kind),
+            })
+            .collect::<Vec<_>>();
+
+        parts.join(", ")
+    }
+}
+
+fn kind(media: &MediaData) -> Option<&'static str> {
+    match media {
+        MediaData::ShowEpisode { .. } | MediaData::ShowSpecial { .. } => Some("episodes"),
+        MediaData::ShowMovie { .. } | MediaData::Movie { .. } | MediaData::MoviePart { .. } | MediaData::Performance { .. } => Some("movies"),
+        MediaData::SportsEvent { .. } => Some("events"),
+        MediaData::HomeVideo { .. } => Some("home videos"),
+        MediaData::Photo { .. } => Some("photos"),
+        MediaData::Book { .. } => Some("books"),
+        MediaData::Comic { .. } => Some("comics"),
+        MediaData::Garbage => None,
+    }
+}
+
+/// The top-level entry of `incoming` a file is in.
+pub fn entry(incoming: &std::path::Path, path: &std::path::Path) -> Option<String> {
+    let relative = path.strip_prefix(incoming).ok()?;
+
+    Some(relative.iter().next()?.to_string_lossy().to_string())
+}
+
+/// The entries something was linked from this run, `added` being those files, by name.
+pub fn imported(incoming: &std::path::Path, files: &[ScannedFile], added: &[&ScannedFile]) -> Vec<Import> {
+    let mut imports = BTreeMap::<String, Import>::new();
+
+    for file in added {
+        let (Some(name), Some(kind)) = (entry(incoming, &file.path), file.metadata.as_ref().and_then(kind)) else { continue; };
+        let import = imports.entry(name.clone()).or_insert_with(|| Import { name, linked: BTreeMap::new(), subtitles: 0, missing: vec![] });
+        *import.linked.entry(kind).or_default() += 1;
+    }
+
+    for file in files {
+        let Some(import) = entry(incoming, &file.path).and_then(|name| imports.get_mut(&name)) else { continue; };
+
+        let extension = crate::analyzer::extension(&file.path);
+        if extension.as_deref().is_some_and(|e| crate::subtitles::EXTENSIONS.contains(&e)) {
+            import.subtitles += 1;
+        } else if crate::analyzer::is_video(&file.path) && file.metadata.is_none() {
+            import.missing.push(file.path.clone());
+        }
+    }
+
+    imports.into_values().collect()
+}
@@ -0,0 +1,32 @@
+//! Ctrl-C (and `SIGTERM`) while the library is being changed: the operation under way finishes,
+//! no new ones start, and what was done gets recorded before exiting. A second one exits right
+//! away, like it would without harvester catching it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle(signal: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+    unsafe { libc::signal(signal, libc::SIG_DFL) };
+}
+
+/// Starts catching them, forgetting earlier ones.
+pub fn catch() {
+    REQUESTED.store(false, Ordering::SeqCst);
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe { libc::signal(signal, handle as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+    }
+}
+
+/// Back to exiting on them.
+pub fn release() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+/// Whether one came since `catch`.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
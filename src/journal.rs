@@ -0,0 +1,157 @@
+//! The plan a run is applying and which of its operations are done, so `harvester resume` can
+//! finish an interrupted run without scanning and planning again, which could come up with
+//! something different from what's half applied.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+use crate::plan::Plan;
+use crate::Runner;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    pub started_at: u64,
+    /// With absolute paths, resuming may happen from another directory.
+    pub plan: Plan,
+    /// What the state records once it's all done: source, link and source inode.
+    pub links: Vec<(std::path::PathBuf, std::path::PathBuf, u64)>,
+}
+
+/// Next to the state.
+pub fn path(state_path: &std::path::Path) -> std::path::PathBuf {
+    state_path.with_file_name("journal.json")
+}
+
+/// One path per line, each the JSON string of a path something was done to.
+fn log_path(path: &std::path::Path) -> std::path::PathBuf {
+    path.with_extension("done")
+}
+
+fn absolute(path: &std::path::Path) -> std::path::PathBuf {
+    std::path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+impl Journal {
+    pub fn new(plan: &Plan, links: Vec<(std::path::PathBuf, std::path::PathBuf, u64)>, now: u64) -> Self {
+        Self { started_at: now, plan: plan.absolute(), links }
+    }
+
+    /// Writes it, with nothing done yet. Replaces the journal of an earlier run.
+    pub fn begin(&self, path: &std::path::Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("unable to create {:?}: {}", parent, e))?;
+        }
+
+        let tmp = path.with_extension("json.tmp");
+        let contents = serde_json::to_string(self).unwrap();
+        std::fs::write(&tmp, contents).map_err(|e| format!("unable to write journal {:?}: {}", tmp, e))?;
+        std::fs::write(log_path(path), "").map_err(|e| format!("unable to write journal {:?}: {}", log_path(path), e))?;
+        std::fs::rename(&tmp, path).map_err(|e| format!("unable to write journal {:?}: {}", path, e))
+    }
+
+    /// The journal of an unfinished run, if there's one, and the paths of what it did.
+    pub fn load(path: &std::path::Path) -> Result<Option<(Self, BTreeSet<std::path::PathBuf>)>, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("unable to read journal {:?}: {}", path, e)),
+        };
+        let journal = serde_json::from_str(&contents).map_err(|e| format!("invalid journal {:?}: {}", path, e))?;
+
+        // A line cut short by the interruption is an operation that may not have finished.
+        let log = std::fs::read_to_string(log_path(path)).unwrap_or_default();
+        let done = log.lines().filter_map(|l| serde_json::from_str(l).ok()).collect();
+
+        Ok(Some((journal, done)))
+    }
+
+    /// What's left of the plan. Extra files are left out, they were reported already.
+    pub fn remaining(&self, done: &BTreeSet<std::path::PathBuf>) -> Plan {
+        Plan {
+            deletes: self.plan.deletes.iter().filter(|d| !done.contains(*d)).cloned().collect(),
+            extra: vec![],
+            creates: self.plan.creates.iter().filter(|c| !done.contains(&c.link)).cloned().collect(),
+            prunes: self.plan.prunes.iter().filter(|p| !done.contains(*p)).cloned().collect(),
+        }
+    }
+
+    /// Removes it once there's nothing left to resume.
+    pub fn finish(path: &std::path::Path) -> Result<(), String> {
+        for path in [log_path(path), path.to_path_buf()] {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(format!("unable to remove journal {:?}: {}", path, e)),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends each path `base` changed to the journal's log, once it's changed.
+pub struct JournalRunner<'a> {
+    base: &'a dyn Runner,
+    log: RefCell<std::fs::File>,
+}
+
+impl<'a> JournalRunner<'a> {
+    pub fn open(base: &'a dyn Runner, path: &std::path::Path) -> Result<Self, String> {
+        let log = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(log_path(path))
+            .map_err(|e| format!("unable to open journal {:?}: {}", log_path(path), e))?;
+
+        Ok(Self { base, log: RefCell::new(log) })
+    }
+
+    fn done(&self, path: &std::path::Path, result: std::io::Result<()>) -> std::io::Result<()> {
+        if result.is_ok() {
+            let line = format!("{}\n", serde_json::to_string(&absolute(path)).unwrap());
+            if let Err(e) = self.log.borrow_mut().write_all(line.as_bytes()) {
+                eprintln!("unable to write journal: {}", e);
+            }
+        }
+
+        result
+    }
+}
+
+impl Runner for JournalRunner<'_> {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.done(path, self.base.remove_dir(path))
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.done(path, self.base.remove_file(path))
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.base.create_dir_all(path)
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.done(link, self.base.hard_link(original, link))
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        self.done(path, self.base.write_file(path, contents))
+    }
+
+    fn exists(&self, path: &std::path::Path) -> bool {
+        self.base.exists(path)
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        self.base.is_dir(path)
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        self.base.list_dir(path)
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        self.base.inode(path)
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        self.base.read_to_string(path)
+    }
+    fn sync(&self) {
+        self.base.sync();
+    }
+}
@@ -0,0 +1,1089 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::os::unix::fs::MetadataExt;
+
+pub mod analyzer;
+pub mod anilist;
+pub mod arr;
+pub mod backup;
+pub mod books;
+pub mod bundle;
+pub mod camera;
+pub mod completion;
+pub mod config;
+pub mod container;
+pub mod daemon;
+pub mod diff;
+pub mod doctor;
+pub mod du;
+pub mod hooks;
+pub mod imports;
+pub mod interrupt;
+pub mod journal;
+pub mod ls;
+pub mod lru;
+pub mod memfs;
+pub mod plan;
+#[cfg(feature = "mediainfo")]
+pub mod mediainfo;
+pub mod mounts;
+pub mod output;
+pub mod overrides;
+pub mod remote;
+pub mod replay;
+pub mod report;
+pub mod retire;
+pub mod scene;
+pub mod schedule;
+pub mod state;
+pub mod store;
+pub mod subtitles;
+#[cfg(feature = "bench")]
+pub mod synthetic;
+pub mod template;
+pub mod timings;
+pub mod tmdb;
+pub mod torrent;
+pub mod tracker;
+pub mod verify;
+
+#[cfg(test)]
+mod test;
+
+pub use analyzer::{Analyzer, MediaData, Rule, ScannedFile};
+
+use config::{Config, LayoutProfile, LinkMode, MovieLayout, ShowMoviePolicy, VersionPolicy};
+use tmdb::Tmdb;
+use torrent::SeedingIndex;
+
+
+/// Metadata NAS and desktop indexers leave around, skipped along with dotfiles.
+pub const IGNORED: [&str; 8] = [
+    "Thumbs.db", "desktop.ini", "@eaDir", "#recycle", "#snapshot", "$RECYCLE.BIN", "System Volume Information", "lost+found",
+];
+
+/// Whether walking a directory skips `path`: dotfiles other than harvester's own, `IGNORED`, and
+/// the config's `ignore` names in `extra`.
+pub fn is_ignored(path: &std::path::Path, extra: &[String]) -> bool {
+    let Some(name) = path.file_name().and_then(std::ffi::OsStr::to_str) else { return false; };
+
+    (name.starts_with('.') && name != overrides::FILENAME && name != plan::SENTINEL)
+        || IGNORED.contains(&name)
+        || extra.iter().any(|e| e == name)
+}
+
+pub fn find_all_files_aux(path: &std::path::Path, ignore: &[String], output: &mut Vec<std::path::PathBuf>) {
+    if path.is_dir() {
+        // Gone since it was listed, like files removed mid-scan.
+        let Ok(entries) = path.read_dir() else { return; };
+        for subdir in entries.filter_map(Result::ok) {
+            let subdir = subdir.path();
+            if !is_ignored(&subdir, ignore) {
+                find_all_files_aux(&subdir, ignore, output);
+            }
+        }
+    } else {
+        output.push(path.to_path_buf());
+    }
+}
+pub fn find_all_files(path: &std::path::Path) -> Vec<std::path::PathBuf> {
+    find_files(path, &[])
+}
+/// `find_all_files`, skipping `ignore` names too.
+pub fn find_files(path: &std::path::Path, ignore: &[String]) -> Vec<std::path::PathBuf> {
+    let mut files = vec![];
+    find_all_files_aux(path, ignore, &mut files);
+    files
+}
+
+/// `find_all_files` on the filesystem as the runner sees it.
+pub fn find_all_files_in(runner: &dyn Runner, path: &std::path::Path, ignore: &[String]) -> Vec<std::path::PathBuf> {
+    if runner.is_dir(path) {
+        runner.list_dir(path).iter().filter(|p| !is_ignored(p, ignore)).flat_map(|p| find_all_files_in(runner, p, ignore)).collect()
+    } else {
+        vec![path.to_path_buf()]
+    }
+}
+
+pub trait Runner {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()>;
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()>;
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()>;
+    fn hard_link(&self, path: &std::path::Path, link: &std::path::Path) -> std::io::Result<()>;
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()>;
+
+    /// The filesystem as it would look after the operations above.
+    fn exists(&self, path: &std::path::Path) -> bool {
+        path.exists()
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        path.is_dir()
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        path.read_dir().unwrap().map(|entry| entry.unwrap().path()).collect()
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        std::fs::metadata(path).ok().map(|m| m.ino())
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    /// Makes the changes so far durable, for runners that keep track of them.
+    fn sync(&self) {}
+}
+
+/// Transient errors are retried, see `mounts::retry`.
+pub struct RealRunner {}
+impl Runner for RealRunner {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        mounts::retry(|| std::fs::remove_dir(path))
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        mounts::retry(|| std::fs::remove_file(path))
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        mounts::retry(|| std::fs::create_dir_all(path))
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        mounts::retry(|| std::fs::hard_link(original, link))
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        mounts::retry(|| std::fs::write(path, contents))
+    }
+}
+
+/// `RealRunner`, fsyncing the directories it changed on `sync` so their entries survive a power
+/// loss, and writing files through a synced temporary one renamed into place.
+#[derive(Default)]
+pub struct DurableRunner {
+    touched: RefCell<BTreeSet<std::path::PathBuf>>,
+}
+
+impl DurableRunner {
+    fn touch(&self, path: &std::path::Path) {
+        if let Some(parent) = path.parent() {
+            self.touched.borrow_mut().insert(parent.to_path_buf());
+        }
+    }
+
+    /// `renameat2` with `RENAME_NOREPLACE` where the kernel and filesystem have it, so nothing that
+    /// showed up in the meantime gets clobbered silently. Replacing is what `write_file` does
+    /// otherwise, so existing files fall back to a plain rename.
+    fn rename(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let (c_from, c_to) = (std::ffi::CString::new(from.as_os_str().as_bytes())?, std::ffi::CString::new(to.as_os_str().as_bytes())?);
+        let result = unsafe { libc::renameat2(libc::AT_FDCWD, c_from.as_ptr(), libc::AT_FDCWD, c_to.as_ptr(), libc::RENAME_NOREPLACE) };
+        let error = std::io::Error::last_os_error();
+        match (result, error.raw_os_error()) {
+            (0, _) => Ok(()),
+            (_, Some(libc::EEXIST | libc::ENOSYS | libc::EINVAL)) => std::fs::rename(from, to),
+            _ => Err(error),
+        }
+    }
+
+    fn write(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let temporary = path.with_file_name(format!(".{}.harvester-tmp", path.file_name().unwrap().to_string_lossy()));
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        Self::rename(&temporary, path).inspect_err(|_| { let _ = std::fs::remove_file(&temporary); })
+    }
+}
+
+impl Runner for DurableRunner {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        RealRunner {}.remove_dir(path)?;
+        self.touch(path);
+        Ok(())
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        RealRunner {}.remove_file(path)?;
+        self.touch(path);
+        Ok(())
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let missing = path.ancestors().take_while(|a| !a.exists()).map(|a| a.to_path_buf()).collect::<Vec<_>>();
+        // Some of them may have been made before it failed.
+        let result = RealRunner {}.create_dir_all(path);
+        missing.iter().filter(|dir| dir.exists()).for_each(|dir| self.touch(dir));
+        result
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        RealRunner {}.hard_link(original, link)?;
+        self.touch(link);
+        Ok(())
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        mounts::retry(|| Self::write(path, contents))?;
+        self.touch(path);
+        Ok(())
+    }
+
+    fn sync(&self) {
+        for dir in std::mem::take(&mut *self.touched.borrow_mut()) {
+            // Gone already when a later step pruned it, its parent is in the set then.
+            if let Ok(file) = std::fs::File::open(&dir) {
+                if let Err(e) = file.sync_all() {
+                    eprintln!("unable to sync {:?}: {}", dir, e);
+                }
+            }
+        }
+    }
+}
+
+/// Refuses anything that would change `protected`, as permission denied errors, for incoming
+/// directories that must stay as the torrent client left them.
+pub struct ReadOnlyRunner {
+    base: Box<dyn Runner>,
+    protected: std::path::PathBuf,
+}
+
+impl ReadOnlyRunner {
+    pub fn new(base: Box<dyn Runner>, protected: &std::path::Path) -> Self {
+        Self { base, protected: std::path::absolute(protected).unwrap_or(protected.to_path_buf()) }
+    }
+
+    fn check(&self, operation: &str, path: &std::path::Path) -> std::io::Result<()> {
+        let absolute = std::path::absolute(path).unwrap_or(path.to_path_buf());
+        if absolute.starts_with(&self.protected) {
+            let message = format!("refusing to {} {:?}, {:?} is read-only", operation, path, self.protected);
+            return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, message));
+        }
+
+        Ok(())
+    }
+}
+
+impl Runner for ReadOnlyRunner {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.check("remove", path)?;
+        self.base.remove_dir(path)
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.check("remove", path)?;
+        self.base.remove_file(path)
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.check("create", path)?;
+        self.base.create_dir_all(path)
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.check("create", link)?;
+        self.base.hard_link(original, link)
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        self.check("write", path)?;
+        self.base.write_file(path, contents)
+    }
+
+    fn exists(&self, path: &std::path::Path) -> bool {
+        self.base.exists(path)
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        self.base.is_dir(path)
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        self.base.list_dir(path)
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        self.base.inode(path)
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        self.base.read_to_string(path)
+    }
+    fn sync(&self) {
+        self.base.sync();
+    }
+}
+
+/// Leaves the filesystem alone, keeping track of what would have changed so later steps see the
+/// same tree a real run would.
+pub struct DryRunner<'a> {
+    /// The filesystem the changes are simulated on top of.
+    base: &'a dyn Runner,
+    removed: RefCell<BTreeSet<std::path::PathBuf>>,
+    dirs: RefCell<BTreeSet<std::path::PathBuf>>,
+    files: RefCell<BTreeSet<std::path::PathBuf>>,
+}
+
+impl Default for DryRunner<'static> {
+    fn default() -> Self {
+        Self::over(&RealRunner {})
+    }
+}
+
+impl<'a> DryRunner<'a> {
+    pub fn over(base: &'a dyn Runner) -> Self {
+        Self { base, removed: Default::default(), dirs: Default::default(), files: Default::default() }
+    }
+
+    fn create(&self, path: &std::path::Path, dir: bool) {
+        self.removed.borrow_mut().remove(path);
+        if dir {
+            self.dirs.borrow_mut().insert(path.to_path_buf());
+        } else {
+            self.files.borrow_mut().insert(path.to_path_buf());
+        }
+    }
+
+    fn remove(&self, path: &std::path::Path) {
+        self.removed.borrow_mut().insert(path.to_path_buf());
+        self.dirs.borrow_mut().remove(path);
+        self.files.borrow_mut().remove(path);
+    }
+}
+
+/// Never fails, whatever the real operations would do.
+impl Runner for DryRunner<'_> {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.remove(path);
+        Ok(())
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.remove(path);
+        Ok(())
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        for dir in path.ancestors().filter(|d| !d.as_os_str().is_empty() && !self.is_dir(d)) {
+            self.create(dir, true);
+        }
+        Ok(())
+    }
+    fn hard_link(&self, _original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.create(link, false);
+        Ok(())
+    }
+    fn write_file(&self, path: &std::path::Path, _contents: &str) -> std::io::Result<()> {
+        self.create(path, false);
+        Ok(())
+    }
+
+    fn exists(&self, path: &std::path::Path) -> bool {
+        !self.removed.borrow().contains(path) && (self.dirs.borrow().contains(path) || self.files.borrow().contains(path) || self.base.exists(path))
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        !self.removed.borrow().contains(path) && (self.dirs.borrow().contains(path) || self.base.is_dir(path))
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let real = match self.base.is_dir(path) {
+            true => self.base.list_dir(path),
+            false => vec![],
+        };
+        let dirs = self.dirs.borrow();
+        let files = self.files.borrow();
+        let created = dirs.iter().chain(files.iter()).filter(|p| p.parent() == Some(path)).cloned();
+
+        let removed = self.removed.borrow();
+        real.into_iter()
+            .chain(created)
+            .filter(|p| !removed.contains(p))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        if self.removed.borrow().contains(path) { None } else { self.base.inode(path) }
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        if self.removed.borrow().contains(path) { None } else { self.base.read_to_string(path) }
+    }
+}
+
+pub struct Layout<'a> {
+    config: &'a Config,
+    tmdb: Option<Tmdb>,
+    anilist: Option<anilist::AniList>,
+    source_dir: std::path::PathBuf,
+    routes: Vec<(regex::Regex, LayoutProfile)>,
+    users: Vec<(regex::Regex, std::path::PathBuf)>,
+    overrides: overrides::Overrides,
+    aliases: std::collections::BTreeMap<String, String>,
+    renumbering: scene::SceneExceptions,
+    #[cfg(feature = "mediainfo")]
+    media_info: RefCell<std::collections::HashMap<std::path::PathBuf, Option<mediainfo::MediaInfo>>>,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(config: &'a Config, source_dir: &std::path::Path) -> Self {
+        let tmdb = config.tmdb.as_ref().map(|t| Tmdb::new(&t.api_key));
+        let anilist = config.anilist.as_ref().map(|a| anilist::AniList::new(a.language));
+        let routes = config.routes.iter().map(|r| (r.matcher(), r.layout)).collect();
+        let users = config.users.iter().map(|u| (u.matcher(), u.target.clone())).collect();
+
+        Self {
+            config,
+            tmdb,
+            anilist,
+            source_dir: source_dir.to_path_buf(),
+            routes,
+            users,
+            overrides: overrides::Overrides::default(),
+            aliases: std::collections::BTreeMap::new(),
+            renumbering: scene::SceneExceptions::default(),
+            #[cfg(feature = "mediainfo")]
+            media_info: RefCell::new(std::collections::HashMap::new()),
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: overrides::Overrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Shows renamed with `harvester rename-show`, see `State::aliases`.
+    pub fn with_aliases(mut self, aliases: std::collections::BTreeMap<String, String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Episodes moved with `harvester move-episodes`, see `State::renumbering`.
+    pub fn with_renumbering(mut self, renumbering: scene::SceneExceptions) -> Self {
+        self.renumbering = renumbering;
+        self
+    }
+
+    /// What TMDB answered on earlier runs, see `State::tmdb`.
+    pub fn with_tmdb_lookups(mut self, lookups: tmdb::Lookups) -> Self {
+        self.tmdb = self.tmdb.map(|t| t.with_lookups(lookups));
+        self
+    }
+
+    /// Including this run's, to be kept for the next one. None when TMDB isn't configured.
+    pub fn tmdb_lookups(&self) -> Option<tmdb::Lookups> {
+        self.tmdb.as_ref().map(Tmdb::lookups)
+    }
+
+    /// What AniList answered on earlier runs, see `State::anilist`.
+    pub fn with_anilist_titles(mut self, titles: std::collections::BTreeMap<String, Option<anilist::Titles>>) -> Self {
+        self.anilist = self.anilist.map(|a| a.with_titles(titles));
+        self
+    }
+
+    /// Like `tmdb_lookups`.
+    pub fn anilist_titles(&self) -> Option<std::collections::BTreeMap<String, Option<anilist::Titles>>> {
+        self.anilist.as_ref().map(anilist::AniList::titles)
+    }
+
+    /// Lookups that failed so far, with nothing to fall back on. Links made anyway could be named
+    /// differently next time, so runs stop before changing anything then.
+    pub fn lookup_errors(&self) -> Vec<String> {
+        let tmdb = self.tmdb.as_ref().map(Tmdb::errors).unwrap_or_default();
+        let anilist = self.anilist.as_ref().map(anilist::AniList::errors).unwrap_or_default();
+
+        tmdb.into_iter().chain(anilist).collect()
+    }
+
+    pub fn profile(&self, file: &ScannedFile) -> LayoutProfile {
+        let show = match &file.metadata {
+            Some(MediaData::ShowEpisode { name, .. } | MediaData::ShowSpecial { name, .. } | MediaData::ShowMovie { name, .. }) => self.config.show(name),
+            _ => None,
+        };
+        if let Some(profile) = show.and_then(|s| s.layout) {
+            return profile;
+        }
+        if let Some(profile) = self.overrides.layout(&file.path) {
+            return profile;
+        }
+
+        let relative = file.path.strip_prefix(&self.source_dir).unwrap_or(&file.path);
+        let relative = relative.to_string_lossy();
+
+        self.routes
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(&relative))
+            .map_or(self.config.layout, |(_, profile)| *profile)
+    }
+
+    pub fn collection(&self, title: &str, year: Option<u32>) -> Option<String> {
+        if let Some(name) = self.config.local_collection(title) {
+            return Some(name.to_string());
+        }
+
+        match (&self.tmdb, &self.config.tmdb) {
+            (Some(tmdb), Some(c)) if c.collections => tmdb.movie_collection(title, year),
+            _ => None,
+        }
+    }
+
+    fn lookup_ids(&self) -> Option<&Tmdb> {
+        match (&self.tmdb, &self.config.tmdb) {
+            (Some(tmdb), Some(c)) if c.ids => Some(tmdb),
+            _ => None,
+        }
+    }
+
+    /// The name shows are filed under: the one they were renamed to, or for anime the AniList title
+    /// when it's configured, the parsed one otherwise. Per-show settings still go by the parsed name.
+    fn display_name(&self, name: &str, profile: LayoutProfile) -> String {
+        if let Some((_, alias)) = self.aliases.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            return alias.clone();
+        }
+
+        match (&self.anilist, profile) {
+            (Some(anilist), LayoutProfile::Anime) => anilist.title(name).unwrap_or(name.to_string()),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn show_folder(&self, name: &str) -> String {
+        let folder = self.config.templates.show_folder.render(&|v| match v {
+            "name" => Some(name.to_string()),
+            _ => None,
+        });
+
+        match self.lookup_ids().and_then(|tmdb| tmdb.show_id(name)) {
+            Some(id) => format!("{} {}", folder, id),
+            None => folder,
+        }
+    }
+
+    fn movie_vars(title: &str, year: Option<u32>) -> impl Fn(&str) -> Option<String> + '_ {
+        move |v| match v {
+            "title" => Some(title.to_string()),
+            "year" => year.map(|y| y.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn movie_folder(&self, title: &str, year: Option<u32>) -> String {
+        self.named_movie_folder(title, title, year)
+    }
+
+    /// `movie_folder` named `name`, with the id of the movie TMDB knows as `title`.
+    fn named_movie_folder(&self, name: &str, title: &str, year: Option<u32>) -> String {
+        let folder = self.config.templates.movie_folder.render(&Self::movie_vars(name, year));
+
+        match self.lookup_ids().and_then(|tmdb| tmdb.movie_id(title, year)) {
+            Some(id) => format!("{} [tmdbid-{}]", folder, id),
+            None => folder,
+        }
+    }
+
+    /// Relative library paths are resolved against the target directory.
+    fn show_library(&self, target_dir: &std::path::Path, name: &str) -> std::path::PathBuf {
+        let library = self.config.show(name).and_then(|s| s.library.as_ref()).unwrap_or(&self.config.libraries.shows);
+        target_dir.join(library)
+    }
+
+    fn movie_library(&self, target_dir: &std::path::Path) -> std::path::PathBuf {
+        target_dir.join(&self.config.libraries.movies)
+    }
+
+    /// Template variables probed from the file itself, only when a template uses them.
+    #[cfg(feature = "mediainfo")]
+    fn media_variable(&self, file: &ScannedFile, name: &str) -> Option<String> {
+        let mut cache = self.media_info.borrow_mut();
+        let info = cache.entry(file.path.clone()).or_insert_with(|| {
+            mediainfo::MediaInfo::probe(&file.path).map_err(|e| eprintln!("{}", e)).ok()
+        });
+
+        info.as_ref()?.variable(name)
+    }
+
+    #[cfg(not(feature = "mediainfo"))]
+    fn media_variable(&self, _file: &ScannedFile, _name: &str) -> Option<String> {
+        None
+    }
+
+    /// What a file is linked with, after `naming.extensions` and `naming.lowercase_extensions`.
+    pub fn extension(&self, file: &ScannedFile) -> String {
+        let extension = file.path.extension().unwrap().to_string_lossy();
+        let naming = &self.config.naming;
+
+        match naming.extensions.iter().find(|(from, _)| from.eq_ignore_ascii_case(&extension)) {
+            Some((_, to)) => to.clone(),
+            None if naming.lowercase_extensions => extension.to_lowercase(),
+            None => extension.to_string(),
+        }
+    }
+
+    /// Variables of the templates naming a single file, besides the media data's.
+    fn file_variable(&self, file: &ScannedFile, name: &str) -> Option<String> {
+        match name {
+            "container" => Some(self.extension(file)),
+            name => self.media_variable(file, name),
+        }
+    }
+
+    fn movie_path(&self, file: &ScannedFile, movies: std::path::PathBuf, title: &str, year: Option<u32>, extension: &str) -> std::path::PathBuf {
+        self.named_movie_path(file, movies, title, title, year, extension)
+    }
+
+    /// `movie_path` named `name`, like `named_movie_folder`.
+    fn named_movie_path(&self, file: &ScannedFile, movies: std::path::PathBuf, name: &str, title: &str, year: Option<u32>, extension: &str) -> std::path::PathBuf {
+        let folder = self.named_movie_folder(name, title, year);
+
+        match self.config.movie_layout {
+            MovieLayout::Folders => {
+                let vars = Self::movie_vars(name, year);
+                let movie = self.config.templates.movie.render(&|v| vars(v).or_else(|| self.file_variable(file, v)));
+                movies.join(folder).join(format!("{}.{}", movie, extension))
+            }
+            MovieLayout::Flat => movies.join(format!("{}.{}", folder, extension)),
+        }
+    }
+
+    /// The target directory of the user whose part of the incoming directory the file is in.
+    fn user_target(&self, file: &ScannedFile, target_dir: &std::path::Path) -> std::path::PathBuf {
+        let relative = file.path.strip_prefix(&self.source_dir).unwrap_or(&file.path);
+        let relative = relative.to_string_lossy();
+
+        match self.users.iter().find(|(matcher, _)| matcher.is_match(&relative)) {
+            Some((_, target)) => target_dir.join(target),
+            None => target_dir.to_path_buf(),
+        }
+    }
+
+    /// Every library directory under the target and user target directories, and those themselves.
+    fn library_dirs(&self, target_dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let libraries = self.config.shows.values().filter_map(|s| s.library.as_ref());
+        let libraries = libraries
+            .chain([&self.config.libraries.shows, &self.config.libraries.movies, &self.config.libraries.sports])
+            .chain(self.config.libraries.performances.as_ref())
+            .chain(self.config.home_videos.then_some(&self.config.libraries.home_videos))
+            .chain(self.config.photos.then_some(&self.config.libraries.photos))
+            .chain(self.config.books.then_some(&self.config.libraries.books))
+            .chain(self.config.books.then_some(&self.config.libraries.comics))
+            .collect::<Vec<_>>();
+        let targets = [target_dir.to_path_buf()].into_iter().chain(self.users.iter().map(|(_, t)| target_dir.join(t)));
+
+        targets.flat_map(|target| [target.clone()].into_iter().chain(libraries.iter().map(move |l| target.join(l)))).collect()
+    }
+
+    pub fn roots(&self, target_dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut roots: Vec<std::path::PathBuf> = vec![];
+        for root in self.library_dirs(target_dir) {
+            if !roots.iter().any(|r| root.starts_with(r)) {
+                roots.push(root);
+            }
+        }
+
+        roots
+    }
+
+    fn special_path(&self, target_dir: &std::path::Path, name: &str, label: &str, group: &str, extension: &str, profile: LayoutProfile) -> Option<std::path::PathBuf> {
+        if self.config.show(name).is_some_and(|s| !s.specials) {
+            return None;
+        }
+
+        let display_name = self.display_name(name, profile);
+        let filename = match profile {
+            LayoutProfile::Default | LayoutProfile::Mirror => format!("{}.{}", label, extension),
+            LayoutProfile::Anime => format!("{} - {}{}.{}", display_name, label, group, extension),
+        };
+
+        Some(self
+            .show_library(target_dir, name)
+            .join(self.show_folder(&display_name))
+            .join("Specials")
+            .join(filename))
+    }
+
+    fn strm_prefix(&self) -> String {
+        match &self.config.strm_base {
+            Some(base) => base.trim_end_matches('/').to_string(),
+            None => std::fs::canonicalize(&self.source_dir)
+                .unwrap_or(self.source_dir.clone())
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// What a `.strm` file for this source should point at.
+    pub fn strm_target(&self, file: &ScannedFile) -> String {
+        let relative = file.path.strip_prefix(&self.source_dir).unwrap_or(&file.path);
+        let components = relative.iter().map(|c| c.to_string_lossy()).collect::<Vec<_>>();
+
+        let components = match &self.config.strm_base {
+            Some(base) if base.starts_with("http://") || base.starts_with("https://") => {
+                components.iter().map(|c| percent_encode(c)).collect()
+            }
+            _ => components.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+        };
+
+        format!("{}/{}", self.strm_prefix(), components.join("/"))
+    }
+
+    pub fn owns_strm(&self, contents: &str) -> bool {
+        contents.starts_with(&format!("{}/", self.strm_prefix()))
+    }
+
+    /// The incoming path with cleaned up names, relative to the target directory.
+    fn mirror_path(&self, file: &ScannedFile, target_dir: &std::path::Path, extension: &str) -> std::path::PathBuf {
+        let relative = file.path.strip_prefix(&self.source_dir).unwrap_or(&file.path);
+        let mut path = target_dir.to_path_buf();
+
+        if let Some(parent) = relative.parent() {
+            for component in parent.iter() {
+                path.push(clean_name(&component.to_string_lossy()));
+            }
+        }
+
+        let stem = clean_name(&file.path.file_stem().unwrap().to_string_lossy());
+        path.join(format!("{}.{}", stem, extension))
+    }
+
+    /// Library numbering for a parsed episode: scene exceptions, then the show's offsets, then
+    /// episodes moved since.
+    pub fn episode_number(&self, name: &str, season: u32, episode: u32) -> (u32, u32) {
+        let (season, episode) = self.config.scene.remap(name, season, episode);
+        let show = self.config.show(name);
+        let season = season.saturating_add_signed(show.map_or(0, |s| s.season_offset));
+        let (season, episode) = scene::Offset::apply_all(show.map_or(&[], |s| &s.offsets), season, episode);
+
+        self.renumbering.remap(name, season, episode)
+    }
+
+    /// Configured as such, or with a single season on TMDB when `flatten_single_season` is set.
+    /// A show's first season on disk doesn't count, it might still be airing.
+    pub fn is_mini_series(&self, name: &str) -> bool {
+        if self.config.show(name).is_some_and(|s| s.mini_series) {
+            return true;
+        }
+
+        self.config.flatten_single_season
+            && self.season_episode_counts(name).is_some_and(|counts| counts.keys().filter(|s| **s != 0).count() == 1)
+    }
+
+    /// From TMDB, when it's configured.
+    pub fn season_episode_counts(&self, name: &str) -> Option<std::collections::BTreeMap<u32, u32>> {
+        self.tmdb.as_ref()?.season_episode_counts(name)
+    }
+
+    pub fn is_blocked(&self, file: &ScannedFile) -> bool {
+        let relative = file.path.strip_prefix(&self.source_dir).unwrap_or(&file.path);
+        self.config.blocklist.blocks(file.metadata.as_ref(), relative)
+    }
+
+    pub fn link_path(&self, file: &ScannedFile, target_dir: &std::path::Path) -> Option<std::path::PathBuf> {
+        let link = self.unbounded_link_path(file, target_dir)?;
+
+        self.fit(link, target_dir)
+    }
+
+    /// The link shortened to `naming.max_name_length` and `naming.max_path_length`, cutting the
+    /// names below its library (the longest first for the path) and keeping the extension. Always
+    /// the same cut for the same link, so runs agree on it. None when it can't be made to fit.
+    fn fit(&self, link: std::path::PathBuf, target_dir: &std::path::Path) -> Option<std::path::PathBuf> {
+        let naming = &self.config.naming;
+        if naming.max_name_length.is_none() && naming.max_path_length.is_none() {
+            return Some(link);
+        }
+
+        let root = self.library_dirs(target_dir).into_iter().filter(|l| link.starts_with(l)).max_by_key(|l| l.components().count())?;
+        let extension = link.extension().map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+        let mut names = link.strip_prefix(&root).unwrap().iter().map(|n| n.to_string_lossy().to_string()).collect::<Vec<_>>();
+        let last = names.len().checked_sub(1)?;
+        let stem = names[last].len() - extension.len();
+        names[last].truncate(stem);
+
+        let budget = |i: usize| if i == last { extension.len() } else { 0 };
+        if let Some(max) = naming.max_name_length {
+            for (i, name) in names.iter_mut().enumerate() {
+                *name = template::truncate(name, max.checked_sub(budget(i))?);
+            }
+        }
+
+        if let Some(max) = naming.max_path_length {
+            let length = |names: &[String]| root.as_os_str().len() + names.iter().map(|n| n.len() + 1).sum::<usize>() + extension.len();
+            while length(&names) > max {
+                let (i, longest) = names.iter().enumerate().max_by_key(|(_, n)| n.len())?;
+                let cut = longest.len().saturating_sub(length(&names) - max).max(1);
+                if cut >= longest.len() {
+                    return None;
+                }
+                names[i] = template::truncate(&names[i], cut);
+            }
+        }
+
+        if names.iter().any(|n| n.is_empty()) {
+            return None;
+        }
+        names[last].push_str(&extension);
+
+        Some(names.iter().fold(root, |path, name| path.join(name)))
+    }
+
+    fn unbounded_link_path(&self, file: &ScannedFile, target_dir: &std::path::Path) -> Option<std::path::PathBuf> {
+        if self.is_blocked(file) || file.metadata.as_ref().is_some_and(MediaData::is_untitled) {
+            return None;
+        }
+        let target_dir = &self.user_target(file, target_dir);
+
+        let extension = match self.config.link_mode {
+            LinkMode::Hardlink => &self.extension(file),
+            LinkMode::Strm => "strm",
+        };
+        let profile = self.profile(file);
+        if profile == LayoutProfile::Mirror {
+            return match file.metadata {
+                None | Some(MediaData::Garbage) => None,
+                Some(_) => Some(self.mirror_path(file, target_dir, extension)),
+            };
+        }
+
+        let group = match (&file.group, profile) {
+            (Some(group), LayoutProfile::Anime) => format!(" [{}]", group),
+            _ => String::new(),
+        };
+
+        match &file.metadata {
+            Some(MediaData::ShowEpisode { name, season, episode }) => {
+                let last_episode = file.last_episode.map(|last| self.episode_number(name, *season, last).1);
+                let (season, episode) = self.episode_number(name, *season, *episode);
+                let show = self.config.show(name);
+                let display_name = self.display_name(name, profile);
+                let vars = |v: &str| match v {
+                    "name" => Some(display_name.clone()),
+                    "season" => Some(season.to_string()),
+                    "episode" => Some(episode.to_string()),
+                    "last_episode" => last_episode.map(|e| e.to_string()),
+                    "group" => file.group.clone(),
+                    v => self.file_variable(file, v),
+                };
+                let filename = match (profile, last_episode) {
+                    (LayoutProfile::Default | LayoutProfile::Mirror, last_episode) => {
+                        let template = show.and_then(|s| s.episode_template.as_ref()).unwrap_or(&self.config.templates.episode);
+                        match last_episode {
+                            // Jellyfin's multi-episode naming, templates without `{last_episode}` can't express it.
+                            Some(last) if !template.uses("last_episode") => {
+                                format!("S{:02}E{:02}-E{:02}.{}", season, episode, last, extension)
+                            }
+                            _ => format!("{}.{}", template.render(&vars), extension),
+                        }
+                    },
+                    (LayoutProfile::Anime, Some(last)) => format!("{} - {:02}-{:02}{}.{}", display_name, episode, last, group, extension),
+                    (LayoutProfile::Anime, None) => format!("{} - {:02}{}.{}", display_name, episode, group, extension),
+                };
+
+                let show_folder = self.show_library(target_dir, name).join(self.show_folder(&display_name));
+                match self.is_mini_series(name) {
+                    true => Some(show_folder.join(filename)),
+                    false => Some(show_folder.join(self.config.templates.season_folder.render(&vars)).join(filename)),
+                }
+            },
+            Some(MediaData::ShowSpecial { name, label }) => {
+                self.special_path(target_dir, name, label, &group, extension, profile)
+            },
+            Some(MediaData::ShowMovie { name, title, year }) => {
+                let label = match year {
+                    Some(y) => format!("{} ({})", title, y),
+                    None => title.to_string(),
+                };
+
+                match self.overrides.show_movies(&file.path).unwrap_or(self.config.show_movies) {
+                    ShowMoviePolicy::Specials => {
+                        self.special_path(target_dir, name, &label, &group, extension, profile)
+                    },
+                    ShowMoviePolicy::Movies => {
+                        Some(self.movie_path(file, self.movie_library(target_dir), &format!("{} {}", name, title), *year, extension))
+                    },
+                }
+            },
+            Some(MediaData::Movie { title, year }) => {
+                let mut movies = self.movie_library(target_dir);
+                if let Some(collection) = self.collection(title, *year) {
+                    movies = movies.join(collection);
+                }
+
+                Some(self.movie_path(file, movies, title, *year, extension))
+            },
+            Some(MediaData::MoviePart { title, year, part }) => {
+                let mut movies = self.movie_library(target_dir);
+                if let Some(collection) = self.collection(title, *year) {
+                    movies = movies.join(collection);
+                }
+
+                // Jellyfin stacks `<movie> - part1`, `<movie> - part2` as one.
+                let movie = self.movie_path(file, movies, title, *year, extension);
+                let stem = movie.file_stem().unwrap().to_string_lossy();
+                Some(movie.with_file_name(format!("{} - part{}.{}", stem, part, extension)))
+            },
+            // Named after the performer too, but grouped and looked up like the movie TMDB knows.
+            Some(MediaData::Performance { performer, title, year }) => {
+                let mut library = self.config.libraries.performances.as_ref().map_or(self.movie_library(target_dir), |l| target_dir.join(l));
+                if let Some(collection) = self.collection(title, *year) {
+                    library = library.join(collection);
+                }
+
+                Some(self.named_movie_path(file, library, &format!("{} - {}", performer, title), title, *year, extension))
+            },
+            Some(MediaData::HomeVideo { year, month, .. } | MediaData::Photo { year, month, .. }) => {
+                let library = match file.metadata {
+                    Some(MediaData::Photo { .. }) => &self.config.libraries.photos,
+                    _ => &self.config.libraries.home_videos,
+                };
+                let stem = file.path.file_stem().unwrap().to_string_lossy();
+                Some(target_dir
+                    .join(library)
+                    .join(year.to_string())
+                    .join(format!("{:02}", month))
+                    .join(format!("{}.{}", stem, extension)))
+            },
+            Some(media @ (MediaData::Book { .. } | MediaData::Comic { .. })) => {
+                let library = match media {
+                    MediaData::Book { .. } => &self.config.libraries.books,
+                    _ => &self.config.libraries.comics,
+                };
+                Some(target_dir.join(library).join(books::link_path(media, extension)?))
+            },
+            Some(MediaData::SportsEvent { league, season, event }) => {
+                let folder = target_dir.join(&self.config.libraries.sports).join(league);
+                Some(match season {
+                    Some(season) => folder.join(season.to_string()).join(format!("{} {} {}.{}", league, season, event, extension)),
+                    None => folder.join(format!("{} {}.{}", league, event, extension)),
+                })
+            },
+            _ => None,
+        }
+    }
+
+    pub fn link_paths<'f>(&self, files: &'f [ScannedFile], target_dir: &std::path::Path) -> Vec<(&'f ScannedFile, std::path::PathBuf)> {
+        // In the order they first come up, with an index to find them by.
+        let mut by_link: Vec<(std::path::PathBuf, Vec<&ScannedFile>)> = vec![];
+        let mut index = std::collections::HashMap::<std::path::PathBuf, usize>::new();
+        for file in files.iter() {
+            let Some(link) = self.link_path(file, target_dir) else { continue; };
+
+            match index.get(&link) {
+                Some(&i) => by_link[i].1.push(file),
+                None => {
+                    index.insert(link.clone(), by_link.len());
+                    by_link.push((link, vec![file]));
+                }
+            }
+        }
+
+        let mut links = vec![];
+        for (link, versions) in by_link {
+            if versions.len() == 1 || self.config.versions == VersionPolicy::FirstWins {
+                links.push((versions[0], link));
+                continue;
+            }
+
+            let mut labels: Vec<String> = vec![];
+            for (i, file) in versions.iter().enumerate() {
+                let mut label = file
+                    .version
+                    .clone()
+                    .or_else(|| file.group.clone())
+                    .unwrap_or_else(|| format!("version {}", i + 1));
+
+                if labels.contains(&label) {
+                    label = format!("{} {}", label, i + 1);
+                }
+
+                labels.push(label.clone());
+                links.push((*file, versioned(&link, &label)));
+            }
+        }
+
+        links
+    }
+}
+
+/// Drops bracketed tags and turns `.` and `_` separators into spaces, keeping the case.
+fn clean_name(name: &str) -> String {
+    static TAGS: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| regex::Regex::new(r"\[[^]]*\]").unwrap());
+    let name = TAGS.replace_all(name, " ").replace(['.', '_'], " ");
+
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Where to link a file identical to the one linked at `original`, as another version of it.
+pub fn version_link(file: &ScannedFile, original: &std::path::Path) -> std::path::PathBuf {
+    let label = file.version.clone().or_else(|| file.group.clone()).unwrap_or_else(|| "copy".to_string());
+    versioned(original, &label)
+}
+
+fn versioned(link: &std::path::Path, label: &str) -> std::path::PathBuf {
+    let stem = link.file_stem().unwrap().to_str().unwrap();
+    let extension = link.extension().unwrap().to_str().unwrap();
+
+    link.with_file_name(format!("{} - {}.{}", stem, label, extension))
+}
+
+/// Removes (through `runner`) the directories under `path` that are or become empty, deepest first.
+fn prune(runner: &dyn Runner, path: &std::path::Path, pruned: &mut Vec<std::path::PathBuf>) -> bool {
+    let mut is_empty = true;
+    for subpath in runner.list_dir(path) {
+        if runner.is_dir(&subpath) {
+            let sub_is_empty = prune(runner, &subpath, pruned);
+            if sub_is_empty && runner.remove_dir(&subpath).is_ok() {
+                pruned.push(subpath);
+            } else {
+                is_empty = false;
+            }
+        } else {
+            is_empty = false;
+        }
+    }
+
+    is_empty
+}
+
+pub fn remove_empty_directories(runner: &dyn Runner, path: &std::path::Path) -> bool {
+    let mut pruned = vec![];
+    let is_empty = prune(runner, path, &mut pruned);
+
+    for dir in pruned {
+        println!("removing directory {:?}", dir);
+    }
+
+    is_empty
+}
+
+/// Removes garbage files from the directories of the `links` sources.
+pub fn purge_garbage(runner: &dyn Runner, output: &output::Output, files: &[ScannedFile], links: &[(&ScannedFile, std::path::PathBuf)], seeding: Option<&SeedingIndex>) -> Vec<std::path::PathBuf> {
+    let linked_directories = links
+        .iter()
+        .filter_map(|(file, _)| file.path.parent().map(|p| p.to_path_buf()))
+        .collect::<std::collections::HashSet<_>>();
+
+    let mut purged = vec![];
+    for file in files.iter().filter(|f| f.metadata == Some(MediaData::Garbage)) {
+        if file.path.parent().is_some_and(|p| linked_directories.contains(p)) {
+            if seeding.is_some_and(|s| s.is_seeding(&file.path)) {
+                output.message(output::Phase::Purge, &file.path, format!("keeping garbage file {:?}: still seeding", file.path));
+                continue;
+            }
+
+            match runner.remove_file(&file.path) {
+                Ok(()) => {
+                    output.message(output::Phase::Purge, &file.path, format!("removing garbage file {:?}", file.path));
+                    purged.push(file.path.clone());
+                }
+                Err(e) => output.error(output::Phase::Purge, &file.path, format!("unable to remove garbage file {:?}: {}", file.path, e)),
+            }
+        }
+    }
+
+    purged
+}
+
+/// None for files gone since they were listed, or dangling symlinks, like `plan_in` skips.
+pub fn library_identity(layout: &Layout, path: &std::path::Path) -> Option<diff::Identity> {
+    if path.extension().is_some_and(|e| e == "strm") {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            if layout.owns_strm(&contents) {
+                return Some(diff::Identity::Strm(contents));
+            }
+        }
+    }
+
+    std::fs::metadata(path).ok().map(|m| diff::Identity::Inode(m.ino()))
+}
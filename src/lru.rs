@@ -0,0 +1,95 @@
+//! A small least recently used cache, for work repeated across files named alike, like the
+//! episodes of a season pack.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+#[derive(Debug)]
+pub struct Lru<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by when they were last used, the oldest first.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Clone + Eq + std::hash::Hash, V: Clone> Lru<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), order: BTreeMap::new(), tick: 0 }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        self.order.insert(self.tick, key.clone());
+        *used = self.tick;
+
+        Some(value.clone())
+    }
+
+    /// Evicts the least recently used entry when it's full.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break; };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// An `Lru` that can be shared, for caches living in otherwise immutable values. Cloning it
+/// gives an empty cache, the clone may well compute things differently.
+#[derive(Debug)]
+pub struct Cache<K, V> {
+    lru: Mutex<Lru<K, V>>,
+}
+
+impl<K: Clone + Eq + std::hash::Hash, V: Clone> Cache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self { lru: Mutex::new(Lru::new(capacity)) }
+    }
+
+    /// Not holding the lock while computing it, `f` may use the cache too.
+    pub fn get_or_insert_with(&self, key: K, f: impl FnOnce() -> V) -> V {
+        if let Some(value) = self.lru.lock().unwrap().get(&key) {
+            return value;
+        }
+
+        let value = f();
+        self.lru.lock().unwrap().insert(key, value.clone());
+        value
+    }
+
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        *lru = Lru::new(lru.capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lru.lock().unwrap().is_empty()
+    }
+}
+
+impl<K: Clone + Eq + std::hash::Hash, V: Clone> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Self::new(self.lru.lock().unwrap().capacity)
+    }
+}
@@ -0,0 +1,132 @@
+//! `harvester ls`: the shows and movies harvester manages, from the state's link records rather
+//! than from the library itself, sorted the way the locale sorts names.
+
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::state::State;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Shows,
+    Movies,
+}
+
+impl std::str::FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shows" => Ok(Kind::Shows),
+            "movies" => Ok(Kind::Movies),
+            _ => Err(format!("unknown library: {}, expected shows or movies", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Kind::Shows => write!(f, "shows"),
+            Kind::Movies => write!(f, "movies"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    /// The show or movie folder, or the movie file's stem for flat layouts.
+    pub name: String,
+    pub files: usize,
+    pub size: u64,
+    /// When the most recent of its links was made.
+    pub linked_at: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Sort {
+    #[default]
+    Name,
+    Size,
+    Recent,
+}
+
+impl std::str::FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Sort::Name),
+            "size" => Ok(Sort::Size),
+            "recent" => Ok(Sort::Recent),
+            _ => Err(format!("unknown sort: {}, expected name, size or recent", s)),
+        }
+    }
+}
+
+/// The show and movie libraries of the target directory and of every user's.
+pub fn libraries(config: &Config, target: &std::path::Path) -> Vec<(Kind, std::path::PathBuf)> {
+    let target = std::path::absolute(target).unwrap_or(target.to_path_buf());
+    let targets = [target.clone()].into_iter().chain(config.users.iter().map(|u| target.join(&u.target)));
+
+    let mut libraries = vec![];
+    for target in targets {
+        let shows = [&config.libraries.shows].into_iter().chain(config.shows.values().filter_map(|s| s.library.as_ref()));
+        libraries.extend(shows.map(|l| (Kind::Shows, target.join(l))));
+        let movies = [&config.libraries.movies].into_iter().chain(config.libraries.performances.as_ref());
+        libraries.extend(movies.map(|l| (Kind::Movies, target.join(l))));
+    }
+
+    libraries
+}
+
+/// The recorded links grouped by the entry they're in, in whichever library is the deepest one
+/// holding them. Links outside the libraries are left out.
+pub fn entries(state: &State, libraries: &[(Kind, std::path::PathBuf)]) -> Vec<Entry> {
+    let mut entries = BTreeMap::<(Kind, String), Entry>::new();
+
+    for record in state.links.iter() {
+        let library = libraries
+            .iter()
+            .filter(|(_, root)| record.link.starts_with(root))
+            .max_by_key(|(_, root)| root.components().count());
+        let Some((kind, root)) = library else { continue; };
+
+        let relative = record.link.strip_prefix(root).unwrap();
+        let name = match relative.components().count() {
+            1 => relative.file_stem(),
+            _ => relative.iter().next(),
+        };
+        let Some(name) = name.map(|n| n.to_string_lossy().to_string()) else { continue; };
+
+        let entry = entries.entry((*kind, name.clone())).or_insert(Entry { kind: *kind, name, files: 0, size: 0, linked_at: 0 });
+        entry.files += 1;
+        entry.size += record.size;
+        entry.linked_at = entry.linked_at.max(record.linked_at);
+    }
+
+    entries.into_values().collect()
+}
+
+/// Through `strcoll`, so it follows `LC_COLLATE` once `setlocale` was called. Names with a NUL
+/// byte in them can't go through it and compare bytewise.
+pub fn collate(a: &str, b: &str) -> std::cmp::Ordering {
+    match (std::ffi::CString::new(a), std::ffi::CString::new(b)) {
+        (Ok(a), Ok(b)) => unsafe { libc::strcoll(a.as_ptr(), b.as_ptr()) }.cmp(&0),
+        _ => a.cmp(b),
+    }
+}
+
+/// Libraries in order, shows first, then entries by `sort`, names breaking ties.
+pub fn sort(entries: &mut [Entry], sort: Sort) {
+    entries.sort_by(|a, b| {
+        let by = match sort {
+            Sort::Name => std::cmp::Ordering::Equal,
+            Sort::Size => b.size.cmp(&a.size),
+            Sort::Recent => b.linked_at.cmp(&a.linked_at),
+        };
+
+        a.kind.cmp(&b.kind).then(by).then_with(|| collate(&a.name, &b.name))
+    });
+}
@@ -1,258 +1,1678 @@
-use std::os::unix::fs::MetadataExt;
-use regex::Regex;
+use harvester::arr::Arr;
+use harvester::backup::{self, Backup};
+use harvester::config::{Config, ContentDuplicatePolicy, DeletedLinkPolicy, LinkMode};
+use harvester::journal::{self, Journal, JournalRunner};
+use harvester::output::Output;
+use harvester::overrides::Overrides;
+use harvester::report::Report;
+use harvester::state::{self, State};
+use harvester::store::StateStore;
+use harvester::subtitles::OpenSubtitles;
+use harvester::timings::{Phase, TimingRunner, Timings};
+use harvester::torrent::{self, SeedingIndex};
+use harvester::tracker::Tracker;
+use harvester::{completion, daemon, diff, doctor, hooks, imports, interrupt, mounts, retire, schedule, verify};
+use harvester::{Analyzer, DryRunner, DurableRunner, Layout, ReadOnlyRunner, RealRunner, Runner};
+use harvester::{find_files, library_identity, plan, purge_garbage, version_link};
 
-#[cfg(test)]
-mod test;
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// `--config`, then `$HARVESTER_CONFIG`, then the default location if there's a file there.
+fn config_path(config_path: Option<std::path::PathBuf>) -> Option<std::path::PathBuf> {
+    config_path
+        .or_else(|| std::env::var_os("HARVESTER_CONFIG").map(std::path::PathBuf::from))
+        .or_else(|| Config::default_path().filter(|p| p.exists()))
+}
+
+fn try_load_config(config_path: Option<std::path::PathBuf>, profile: Option<&str>) -> Result<Config, String> {
+    match (self::config_path(config_path), profile) {
+        (Some(path), _) => Config::load(&path, profile),
+        (None, Some(_)) => Err("--profile needs a config file".to_string()),
+        (None, None) => Config::from_env(),
+    }
+}
+
+fn load_config(config_path: Option<std::path::PathBuf>, profile: Option<&str>) -> Config {
+    try_load_config(config_path, profile).unwrap_or_else(|e| fail(&e))
+}
+
+/// The positional ones, or the config's.
+fn directories(positional: &[&String], config: &Config) -> Option<(std::path::PathBuf, std::path::PathBuf)> {
+    match (positional, &config.incoming, &config.target) {
+        ([incoming, target, ..], _, _) => Some((incoming.into(), target.into())),
+        ([], Some(incoming), Some(target)) => Some((incoming.clone(), target.clone())),
+        _ => None,
+    }
+}
+
+fn print_warnings(files: &[harvester::ScannedFile]) {
+    for warning in files.iter().filter_map(|f| f.warning.as_ref()) {
+        eprintln!("{}", warning);
+    }
+}
 
+fn try_load_state(config: &Config) -> Result<(Box<dyn StateStore>, State), String> {
+    let store = config.state_store().ok_or("unable to determine state directory, set state_dir")?;
+    let state = store.load()?;
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub enum MediaData {
-    Movie { title: String, year: Option<u32> },
-    ShowEpisode { name: String, season: u32, episode: u32 },
-    Garbage,
+    Ok((store, state))
 }
 
-pub struct ScannedFile {
-    path: std::path::PathBuf,
-    metadata: Option<MediaData>,
-    inode: u64,
+fn load_state(config: &Config) -> (Box<dyn StateStore>, State) {
+    try_load_state(config).unwrap_or_else(|e| fail(&e))
 }
 
-pub struct Analyzer {
-    cleaner: Regex,
-    title_season_episode: Regex,
-    title_episode_dash: Regex,
-    title_episode_quoted_name: Regex,
-    title_episode: Regex,
-    movie_year: Regex,
+/// The layout runs link with: the overrides, and the aliases, renumbering and lookups of the state.
+/// Check `lookup_errors` after linking with it, a failed lookup isn't the lack of an id.
+fn library_layout<'a>(config: &'a Config, incoming: &std::path::Path, overrides: Overrides, state: &State) -> Result<Layout<'a>, String> {
+    let layout = Layout::new(config, incoming).with_overrides(overrides).with_aliases(state.aliases.clone());
+    let layout = layout.with_renumbering(state.renumbering()?).with_tmdb_lookups(state.tmdb.clone());
+
+    Ok(layout.with_anilist_titles(state.anilist.clone()))
+}
+
+/// Plans a run against a `--record`ed tree and prints what it'd do, without touching anything.
+fn replay_run(config: &Config, path: &std::path::Path, verbose: bool) -> Result<(), String> {
+    let recording = harvester::replay::Recording::load(path)?;
+    println!("replaying {:?}: {:?} into {:?}", path, recording.incoming, recording.target);
+
+    let (fs, files, plan) = recording.plan(config)?;
+    print_warnings(&files);
+
+    let output = Output::default();
+    plan::apply(&plan, &DryRunner::over(&fs), &output);
+    output.flush(verbose);
+
+    let media = |inode: Option<u64>| files.iter().find(|f| Some(f.inode) == inode).and_then(|f| f.metadata.as_ref());
+    let added = plan.added().filter_map(|c| media(fs.inode(&c.source))).collect::<Vec<_>>();
+    let removed = plan.removed().filter_map(|l| media(fs.inode(l))).collect::<Vec<_>>();
+
+    let mut report = Report::default();
+    report.track_garbage(&files);
+    report.track_broken(&files);
+    report.changes = harvester::report::summarize(&added, &removed);
+    report.print();
+    Ok(())
 }
 
-impl Analyzer {
-    pub fn new() -> Self {
-        let cleaner = Regex::new(r"([. _]*)\[[^]]+\]([. _]*)").unwrap();
-        let title_season_episode = Regex::new(r"(.*) [sS](\d+)[eE](\d+) (.*)").unwrap();
-        let title_episode_dash = Regex::new(r"^(.*) - (\d+)(v\d)?( END)?( .*)?$").unwrap();
-        let title_episode_quoted_name = Regex::new(r"^(.*) [eE](\d+)( END)? '.*'?$").unwrap();
-        let title_episode = Regex::new(r"^(.*) (\d+)( END)?( \((.*)\))?( v2)?$").unwrap();
-        let movie_year = Regex::new(r"(.*[^-]) (\d{4})( [^-]|$)").unwrap();
+/// How a run went, when it didn't fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Arguments were missing, only the usage was printed.
+    Usage,
+    Finished,
+    /// By a signal, with changes left for `harvester resume`.
+    Interrupted,
+}
+
+/// Errors are returned rather than exiting, the daemon keeps going after a failed run.
+fn run_command(args: &[String]) -> Result<Outcome, String> {
+    let mut positional = vec![];
+    let mut dry_run = false;
+    let mut purge = false;
+    let mut link_mode = None;
+    let mut config_path = None;
+    let mut profile = None;
+    let mut all_warnings = false;
+    let mut force = false;
+    let mut verbose = false;
+    let mut explain = false;
+    let mut similar_movies = false;
+    let mut record = None;
+    let mut replay = None;
+    let mut read_only_incoming = false;
+    let mut timed = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry" => dry_run = true,
+            "--force" => force = true,
+            "--verbose" => verbose = true,
+            "--explain" => explain = true,
+            "--similar-movies" => similar_movies = true,
+            "--record" => record = Some(args.next().map(std::path::PathBuf::from).ok_or("--record needs a file")?),
+            "--replay" => replay = Some(args.next().map(std::path::PathBuf::from).ok_or("--replay needs a file")?),
+            "--all-warnings" => all_warnings = true,
+            "--purge-garbage" => purge = true,
+            "--read-only-incoming" => read_only_incoming = true,
+            "--timings" => timed = true,
+            "--link-mode" => link_mode = args.next().map(|m| m.parse::<LinkMode>()).transpose()?,
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut config = try_load_config(config_path, profile.as_deref())?;
+    if let Some(link_mode) = link_mode {
+        config.link_mode = link_mode;
+    }
+    config.read_only_incoming |= read_only_incoming;
+    if config.read_only_incoming && purge {
+        return Err("--purge-garbage deletes from the incoming directory, which is read-only".to_string());
+    }
+
+    if let Some(replay) = replay {
+        replay_run(&config, &replay, verbose)?;
+        return Ok(Outcome::Finished);
+    }
+
+    let Some((incoming, jellyfin)) = directories(&positional, &config) else {
+        eprintln!("usage: harvester [<incoming> <jellyfin>] [--dry] [--purge-garbage] [--read-only-incoming] [--timings] [--all-warnings] [--force] [--verbose] [--explain] [--similar-movies] [--record <file>] [--link-mode hardlink|strm] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester --replay <file> [--verbose] [--link-mode hardlink|strm] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester init [[<incoming>] <target>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester parse <filename> [--config <file>] [--profile <name>]");
+        eprintln!("       harvester conflicts [<incoming>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester debug-bundle [<incoming> <target>] [--anonymize] [--output <file>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester diff [<incoming> <jellyfin>] [--link-mode hardlink|strm] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester doctor [<incoming> <jellyfin>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester du [<target>] [--top <count>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester ls [shows|movies] [--target <dir>] [--match <text>] [--since <days>] [--sort name|size|recent] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester retire [--ratio <ratio>] [--days <days>] [--remove] [--delete-data] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester missing [<incoming> <jellyfin>] [--json] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester verify [--repair] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester resume [--dry] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester rollback [--to <backup>] [--dry] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester restore [<path|show>] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester history <path|show> [--config <file>] [--profile <name>]");
+        eprintln!("       harvester rename-show <old name> <new name> [--dry] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester move-episodes [<incoming> <jellyfin>] --show <name> --from-season <season> --range <first>-<last> --to-season <season> [--dry] [--config <file>] [--profile <name>]");
+        eprintln!("       harvester state export [<file>] | import <file> [--replace] [--rebase <old>=<new>] | check [--config <file>] [--profile <name>]");
+        eprintln!("       harvester daemon [<incoming> <jellyfin>] [--interval <seconds>] [--listen <address>] [--socket <path>] [run options]");
+        eprintln!("       harvester health [--live] [--listen <address>]");
+        eprintln!("       harvester ctl status|rescan|pause|resume|dump-state [--socket <path>]");
+        return Ok(Outcome::Usage);
+    };
+    doctor::overlap(&incoming, &Layout::new(&config, &incoming).roots(&jellyfin))?;
+
+    let (store, mut state) = try_load_state(&config)?;
+
+    // An unmounted network filesystem looks like everything was deleted.
+    let mounts = mounts::mounts();
+    if !force {
+        if let Err(e) = state.check_filesystems(&mounts, &[&incoming, &jellyfin]) {
+            return Err(format!("{}\nNot touching anything, run harvester init again if that's expected, or use --force", e));
+        }
+
+        let absolute = |p: &std::path::Path| std::path::absolute(p).unwrap_or(p.to_path_buf());
+        let checks = [
+            (&incoming, state.links.iter().filter(|r| r.source.starts_with(absolute(&incoming))).count()),
+            (&jellyfin, state.links.iter().filter(|r| r.link.starts_with(absolute(&jellyfin))).count()),
+        ];
+
+        for (dir, expected) in checks {
+            if let Some(problem) = mounts::looks_unmounted(dir, expected) {
+                let fstype = mounts::mount_of(&mounts, dir).filter(|m| m.is_network()).map_or(String::new(), |m| format!(" ({})", m.fstype));
+                return Err(format!("{}{}, is it mounted? Not touching anything, use --force if it's meant to be empty", problem, fstype));
+            }
+        }
+    }
+
+    if config.link_mode == LinkMode::Hardlink && !dry_run && jellyfin.is_dir() {
+        doctor::hard_links(&jellyfin)?;
+    }
+
+    let hook = |event: hooks::Event| {
+        if !dry_run {
+            hooks::run(&config.hooks, event).unwrap_or_else(|e| eprintln!("{}", e));
+        }
+    };
+    hook(hooks::Event::RunStart);
+    let mut run_end = (!dry_run).then(|| hooks::RunEnd::new(&config.hooks));
+
+    if let Some(remote) = &config.remote {
+        match harvester::remote::list(remote) {
+            Ok(files) => {
+                let missing = harvester::remote::missing(&files, &incoming);
+                if dry_run {
+                    for file in missing.iter() {
+                        println!("would pull {:?} from {}", file.path, remote.host);
+                    }
+                } else if !missing.is_empty() {
+                    println!("pulling {} files from {}", missing.len(), remote.host);
+                    harvester::remote::pull(remote, &missing, &incoming).unwrap_or_else(|e| eprintln!("{}", e));
+                }
+            }
+            Err(e) => eprintln!("not pulling from {}: {}", remote.host, e),
+        }
+    }
+
+    let timings = Timings::default();
+    let overrides = Overrides::load(&incoming)?;
+    let mut scanned_files = overrides.analyze_directory_timed(&config, &incoming, &timings)?;
+    if explain {
+        for (path, explanation) in overrides.explain(&config, &scanned_files)? {
+            println!("{:?}: {}", path, explanation);
+        }
+    }
+    let layout = library_layout(&config, &incoming, overrides, &state)?;
+
+    if let Some(record) = &record {
+        let recording = harvester::replay::record(&incoming, &jellyfin, &layout.roots(&jellyfin));
+        recording.save(record)?;
+        println!("recorded {} files to {:?}", recording.files.len(), record);
+    }
+
+    let mut blocked = vec![];
+    for file in scanned_files.iter_mut().filter(|f| f.metadata != Some(harvester::MediaData::Garbage) && layout.is_blocked(f)) {
+        file.warning = None;
+        blocked.push(file.path.clone());
+    }
+    for path in state.record_blocked(blocked) {
+        println!("blocklisted, not linking {:?}", path);
+    }
+
+    if all_warnings {
+        print_warnings(&scanned_files);
+    } else {
+        let new = state.new_warnings(&scanned_files);
+        for file in new.iter() {
+            eprintln!("{}", file.warning.as_ref().unwrap());
+        }
+
+        let known = scanned_files.iter().filter(|f| f.warning.is_some()).count() - new.len();
+        if known > 0 {
+            eprintln!("{} files with known warnings, use --all-warnings to see them", known);
+        }
+    }
+    state.record_warnings(&scanned_files);
+
+    // A missing library means it's not mounted, not that everything in it was deleted.
+    if config.deleted_links == DeletedLinkPolicy::Tombstone && jellyfin.is_dir() {
+        let sources = scanned_files
+            .iter()
+            .map(|f| std::fs::canonicalize(&f.path).unwrap_or(f.path.clone()))
+            .collect::<std::collections::HashSet<_>>();
+
+        for tombstone in state.tombstone_deleted(&sources, state::now()) {
+            println!("{:?} was deleted from the library, not linking {:?} again (see harvester restore)", tombstone.link, tombstone.source);
+        }
+    }
+
+    let runner: Box<dyn Runner> = if dry_run {
+        Box::new(DryRunner::default())
+    } else if config.durable {
+        Box::new(DurableRunner::default())
+    } else {
+        Box::new(RealRunner {})
+    };
+    let runner: Box<dyn Runner> = match config.read_only_incoming {
+        true => Box::new(ReadOnlyRunner::new(runner, &incoming)),
+        false => runner,
+    };
+    let runner = TimingRunner::new(runner.as_ref(), &timings);
+
+    let mut report = Report::default();
+    report.track_garbage(&scanned_files);
+    report.track_broken(&scanned_files);
+
+    let imports_instead = |f: &harvester::ScannedFile| {
+        f.metadata.as_ref().and_then(|m| Arr::for_media(&config, m)).is_some_and(|a| a.imports_instead())
+    };
+
+    let mut duplicates = state.duplicates(&scanned_files);
+    let content_duplicates = match config.content_duplicates {
+        ContentDuplicatePolicy::Link => vec![],
+        _ => state.content_duplicates(&scanned_files),
+    };
+    if config.content_duplicates == ContentDuplicatePolicy::Skip {
+        duplicates.extend(content_duplicates.iter().map(|(f, r)| (f.path.clone(), r.source.clone())));
+    }
+
+    let skip = |f: &harvester::ScannedFile| {
+        imports_instead(f)
+            || state.is_tombstoned(&std::fs::canonicalize(&f.path).unwrap_or(f.path.clone()))
+            || duplicates.iter().any(|(source, _)| *source == f.path)
+            || content_duplicates.iter().any(|(d, _)| d.path == f.path)
+    };
+
+    let mut links = layout.link_paths(&scanned_files, &jellyfin);
+    if let Some(error) = layout.lookup_errors().first() {
+        return Err(format!("{}, not touching anything", error));
+    }
+    links.retain(|(f, _)| !skip(f));
+    if config.content_duplicates == ContentDuplicatePolicy::Version {
+        links.extend(content_duplicates.iter().map(|(f, r)| (*f, version_link(f, &r.link))));
+    }
+
+    let scheduled = schedule::allows(&config.schedule.deletions, &schedule::LocalTime::now());
+    let guarded = match config.require_sentinel {
+        true => plan::check_sentinel(&layout, &jellyfin),
+        false => Ok(()),
+    };
+    let deletions = scheduled && guarded.is_ok();
+
+    let plan_size = |p: &plan::Plan| p.deletes.len() + p.creates.len() + p.prunes.len();
+    let mut plan = timings.time(Phase::Plan, || plan::plan(&layout, &scanned_files, &jellyfin, &links), plan_size);
+    if !deletions {
+        let deferred = plan.defer_deletions();
+        if let Err(e) = &guarded {
+            eprintln!("{}, not removing anything", e);
+        }
+        if !deferred.is_empty() && !scheduled {
+            println!("outside of the deletion schedule, leaving {} files for a later run", deferred.len());
+        } else if !deferred.is_empty() {
+            println!("leaving {} files for a later run", deferred.len());
+        }
+    }
+    if let Err(e) = plan::preflight(&plan) {
+        match dry_run {
+            true => eprintln!("{}", e),
+            false => return Err(format!("{}, not touching anything", e)),
+        }
+    }
+
+    if !dry_run && backup::is_needed(&plan) && config.backups.keep > 0 {
+        let backup = Backup::new(&plan, &state, &runner, state::now());
+        match backup.save(&backup::dir(store.path()), config.backups.keep) {
+            Ok(path) => println!("backed up the state and plan to {:?}, see harvester rollback", path),
+            Err(e) => return Err(format!("{}, not touching anything", e)),
+        }
+    }
+
+    let output = Output::default();
+    let journal_path = journal::path(store.path());
+    let journaled = match dry_run {
+        true => None,
+        false => {
+            if journal_path.exists() {
+                println!("replacing the journal of an interrupted run, this run does what's left of it");
+            }
+            let records = links
+                .iter()
+                .map(|(file, link)| (std::fs::canonicalize(&file.path).unwrap_or(file.path.clone()), std::path::absolute(link).unwrap_or(link.clone()), file.inode))
+                .collect();
+            Journal::new(&plan, records, state::now()).begin(&journal_path)?;
+            Some(JournalRunner::open(&runner, &journal_path)?)
+        }
+    };
+    interrupt::catch();
+    let applied = match &journaled {
+        None => plan::apply(&plan, &runner, &output),
+        Some(journaled) => plan::apply(&plan, journaled, &output),
+    };
+    output.flush(verbose);
+    plan.forget(&applied);
+    links.retain(|(_, link)| applied.is_done(link));
+    // Only what was done gets recorded, the rest is left for the next run.
+    let interrupted = !applied.skipped.is_empty();
+    report.failures = applied.failures.into_iter().map(|f| (f.path, f.error)).collect();
+
+    let added = plan
+        .added()
+        .filter_map(|c| links.iter().find(|(_, l)| *l == c.link))
+        .filter_map(|(f, _)| f.metadata.as_ref())
+        .collect::<Vec<_>>();
+    let removed = plan
+        .removed()
+        .filter_map(|l| state.links.iter().find(|r| r.link == std::path::absolute(l).unwrap_or(l.clone())))
+        .filter_map(|r| scanned_files.iter().find(|f| std::fs::canonicalize(&f.path).unwrap_or(f.path.clone()) == r.source))
+        .filter_map(|f| f.metadata.as_ref())
+        .collect::<Vec<_>>();
+    report.changes = harvester::report::summarize(&added, &removed);
+
+    for link in plan.removed() {
+        hook(hooks::Event::Removed { link });
+    }
+    for create in plan.added() {
+        hook(hooks::Event::Linked { source: &create.source, link: &create.link });
+    }
+
+    let added_files = plan.added().filter_map(|c| links.iter().find(|(_, l)| *l == c.link)).map(|(f, _)| *f).collect::<Vec<_>>();
+    report.imports = imports::imported(&incoming, &scanned_files, &added_files);
+    for import in report.imports.iter() {
+        let summary = import.describe();
+        hook(hooks::Event::Imported { torrent: &import.name, summary: &summary, complete: import.is_complete() });
+    }
+
+    if let (Some(subtitles), false) = (&config.subtitles, dry_run || interrupted) {
+        match OpenSubtitles::new(subtitles) {
+            Ok(client) => {
+                for (file, link) in links.iter().filter(|(_, l)| plan.added().any(|c| c.link == *l)) {
+                    let media = file.metadata.as_ref().unwrap();
+                    client.fetch(media, &file.path, link).unwrap_or_else(|e| eprintln!("{}", e));
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
 
-        Self {
-            cleaner,
-            title_season_episode,
-            title_episode_dash,
-            title_episode_quoted_name,
-            title_episode,
-            movie_year,
+    // Only files that weren't imported on an earlier run, asking again makes them import copies.
+    let arr_imports = scanned_files.iter().filter(|f| imports_instead(f)).collect::<Vec<_>>();
+    let sources = arr_imports.iter().map(|f| std::fs::canonicalize(&f.path).unwrap_or(f.path.clone())).collect::<Vec<_>>();
+    let new_imports = state.new_imports(&sources).into_iter().cloned().collect::<Vec<_>>();
+    for (file, source) in arr_imports.iter().zip(sources.iter()).filter(|(_, s)| new_imports.contains(s) && !interrupted) {
+        let arr = Arr::for_media(&config, file.metadata.as_ref().unwrap()).unwrap();
+        println!("importing {:?} through {}", file.path, arr.kind);
+        if !dry_run {
+            match arr.import(&file.path) {
+                Ok(()) => state.record_imported(source),
+                Err(e) => eprintln!("{}", e),
+            }
         }
     }
 
-    pub fn analyze_directory(&self, path: &std::path::PathBuf) -> Vec<ScannedFile> {
-        println!("scanning {:?}...", path);
+    if !dry_run && !interrupted && !plan.creates.is_empty() {
+        for arr in Arr::all(&config).iter().filter(|a| !a.imports_instead()) {
+            println!("asking {} to rescan its library", arr.kind);
+            arr.rescan().unwrap_or_else(|e| eprintln!("{}", e));
+        }
+    }
 
-        let files = find_all_files(path)
+    if !dry_run {
+        let links = links
             .iter()
-            .map(|f| ScannedFile {
-                path: f.clone(),
-                metadata: self.analyze(f),
-                inode: std::fs::metadata(f).unwrap().ino(),
+            .map(|(file, link)| {
+                let source = std::fs::canonicalize(&file.path).unwrap_or(file.path.clone());
+                let link = std::path::absolute(link).unwrap_or(link.clone());
+
+                (file, source, link)
             })
             .collect::<Vec<_>>();
 
-        println!("found {} files.", files.len());
-        files
-    }
-
-
-    pub fn analyze(&self, path: &std::path::PathBuf) -> Option<MediaData> {
-        match path.extension().and_then(std::ffi::OsStr::to_str) {
-            Some("mkv" | "mp4") => {
-                let name = path.file_stem().unwrap().to_str().unwrap().to_lowercase();
-                let name = self.cleaner.replace_all(&name, "");
-                let name = name.replace("_", " ");
-                let name = name.replace(".", " ");
-
-                if let Some(x) = self.title_season_episode.captures(&name) {
-                    Some(MediaData::ShowEpisode {
-                        name: x.get(1).unwrap().as_str().to_string(),
-                        season: x.get(2).unwrap().as_str().parse::<u32>().unwrap(),
-                        episode: x.get(3).unwrap().as_str().parse::<u32>().unwrap(),
-                    })
-                } else if let Some(x) = self.title_episode_dash.captures(&name) {
-                    Some(MediaData::ShowEpisode {
-                        name: x.get(1).unwrap().as_str().to_string(),
-                        season: 1,
-                        episode: x.get(2).unwrap().as_str().parse::<u32>().unwrap(),
-                    })
-                } else if let Some(x) = self.title_episode_quoted_name.captures(&name) {
-                    Some(MediaData::ShowEpisode {
-                        name: x.get(1).unwrap().as_str().to_string(),
-                        season: 1,
-                        episode: x.get(2).unwrap().as_str().parse::<u32>().unwrap(),
-                    })
-                } else if let Some(x) = self.title_episode.captures(&name) {
-                    Some(MediaData::ShowEpisode {
-                        name: x.get(1).unwrap().as_str().to_string(),
-                        season: 1,
-                        episode: x.get(2).unwrap().as_str().parse::<u32>().unwrap(),
-                    })
-                } else if let Some(x) = self.movie_year.captures(&name) {
-                    Some(MediaData::Movie {
-                        title: x.get(1).unwrap().as_str().to_string(),
-                        year: Some(x.get(2).unwrap().as_str().parse::<u32>().unwrap()),
-                    })
-                } else {
-                    eprintln!("unknown filename pattern: {:?}", name);
-                    None
-                }
+        let added = links
+            .iter()
+            .filter(|(_, source, link)| !state.has_link(source, link))
+            .filter_map(|(file, _, _)| file.metadata.as_ref())
+            .collect::<Vec<_>>();
+
+        if !added.is_empty() && !interrupted {
+            for tracker in Tracker::all(&config) {
+                println!("exporting {} new items to {}", added.len(), tracker.kind);
+                tracker.add_to_collection(&added).unwrap_or_else(|e| eprintln!("{}", e));
+            }
+        }
+
+        let absolute = |p: &std::path::Path| std::path::absolute(p).unwrap_or(p.to_path_buf());
+        let linked = plan
+            .added()
+            .map(|c| (std::fs::canonicalize(&c.source).unwrap_or(c.source.clone()), absolute(&c.link)))
+            .collect::<Vec<_>>();
+        let unlinked = plan.removed().map(|l| absolute(l)).collect::<Vec<_>>();
+        state.record_history(&scanned_files, &linked, &unlinked, state::now());
+
+        state.record_links(links.into_iter().map(|(file, source, link)| (source, link, file.inode)).collect(), state::now());
+        if let Some(lookups) = layout.tmdb_lookups() {
+            state.tmdb = lookups;
+        }
+        if let Some(titles) = layout.anilist_titles() {
+            state.anilist = titles;
+        }
+        if let Err(e) = store.save(&state) {
+            interrupt::release();
+            return Err(e);
+        }
+        if !interrupted {
+            Journal::finish(&journal_path).unwrap_or_else(|e| eprintln!("{}", e));
+        }
+    }
+    interrupt::release();
+
+    let (linked, removed) = (plan.added().count(), plan.removed().count());
+    if interrupted {
+        if let Some(run_end) = &mut run_end {
+            run_end.ended(hooks::Outcome::Interrupted, linked, removed);
+        }
+        report.print();
+        println!(
+            "interrupted: {} links made and {} files removed, {} changes left, run harvester resume to finish them",
+            linked,
+            removed,
+            applied.skipped.len(),
+        );
+        return Ok(Outcome::Interrupted);
+    }
+
+    if config.link_mode == LinkMode::Hardlink {
+        report.track_link_counts(&links.iter().map(|(f, _)| f.path.as_path()).collect::<Vec<_>>());
+    }
+
+    report.duplicates = duplicates;
+    if similar_movies {
+        let movies = links
+            .iter()
+            .filter(|(f, _)| matches!(f.metadata, Some(harvester::MediaData::Movie { .. })))
+            .filter_map(|(f, link)| {
+                let duration = harvester::container::duration(&f.path)?;
+                Some(harvester::report::MovieFile { media: f.metadata.as_ref()?, link, duration, size: f.size })
+            })
+            .collect();
+        report.similar_movies = harvester::report::similar_movies(movies);
+    }
+    if config.tmdb.is_some() {
+        report.seasons = completion::seasons(&layout, &links, &|name| layout.season_episode_counts(name), false);
+        for error in layout.lookup_errors() {
+            eprintln!("{}", error);
+        }
+    }
+
+    if purge && !scheduled {
+        println!("outside of the deletion schedule, not purging garbage");
+
+    } else if purge {
+        let seeding = match &config.torrent {
+            Some(torrent) => match SeedingIndex::load(torrent) {
+                Ok(index) => Some(index),
+                Err(e) => { eprintln!("not purging garbage, unable to check seeding torrents: {}", e); None },
+            },
+            None => { eprintln!("no torrent client configured, assuming nothing is seeding"); None },
+        };
+
+        if seeding.is_some() || config.torrent.is_none() {
+            report.purged = purge_garbage(&runner, &output, &scanned_files, &links, seeding.as_ref());
+            output.flush(verbose);
+        }
+    }
+
+    report.print();
+    if timed {
+        timings.print();
+    }
+    if let Some(run_end) = &mut run_end {
+        run_end.ended(hooks::Outcome::Finished, linked, removed);
+    }
+    Ok(Outcome::Finished)
+}
+
+fn diff_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut link_mode = None;
+    let mut config_path = None;
+    let mut profile = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--link-mode" => link_mode = args.next().map(|m| m.parse::<LinkMode>().unwrap_or_else(|e| fail(&e))),
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut config = load_config(config_path, profile.as_deref());
+    if let Some(link_mode) = link_mode {
+        config.link_mode = link_mode;
+    }
+
+    let (incoming, jellyfin) = directories(&positional, &config)
+        .unwrap_or_else(|| fail("usage: harvester diff [<incoming> <jellyfin>] [--link-mode hardlink|strm] [--config <file>] [--profile <name>]"));
+    doctor::overlap(&incoming, &Layout::new(&config, &incoming).roots(&jellyfin)).unwrap_or_else(|e| fail(&e));
+
+    let overrides = Overrides::load(&incoming).unwrap_or_else(|e| fail(&e));
+    let scanned_files = overrides.analyze_directory(&config, &incoming).unwrap_or_else(|e| fail(&e));
+    print_warnings(&scanned_files);
+    let (_, state) = load_state(&config);
+    let layout = library_layout(&config, &incoming, overrides, &state).unwrap_or_else(|e| fail(&e));
+
+    let links = layout.link_paths(&scanned_files, &jellyfin);
+    if let Some(error) = layout.lookup_errors().first() {
+        fail(&format!("{}, unable to tell where files belong", error));
+    }
+    let expected = links
+        .into_iter()
+        .map(|(file, link)| {
+            let identity = match config.link_mode {
+                LinkMode::Hardlink => diff::Identity::Inode(file.inode),
+                LinkMode::Strm => diff::Identity::Strm(layout.strm_target(file)),
+            };
+
+            (link, identity)
+        })
+        .collect::<Vec<_>>();
+
+    let actual = layout
+        .roots(&jellyfin)
+        .iter()
+        .filter(|r| r.exists())
+        .flat_map(|r| find_files(r, &config.ignore))
+        .filter_map(|path| {
+            let identity = library_identity(&layout, &path)?;
+            Some((path, identity))
+        })
+        .collect::<Vec<_>>();
+
+    let changes = diff::diff(&expected, &actual);
+    for change in changes.iter() {
+        println!("{}", change);
+    }
+
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn doctor_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut config_path = None;
+    let mut profile = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let config_path = self::config_path(config_path);
+    let (mut findings, config) = doctor::config(config_path.as_deref(), profile.as_deref());
+
+    if let Some(config) = &config {
+        if let Some((incoming, target)) = directories(&positional, config) {
+            findings.extend(doctor::paths(config, &incoming, &target));
+        }
+
+        findings.extend(doctor::state(config));
+        findings.extend(doctor::rules(config));
+        findings.extend(doctor::integrations(config));
+    }
+
+    for finding in findings.iter() {
+        println!("{}", finding);
+    }
+
+    if findings.iter().any(|f| f.problem.is_some()) {
+        std::process::exit(1);
+    }
+}
+
+fn retire_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut ratio = None;
+    let mut days = None;
+    let mut remove = false;
+    let mut delete_data = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            "--ratio" => ratio = args.next().and_then(|r| r.parse::<f64>().ok()),
+            "--days" => days = args.next().and_then(|d| d.parse::<u64>().ok()),
+            "--remove" => remove = true,
+            "--delete-data" => { remove = true; delete_data = true; },
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let (_, state) = load_state(&config);
+    if delete_data && config.read_only_incoming {
+        fail("--delete-data deletes from the incoming directory, which is read-only");
+    }
+    if delete_data && config.link_mode == LinkMode::Strm {
+        fail("--delete-data would leave .strm files pointing at deleted data, use --remove");
+    }
+
+    let thresholds = retire::Thresholds {
+        ratio: ratio.or(config.retire.ratio),
+        seeding_time: days.or(config.retire.seed_days).map(|d| d * 24 * 60 * 60),
+    };
+
+    if thresholds.ratio.is_none() && thresholds.seeding_time.is_none() {
+        fail("no retirement threshold, use --ratio/--days or configure [retire]");
+    }
+
+    let torrent_config = config.torrent.as_ref().unwrap_or_else(|| fail("no torrent client configured"));
+    let client = torrent::connect(torrent_config).unwrap_or_else(|e| fail(&e));
+    let torrents = client.torrents().unwrap_or_else(|e| fail(&e));
+
+    for torrent in retire::candidates(&torrents, &Analyzer::from_config(&config).unwrap_or_else(|e| fail(&e)), &state, thresholds) {
+        println!(
+            "{}: ratio {:.2}, seeded for {} days",
+            torrent.name,
+            torrent.ratio,
+            torrent.seeding_time / (24 * 60 * 60),
+        );
+
+        if remove {
+            println!("removing torrent {:?}{}", torrent.name, if delete_data { " and its data" } else { "" });
+            if let Err(e) = client.remove(torrent, delete_data) {
+                eprintln!("{}", e);
             }
-            Some("srt" | "sub") => { Some(MediaData::Garbage) }
-            Some("idx")         => { Some(MediaData::Garbage) }
-            Some("ogg" | "mp3") => { Some(MediaData::Garbage) }
-            Some("jpg" | "png") => { Some(MediaData::Garbage) }
-            Some("ts" | "bdjo" | "clpi" | "mpls" | "m2ts" | "bdmv") => { Some(MediaData::Garbage) }
-            Some("torrent" | "meta" | "exe" | "nfo" | "txt" | "md5") => { Some(MediaData::Garbage) }
-            _ => { eprintln!("unknown extension: {:?}", path); None },
         }
     }
 }
 
-pub fn find_all_files_aux(path: &std::path::PathBuf, output: &mut Vec<std::path::PathBuf>) {
-    if path.is_dir() {
-        for subdir in path.read_dir().unwrap() {
-            find_all_files_aux(&subdir.unwrap().path(), output);
+fn missing_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut config_path = None;
+    let mut profile = None;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            "--json" => json = true,
+            _ => positional.push(arg),
         }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let (incoming, jellyfin) = directories(&positional, &config)
+        .unwrap_or_else(|| fail("usage: harvester missing [<incoming> <jellyfin>] [--json] [--config <file>] [--profile <name>]"));
+    if config.tmdb.is_none() {
+        fail("finding missing episodes needs episode counts, configure [tmdb]");
+    }
+
+    let overrides = Overrides::load(&incoming).unwrap_or_else(|e| fail(&e));
+    let scanned_files = overrides.analyze_directory(&config, &incoming).unwrap_or_else(|e| fail(&e));
+    let (_, state) = load_state(&config);
+    let layout = library_layout(&config, &incoming, overrides, &state).unwrap_or_else(|e| fail(&e));
+
+    let links = layout.link_paths(&scanned_files, &jellyfin);
+    let seasons = completion::seasons(&layout, &links, &|name| layout.season_episode_counts(name), true);
+    if let Some(error) = layout.lookup_errors().first() {
+        fail(&format!("{}, unable to tell what's missing", error));
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&completion::missing(&seasons)).unwrap());
     } else {
-        output.push(path.clone());
+        for season in seasons.iter().filter(|s| !s.is_complete()) {
+            println!("{}", season);
+        }
     }
 }
-pub fn find_all_files(path: &std::path::PathBuf) -> Vec<std::path::PathBuf> {
-    let mut files = vec![];
-    find_all_files_aux(path, &mut files);
-    files
+
+fn init_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut positional = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(std::path::PathBuf::from(arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let (incoming, target) = match &positional[..] {
+        [target] => (config.incoming.clone(), target.clone()),
+        [incoming, target] => (Some(incoming.clone()), target.clone()),
+        [] if config.target.is_some() => (config.incoming.clone(), config.target.clone().unwrap()),
+        _ => fail("usage: harvester init [[<incoming>] <target>] [--config <file>] [--profile <name>]"),
+    };
+    let (store, mut state) = load_state(&config);
+
+    let layout = Layout::new(&config, incoming.as_deref().unwrap_or(std::path::Path::new("")));
+    for path in plan::init(&layout, &target).unwrap_or_else(|e| fail(&e)) {
+        println!("created {:?}", path);
+    }
+
+    let mounts = mounts::mounts();
+    for dir in incoming.into_iter().chain([target]) {
+        let identity = mounts::identity(&mounts, &dir).unwrap_or_else(|e| fail(&format!("{:?}: {}", dir, e)));
+        println!("{:?} is on {}", dir, identity);
+        state.filesystems.insert(std::path::absolute(&dir).unwrap_or(dir), identity);
+    }
+
+    store.save(&state).unwrap_or_else(|e| fail(&e));
+    println!("state in {:?}", store.path().parent().unwrap());
 }
 
-trait Runner {
-    fn remove_dir(&self, path: &std::path::PathBuf);
-    fn remove_file(&self, path: &std::path::PathBuf);
-    fn create_dir_all(&self, path: &std::path::PathBuf);
-    fn hard_link(&self, path: &std::path::PathBuf, link: &std::path::PathBuf);
+fn du_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut top = 20;
+    let mut positional = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--top" => top = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| fail("--top needs a number")),
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(std::path::PathBuf::from(arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let target = match &positional[..] {
+        [target] => target.clone(),
+        [] if config.target.is_some() => config.target.clone().unwrap(),
+        _ => fail("usage: harvester du [<target>] [--top <count>] [--config <file>] [--profile <name>]"),
+    };
+    let layout = Layout::new(&config, config.incoming.as_deref().unwrap_or(std::path::Path::new("")));
+
+    let usage = harvester::du::usage(&layout.roots(&target));
+    let total = harvester::du::total(&usage);
+    let human_size = harvester::report::human_size;
+    let describe = |u: &harvester::du::Usage| {
+        format!(
+            "{}, {} only in the library, {} also linked elsewhere, {} duplicated",
+            human_size(u.total), human_size(u.exclusive()), human_size(u.shared), human_size(u.duplicated),
+        )
+    };
+
+    let mut items = usage.iter().collect::<Vec<_>>();
+    items.sort_by_key(|(_, u)| std::cmp::Reverse(u.total));
+    for (item, u) in items.into_iter().take(top) {
+        println!("{:?}: {}", item, describe(u));
+    }
+    println!("library: {}", describe(&total));
+}
+
+fn ls_command(args: &[String]) {
+    const USAGE: &str = "usage: harvester ls [shows|movies] [--target <dir>] [--match <text>] [--since <days>] [--sort name|size|recent] [--config <file>] [--profile <name>]";
+    let mut config_path = None;
+    let mut profile = None;
+    let mut target = None;
+    let mut kind = None;
+    let mut query = None;
+    let mut since = None;
+    let mut sort = harvester::ls::Sort::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--target" => target = args.next().map(std::path::PathBuf::from),
+            "--match" => query = Some(args.next().unwrap_or_else(|| fail(USAGE)).to_lowercase()),
+            "--since" => since = Some(args.next().and_then(|d| d.parse::<u64>().ok()).unwrap_or_else(|| fail("--since needs a number of days"))),
+            "--sort" => sort = args.next().unwrap_or_else(|| fail(USAGE)).parse().unwrap_or_else(|e: String| fail(&e)),
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ if kind.is_none() => kind = Some(arg.parse::<harvester::ls::Kind>().unwrap_or_else(|e| fail(&e))),
+            _ => fail(USAGE),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let target = target.or(config.target.clone()).unwrap_or_else(|| fail(USAGE));
+    let (_, state) = load_state(&config);
+
+    // Collation comes from the environment, like `ls` and `sort`.
+    unsafe { libc::setlocale(libc::LC_COLLATE, c"".as_ptr()) };
+
+    let mut entries = harvester::ls::entries(&state, &harvester::ls::libraries(&config, &target));
+    let now = state::now();
+    entries.retain(|e| kind.is_none_or(|k| e.kind == k));
+    entries.retain(|e| query.as_ref().is_none_or(|q| e.name.to_lowercase().contains(q)));
+    entries.retain(|e| since.is_none_or(|days| e.linked_at + days * 86400 >= now));
+    harvester::ls::sort(&mut entries, sort);
+
+    for entry in entries {
+        let files = if entry.files == 1 { "1 file".to_string() } else { format!("{} files", entry.files) };
+        println!("{}/{}  {}, {}", entry.kind, entry.name, files, harvester::report::human_size(entry.size));
+    }
 }
 
-struct RealRunner {}
-impl Runner for RealRunner {
-    fn remove_dir(&self, path: &std::path::PathBuf) {
-        std::fs::remove_dir(path).unwrap();
+fn parse_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut name = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ if name.is_none() => name = Some(std::path::PathBuf::from(arg)),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
     }
-    fn remove_file(&self, path: &std::path::PathBuf) {
-        std::fs::remove_file(path).unwrap();
+
+    let name = name.unwrap_or_else(|| fail("usage: harvester parse <filename> [--config <file>] [--profile <name>]"));
+    let config = load_config(config_path, profile.as_deref());
+    let analyzer = Analyzer::from_config(&config).unwrap_or_else(|e| fail(&e));
+
+    let incoming = config.incoming.clone().unwrap_or_default();
+    let path = incoming.join(&name);
+    println!("cleaned up: {:?}", analyzer.clean(&path));
+    if harvester::analyzer::is_video(&path) {
+        println!("matched: {}", analyzer.explain(&path));
     }
-    fn create_dir_all(&self, path: &std::path::PathBuf) {
-        std::fs::create_dir_all(path).unwrap();
+
+    let (rule, media) = analyzer.parse(&path).unwrap_or_else(|e| fail(&e));
+    println!("rule: {}", rule.map_or("none, by extension".to_string(), |r| r.to_string()));
+    println!("parsed: {}", media);
+
+    for (other, outcome) in analyzer.candidates(&path).into_iter().skip(1) {
+        println!("also matched by {}: {}", other, outcome.map_or("nothing extracted".to_string(), |m| m.to_string()));
     }
-    fn hard_link(&self, original: &std::path::PathBuf, link: &std::path::PathBuf) {
-        std::fs::hard_link(original, link).unwrap();
+
+    let last_episode = analyzer.last_episode(&path, Some(&media));
+    if let Some(last) = last_episode {
+        println!("last episode: {}", last);
+    }
+
+    let file = harvester::ScannedFile {
+        metadata: Some(media),
+        group: analyzer.release_group(&path),
+        version: analyzer.version(&path),
+        last_episode,
+        path,
+        inode: 0,
+        size: 0,
+        links: 1,
+        warning: None,
+        broken: None,
+    };
+
+    let target = config.target.clone().unwrap_or_else(|| "<target>".into());
+    match Layout::new(&config, &incoming).link_path(&file, &target) {
+        Some(link) => println!("link: {:?}", link),
+        None => println!("link: none, not linked"),
     }
 }
 
-struct DryRunner {}
-impl Runner for DryRunner {
-    fn remove_dir(&self, _path: &std::path::PathBuf) {}
-    fn remove_file(&self, _path: &std::path::PathBuf) {}
-    fn create_dir_all(&self, _path: &std::path::PathBuf) {}
-    fn hard_link(&self, _original: &std::path::PathBuf, _link: &std::path::PathBuf) {}
+fn conflicts_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut config_path = None;
+    let mut profile = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let incoming = match &positional[..] {
+        [incoming] => std::path::PathBuf::from(incoming),
+        [] => config.incoming.clone().unwrap_or_else(|| fail("usage: harvester conflicts [<incoming>] [--config <file>] [--profile <name>]")),
+        _ => fail("usage: harvester conflicts [<incoming>] [--config <file>] [--profile <name>]"),
+    };
+
+    let overrides = Overrides::load(&incoming).unwrap_or_else(|e| fail(&e));
+    let scanned_files = overrides.analyze_directory(&config, &incoming).unwrap_or_else(|e| fail(&e));
+    let conflicts = overrides.conflicts(&config, &scanned_files).unwrap_or_else(|e| fail(&e));
+
+    for (path, candidates) in conflicts.iter() {
+        println!("{:?}", path);
+        for (i, (rule, outcome)) in candidates.iter().enumerate() {
+            let outcome = outcome.as_ref().map_or("nothing extracted".to_string(), |m| m.to_string());
+            println!("  {} {}: {}", if i == 0 { "*" } else { " " }, rule, outcome);
+        }
+    }
+    println!("{} names where the rule order decides what they are.", conflicts.len());
 }
 
-fn create_links(runner: &dyn Runner, files: &Vec<ScannedFile>, target_dir: &std::path::PathBuf) -> Vec<(std::path::PathBuf, std::path::PathBuf)> {
-    let mut links = vec![];
+fn debug_bundle_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut config_path = None;
+    let mut profile = None;
+    let mut anonymize = false;
+    let mut output = None;
 
-    for file in files.iter() {
-        let link = match &file.metadata {
-            Some(MediaData::ShowEpisode { name, season, episode }) => {
-                target_dir
-                    .join("shows")
-                    .join(name)
-                    .join(format!("Season {}", season))
-                    .join(format!("episode {}.{}", episode, file.path.extension().unwrap().to_str().unwrap()))
-            },
-            Some(MediaData::Movie { title, year }) => {
-                target_dir
-                    .join("movies")
-                    .join(match year {
-                        Some(y) => format!("{} ({})", title, y),
-                        None => title.to_string(),
-                    })
-                    .join(format!("movie.{}", file.path.extension().unwrap().to_str().unwrap()))
-            },
-            _ => { continue; }
-        };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            "--anonymize" => anonymize = true,
+            "--output" => output = Some(args.next().map(std::path::PathBuf::from).unwrap_or_else(|| fail("--output needs a file"))),
+            _ => positional.push(arg),
+        }
+    }
+
+    let config = load_config(config_path.clone(), profile.as_deref());
+    let Some((incoming, target)) = directories(&positional, &config) else {
+        fail("usage: harvester debug-bundle [<incoming> <target>] [--anonymize] [--output <file>] [--config <file>] [--profile <name>]");
+    };
+    let output = output.unwrap_or_else(|| format!("harvester-debug-{}.tar.gz", state::now()).into());
+
+    let dir = std::env::temp_dir().join(format!("harvester-debug-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap_or_else(|e| fail(&format!("unable to create {:?}: {}", dir, e)));
+    let write = |name: &str, contents: &str| {
+        std::fs::write(dir.join(name), contents).unwrap_or_else(|e| fail(&format!("unable to write {:?}: {}", dir.join(name), e)));
+    };
+
+    write("version.txt", &harvester::bundle::version_info());
+
+    if let Some(path) = self::config_path(config_path) {
+        let contents = std::fs::read_to_string(&path).unwrap_or_else(|e| fail(&format!("unable to read {:?}: {}", path, e)));
+        let mut value = contents.parse::<toml::Value>().unwrap_or_else(|e| fail(&format!("invalid config {:?}: {}", path, e)));
+        harvester::bundle::redact(&mut value);
+        write("config.toml", &toml::to_string(&value).unwrap());
+    }
+
+    let layout = Layout::new(&config, &incoming);
+    let recording = harvester::replay::record(&incoming, &target, &layout.roots(&target));
+    let recording = if anonymize { harvester::bundle::anonymize(&recording) } else { recording };
+    write("recording.json", &serde_json::to_string_pretty(&recording).unwrap());
+
+    // Parsed names and warnings in the history have titles in them.
+    if !anonymize {
+        let (_, state) = load_state(&config);
+        let recent = &state.history[state.history.len().saturating_sub(200)..];
+        write("history.json", &serde_json::to_string_pretty(recent).unwrap());
+    }
+
+    let archived = harvester::bundle::archive(&dir, &output);
+    let _ = std::fs::remove_dir_all(&dir);
+    archived.unwrap_or_else(|e| fail(&e));
+
+    println!("wrote {:?}, {} files recorded{}", output, recording.files.len(), if anonymize { ", names anonymized" } else { "" });
+}
+
+/// Applies what's left of an interrupted run's plan, from its journal.
+fn resume_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut dry_run = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry" => dry_run = true,
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let (store, mut state) = load_state(&config);
+    let journal_path = journal::path(store.path());
+    let Some((journal, done)) = Journal::load(&journal_path).unwrap_or_else(|e| fail(&e)) else {
+        println!("no interrupted run to resume");
+        return;
+    };
 
-        if !link.exists() {
-            println!("creating hard link: {:?}", link);
-            links.push((file.path.clone(), link.clone()));
-            runner.create_dir_all(&link.parent().unwrap().to_path_buf());
-            runner.hard_link(&file.path, &link);
+    let mut plan = journal.remaining(&done);
+    println!(
+        "resuming the interrupted run: {} files to remove, {} links to make",
+        plan.deletes.len(),
+        plan.creates.len(),
+    );
+    if dry_run {
+        for file in plan.deletes.iter() {
+            println!("  removing file {:?}", file);
+        }
+        for create in plan.creates.iter() {
+            println!("  creating {:?}", create.link);
         }
+        return;
+    }
+
+    let runner: Box<dyn Runner> = match config.durable {
+        true => Box::new(DurableRunner::default()),
+        false => Box::new(RealRunner {}),
+    };
+    let runner: Box<dyn Runner> = match (config.read_only_incoming, &config.incoming) {
+        (true, Some(incoming)) => Box::new(ReadOnlyRunner::new(runner, incoming)),
+        _ => runner,
+    };
+
+    let output = Output::default();
+    interrupt::catch();
+    let journaled = JournalRunner::open(runner.as_ref(), &journal_path).unwrap_or_else(|e| fail(&e));
+    let applied = plan::apply(&plan, &journaled, &output);
+    output.flush(false);
+    plan.forget(&applied);
+
+    let linked = plan.added().map(|c| (c.source.clone(), c.link.clone())).collect::<Vec<_>>();
+    let unlinked = plan.removed().cloned().collect::<Vec<_>>();
+    state.record_changes(&linked, &unlinked, state::now());
+    let links = journal.links.into_iter().filter(|(_, link, _)| applied.is_done(link)).collect();
+    state.record_links(links, state::now());
+    store.save(&state).unwrap_or_else(|e| fail(&e));
+    interrupt::release();
+
+    for failure in applied.failures.iter() {
+        eprintln!("{}", failure.error);
+    }
+    if !applied.skipped.is_empty() {
+        println!("interrupted again, {} changes left, run harvester resume to finish them", applied.skipped.len());
+        std::process::exit(130);
     }
 
-    links
+    Journal::finish(&journal_path).unwrap_or_else(|e| eprintln!("{}", e));
+    println!("{} links made, {} files removed, {} failed.", linked.len(), unlinked.len(), applied.failures.len());
 }
 
-fn remove_empty_directories(runner: &dyn Runner, path: &std::path::PathBuf) -> bool {
-    let mut is_empty = true;
-    for subdir in path.read_dir().unwrap() {
-        let subpath = subdir.unwrap().path();
-        if subpath.is_dir() {
-            let sub_is_empty = remove_empty_directories(runner, &subpath);
-            if sub_is_empty {
-                println!("removing directory {:?}", subpath);
-                runner.remove_dir(&subpath);
-            } else {
-                is_empty = false;
+/// Puts the library and the state back the way they were before the run a backup was taken for.
+fn rollback_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut dry_run = false;
+    let mut to = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry" => dry_run = true,
+            "--to" => to = Some(args.next().and_then(|t| t.parse::<u64>().ok()).unwrap_or_else(|| fail("--to needs a backup, see harvester rollback"))),
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let store = config.state_store().unwrap_or_else(|| fail("unable to determine state directory, set state_dir"));
+    let dir = backup::dir(store.path());
+
+    let Some(to) = to else {
+        let names = backup::list(&dir);
+        if names.is_empty() {
+            println!("no backups in {:?}", dir);
+        }
+        for name in names {
+            match Backup::load(&dir, name) {
+                Ok(b) => println!("{}: {} links removed, {} made", name, b.plan.removed().count(), b.plan.added().count()),
+                Err(e) => eprintln!("{}", e),
             }
-        } else {
-            is_empty = false;
         }
+        return;
+    };
+
+    let backup = Backup::load(&dir, to).unwrap_or_else(|e| fail(&e));
+    let runner: Box<dyn Runner> = match config.durable {
+        true => Box::new(DurableRunner::default()),
+        false => Box::new(RealRunner {}),
+    };
+    let undo = backup.undo(runner.as_ref());
+    println!("rolling back to {}: {} links to remove, {} to make again", to, undo.deletes.len(), undo.creates.len());
+    if dry_run {
+        for link in undo.deletes.iter() {
+            println!("  removing {:?}", link);
+        }
+        for create in undo.creates.iter() {
+            println!("  linking {:?} to {:?}", create.link, create.source);
+        }
+        return;
+    }
+
+    // The state first, an interrupted rollback is finished by harvester resume on top of it.
+    store.save(&backup.state).unwrap_or_else(|e| fail(&e));
+
+    let journal_path = journal::path(store.path());
+    let records = undo.creates.iter().filter_map(|c| Some((c.source.clone(), c.link.clone(), runner.inode(&c.source)?))).collect();
+    Journal::new(&undo, records, state::now()).begin(&journal_path).unwrap_or_else(|e| fail(&e));
+
+    let output = Output::default();
+    interrupt::catch();
+    let journaled = JournalRunner::open(runner.as_ref(), &journal_path).unwrap_or_else(|e| fail(&e));
+    let applied = plan::apply(&undo, &journaled, &output);
+    output.flush(false);
+    interrupt::release();
+
+    for failure in applied.failures.iter() {
+        eprintln!("{}", failure.error);
+    }
+    if !applied.skipped.is_empty() {
+        println!("interrupted, {} changes left, run harvester resume to finish them", applied.skipped.len());
+        std::process::exit(130);
     }
 
-    is_empty
+    Journal::finish(&journal_path).unwrap_or_else(|e| eprintln!("{}", e));
+    println!("rolled back to {}, the next run makes the same changes again unless the config or the incoming directory changed", to);
 }
 
-fn remove_hardlinks(runner: &dyn Runner, source: &Vec<ScannedFile>, target_dir: &std::path::PathBuf) {
-    let source_inodes = source.iter().map(|f| f.inode).collect::<std::collections::HashSet<_>>();
+fn verify_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut repair = false;
 
-    for file in find_all_files(target_dir) {
-        let inode = std::fs::metadata(&file).unwrap().ino();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--repair" => repair = true,
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let config = load_config(config_path, profile.as_deref());
+    let (store, mut state) = load_state(&config);
+
+    let problems = verify::verify(&state, config.link_mode);
+    for (record, problem) in problems.iter() {
+        println!("{}: {:?} (from {:?})", problem, record.link, record.source);
+    }
+    println!("{} links checked, {} problems.", state.links.len(), problems.len());
 
-        if source_inodes.contains(&inode) {
-            println!("removing file {:?}", file);
-            runner.remove_file(&file);
-        } else {
-            eprintln!("extra file found: {:?}", file);
+    let mut unrepaired = problems.len();
+    if repair && !problems.is_empty() {
+        unrepaired = problems.iter().filter(|(_, p)| *p != verify::Problem::Missing).count();
+
+        for repair in verify::repair(&mut state, config.link_mode, config.deleted_links, &RealRunner {}, state::now()) {
+            match repair {
+                verify::Repair::Recreated(link) => println!("recreated {:?}", link),
+                verify::Repair::Tombstoned(link) => println!("not linking {:?} again", link),
+                verify::Repair::Skipped(link) => {
+                    println!("not recreating {:?}, run harvester to write it again", link);
+                    unrepaired += 1;
+                }
+                verify::Repair::Failed(link, e) => {
+                    eprintln!("unable to recreate {:?}: {}", link, e);
+                    unrepaired += 1;
+                }
+            }
         }
+        store.save(&state).unwrap_or_else(|e| fail(&e));
+    }
+
+    if unrepaired > 0 {
+        std::process::exit(1);
     }
 }
 
-fn main() {
-    let args = std::env::args().collect::<Vec<_>>();
+fn restore_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut query = None;
 
-    if args.len() < 3 {
-        eprintln!("usage: harvester <incoming> <jellyfin> [--dry]");
-        return;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ if query.is_none() => query = Some(arg.as_str()),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
     }
 
-    let incoming = std::path::PathBuf::from(&args[1]);
-    let jellyfin = std::path::PathBuf::from(&args[2]);
-    let dry_run = args.len() > 3 && args[3] == "--dry";
+    let config = load_config(config_path, profile.as_deref());
+    let (store, mut state) = load_state(&config);
 
-    let scanned_files = Analyzer::new().analyze_directory(&incoming);
+    let restored = state.restore(query);
+    if restored.is_empty() {
+        fail("no matching tombstones");
+    }
 
-    let runner: Box<dyn Runner> = if dry_run {
-        Box::new(DryRunner {})
-    } else {
-        Box::new(RealRunner {})
+    for tombstone in restored.iter() {
+        println!("{:?} will be linked again", tombstone.source);
+    }
+    store.save(&state).unwrap_or_else(|e| fail(&e));
+}
+
+fn history_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut query = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ if query.is_none() => query = Some(arg.as_str()),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let query = query.unwrap_or_else(|| fail("usage: harvester history <path|show> [--config <file>] [--profile <name>]"));
+    let config = load_config(config_path, profile.as_deref());
+    let (_, state) = load_state(&config);
+
+    let history = state.history_of(query);
+    if history.is_empty() {
+        fail(&format!("no history for {:?}", query));
+    }
+
+    for (source, records) in history {
+        println!("{:?}", source);
+        for record in records {
+            println!("  {}  {}", schedule::LocalTime::at(record.at), record.event);
+        }
+    }
+}
+
+fn rename_show_command(args: &[String]) {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut dry_run = false;
+    let mut positional = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry" => dry_run = true,
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let [old, new] = positional[..] else {
+        fail("usage: harvester rename-show <old name> <new name> [--dry] [--config <file>] [--profile <name>]");
     };
+    let config = load_config(config_path, profile.as_deref());
+    let (store, mut state) = load_state(&config);
+
+    let layout = Layout::new(&config, config.incoming.as_deref().unwrap_or(std::path::Path::new(""))).with_tmdb_lookups(state.tmdb.clone());
+    let (from, to) = (layout.show_folder(state.alias(old).unwrap_or(old)), layout.show_folder(new));
+    if let Some(error) = layout.lookup_errors().first() {
+        fail(&format!("{}, not renaming anything", error));
+    }
+    let folders = state.link_dirs_named(&from);
+    if folders.is_empty() {
+        fail(&format!("no linked show folder named {:?}", from));
+    }
+
+    for folder in folders.iter() {
+        let renamed = folder.with_file_name(&to);
+        if renamed.exists() && !renamed.eq(folder) {
+            fail(&format!("{:?} already exists, not renaming anything", renamed));
+        }
+    }
+
+    for folder in folders {
+        let renamed = folder.with_file_name(&to);
+        println!("{}{:?} -> {:?}", if dry_run { "would rename " } else { "" }, folder, renamed);
+        if !dry_run {
+            std::fs::rename(&folder, &renamed).unwrap_or_else(|e| fail(&format!("unable to rename {:?}: {}", folder, e)));
+            state.move_links(&folder, &renamed);
+        }
+    }
+
+    if !dry_run {
+        state.rename_show(old, new);
+        if let Some(lookups) = layout.tmdb_lookups() {
+            state.tmdb = lookups;
+        }
+        store.save(&state).unwrap_or_else(|e| fail(&e));
+        println!("future episodes of {:?} go to {:?}", old, to);
+    }
+}
+
+fn move_episodes_command(args: &[String]) {
+    const USAGE: &str = "usage: harvester move-episodes [<incoming> <jellyfin>] --show <name> --from-season <season> --range <first>-<last> --to-season <season> [--dry] [--config <file>] [--profile <name>]";
+    let mut config_path = None;
+    let mut profile = None;
+    let mut dry_run = false;
+    let (mut show, mut from_season, mut range, mut to_season) = (None, None, None, None);
+    let mut positional = vec![];
+
+    let number = |arg: Option<&String>| arg.and_then(|n| n.parse::<u32>().ok()).unwrap_or_else(|| fail(USAGE));
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--show" => show = Some(args.next().unwrap_or_else(|| fail(USAGE)).clone()),
+            "--from-season" => from_season = Some(number(args.next())),
+            "--to-season" => to_season = Some(number(args.next())),
+            "--range" => {
+                let parsed = args.next().and_then(|r| r.split_once('-')).and_then(|(a, b)| Some((a.parse::<u32>().ok()?, b.parse::<u32>().ok()?)));
+                range = Some(parsed.filter(|(a, b)| a <= b).unwrap_or_else(|| fail("--range needs episodes like 13-24")));
+            }
+            "--dry" => dry_run = true,
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            _ => positional.push(arg),
+        }
+    }
+
+    let (Some(show), Some(from_season), Some((first, last)), Some(to_season)) = (show, from_season, range, to_season) else {
+        fail(USAGE);
+    };
+    let config = load_config(config_path, profile.as_deref());
+    let (incoming, jellyfin) = directories(&positional, &config).unwrap_or_else(|| fail(USAGE));
+    let (store, mut state) = load_state(&config);
+
+    let overrides = Overrides::load(&incoming).unwrap_or_else(|e| fail(&e));
+    let scanned_files = overrides.analyze_directory(&config, &incoming).unwrap_or_else(|e| fail(&e));
+    let layout = library_layout(&config, &incoming, overrides, &state).unwrap_or_else(|e| fail(&e));
+    let before = layout.link_paths(&scanned_files, &jellyfin);
+    if let Some(error) = layout.lookup_errors().first() {
+        fail(&format!("{}, not moving anything", error));
+    }
+
+    state.renumber(&show, from_season, first, last, to_season);
+    let layout = layout.with_renumbering(state.renumbering().unwrap_or_else(|e| fail(&e)));
+    let after = layout.link_paths(&scanned_files, &jellyfin);
+
+    // The state has sources canonicalized and links absolute, like runs record them.
+    let absolute = |p: &std::path::Path| std::path::absolute(p).unwrap_or(p.to_path_buf());
+    let moves = before
+        .into_iter()
+        .zip(after)
+        .filter(|((file, from), (moved, to))| file.path == moved.path && from != to)
+        .map(|((file, from), (_, to))| (std::fs::canonicalize(&file.path).unwrap_or(file.path.clone()), absolute(&from), absolute(&to)))
+        .filter(|(source, from, _)| state.has_link(source, from))
+        .map(|(_, from, to)| (from, to))
+        .collect::<Vec<_>>();
+    if moves.is_empty() {
+        fail(&format!("no linked episodes of {} season {} match {}-{}, not changing anything", show, from_season, first, last));
+    }
+    if let Some((_, to)) = moves.iter().find(|(_, to)| to.exists()) {
+        fail(&format!("{:?} already exists, not moving anything", to));
+    }
+
+    for (from, to) in moves.iter() {
+        println!("{}{:?} -> {:?}", if dry_run { "would move " } else { "" }, from, to);
+        if !dry_run {
+            std::fs::create_dir_all(to.parent().unwrap()).unwrap_or_else(|e| fail(&format!("unable to create {:?}: {}", to.parent().unwrap(), e)));
+            std::fs::rename(from, to).unwrap_or_else(|e| fail(&format!("unable to move {:?}: {}", from, e)));
+            state.move_links(from, to);
+            // The season folder it came from, once it's empty.
+            let _ = std::fs::remove_dir(from.parent().unwrap());
+        }
+    }
+
+    if !dry_run {
+        store.save(&state).unwrap_or_else(|e| fail(&e));
+        println!("moved {} episodes, future ones go to season {} too", moves.len(), to_season);
+    }
+}
+
+fn state_command(args: &[String]) {
+    let mut positional = vec![];
+    let mut config_path = None;
+    let mut profile = None;
+    let mut replace = false;
+    let mut rebases = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => config_path = args.next().map(std::path::PathBuf::from),
+            "--profile" => profile = args.next().cloned(),
+            "--replace" => replace = true,
+            "--rebase" => {
+                let rebase = args.next().and_then(|r| r.split_once('=')).unwrap_or_else(|| fail("--rebase needs <old prefix>=<new prefix>"));
+                rebases.push((std::path::PathBuf::from(rebase.0), std::path::PathBuf::from(rebase.1)));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
 
-    remove_hardlinks(runner.as_ref(), &scanned_files, &jellyfin);
-    create_links(runner.as_ref(), &scanned_files, &jellyfin);
-    remove_empty_directories(runner.as_ref(), &jellyfin);
+    let config = load_config(config_path, profile.as_deref());
+    if positional[..] == ["check"] {
+        return state_check(&config);
+    }
+    let (store, mut state) = load_state(&config);
+
+    match positional[..] {
+        ["export"] => println!("{}", serde_json::to_string_pretty(&state.snapshot(state::now())).unwrap()),
+        ["export", file] => {
+            let contents = serde_json::to_string_pretty(&state.snapshot(state::now())).unwrap();
+            std::fs::write(file, contents).unwrap_or_else(|e| fail(&format!("unable to write {:?}: {}", file, e)));
+        }
+        ["import", file] => {
+            let contents = std::fs::read_to_string(file).unwrap_or_else(|e| fail(&format!("unable to read {:?}: {}", file, e)));
+            let snapshot = serde_json::from_str(&contents).unwrap_or_else(|e| fail(&format!("invalid snapshot {:?}: {}", file, e)));
+            state.import(snapshot, &rebases, replace).unwrap_or_else(|e| fail(&e));
+            store.save(&state).unwrap_or_else(|e| fail(&e));
+            println!("imported {:?}, {} links recorded", file, state.links.len());
+        }
+        _ => fail("usage: harvester state export [<file>] | import <file> [--replace] [--rebase <old>=<new>] | check [--config <file>] [--profile <name>]"),
+    }
+}
+
+/// Whether this harvester can use the state, what upgrading it takes, and whether it's consistent.
+fn state_check(config: &Config) {
+    let store = config.state_store().unwrap_or_else(|| fail("unable to determine state directory, set state_dir"));
+    let Some(schema) = store.schema().unwrap_or_else(|e| fail(&e)) else {
+        println!("no state in {:?} yet", store.path());
+        return;
+    };
+
+    println!("state {:?} has schema version {}, this harvester uses {}", store.path(), schema, state::SCHEMA_VERSION);
+    if schema > state::SCHEMA_VERSION {
+        fail("it's from a newer harvester, upgrade to use it");
+    }
+    if schema < state::SCHEMA_VERSION {
+        println!("{} migrations to apply, the next run does it and keeps a copy of the current file", state::SCHEMA_VERSION - schema);
+    }
+
+    let state = store.load().unwrap_or_else(|e| fail(&e));
+    let problems = state.problems();
+    for problem in problems.iter() {
+        println!("  {}", problem);
+    }
+    match problems.len() {
+        0 => println!("{} links, {} tombstones, no problems found", state.links.len(), state.tombstones.len()),
+        n => fail(&format!("{} problems found", n)),
+    }
+}
+
+fn daemon_command(args: &[String]) {
+    let mut run_args = vec![];
+    let mut interval = 300;
+    let mut listen = daemon::DEFAULT_LISTEN.to_string();
+    let mut socket = daemon::default_socket();
+    let mut config_path = None;
+    let mut profile = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => interval = args.next().and_then(|i| i.parse::<u64>().ok()).unwrap_or_else(|| fail("--interval needs a number of seconds")),
+            "--listen" => listen = args.next().cloned().unwrap_or_else(|| fail("--listen needs an address")),
+            "--socket" => socket = args.next().map(std::path::PathBuf::from),
+            "--config" => {
+                config_path = args.next().map(std::path::PathBuf::from);
+                run_args.push(arg.clone());
+                run_args.extend(config_path.iter().map(|p| p.to_string_lossy().to_string()));
+            }
+            "--profile" => {
+                profile = args.next().cloned();
+                run_args.push(arg.clone());
+                run_args.extend(profile.clone());
+            }
+            _ => run_args.push(arg.clone()),
+        }
+    }
+
+    let listener = std::net::TcpListener::bind(&listen).unwrap_or_else(|e| fail(&format!("unable to listen on {}: {}", listen, e)));
+    let daemon = std::sync::Arc::new(daemon::Daemon::default());
+    println!("health checks on http://{}/healthz and /readyz", listen);
+
+    let server = daemon.clone();
+    std::thread::spawn(move || daemon::serve(listener, server));
+
+    let socket = socket.unwrap_or_else(|| fail("unable to determine control socket path, use --socket"));
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent).unwrap_or_else(|e| fail(&format!("unable to create {:?}: {}", parent, e)));
+    }
+    if daemon::send(&socket, "status").is_ok() {
+        fail(&format!("another daemon is listening on {:?}", socket));
+    }
+    let _ = std::fs::remove_file(&socket);
+    let control = std::os::unix::net::UnixListener::bind(&socket).unwrap_or_else(|e| fail(&format!("unable to listen on {:?}: {}", socket, e)));
+    println!("control socket on {:?}", socket);
+
+    let (store, controller) = (load_config(config_path, profile.as_deref()).state_store(), daemon.clone());
+    std::thread::spawn(move || daemon::control(control, controller, store));
+
+    loop {
+        if !daemon.status().paused {
+            daemon.run_started();
+            match run_command(&run_args) {
+                Ok(Outcome::Finished) => daemon.run_finished(state::now()),
+                Ok(Outcome::Usage) => std::process::exit(1),
+                Ok(Outcome::Interrupted) => std::process::exit(130),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    daemon.run_failed(state::now(), &e);
+                }
+            }
+        }
+
+        daemon.wait(std::time::Duration::from_secs(interval));
+    }
+}
+
+fn ctl_command(args: &[String]) {
+    let mut socket = daemon::default_socket();
+    let mut command = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => socket = args.next().map(std::path::PathBuf::from),
+            _ if command.is_none() => command = Some(arg.as_str()),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let command = command.unwrap_or_else(|| fail("usage: harvester ctl status|rescan|pause|resume|dump-state [--socket <path>]"));
+    let socket = socket.unwrap_or_else(|| fail("unable to determine control socket path, use --socket"));
+
+    match daemon::send(&socket, command) {
+        Ok(response) => print!("{}", response),
+        Err(e) => fail(&e),
+    }
+}
+
+fn health_command(args: &[String]) {
+    let mut listen = daemon::DEFAULT_LISTEN.to_string();
+    let mut path = "/readyz";
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--live" => path = "/healthz",
+            "--listen" => listen = args.next().cloned().unwrap_or_else(|| fail("--listen needs an address")),
+            _ => fail(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    match daemon::check(&listen, path) {
+        Ok(body) => print!("{}", body),
+        Err(e) => fail(&e),
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match args.first().map(String::as_str) {
+        Some("init") => init_command(&args[1..]),
+        Some("parse") => parse_command(&args[1..]),
+        Some("conflicts") => conflicts_command(&args[1..]),
+        Some("debug-bundle") => debug_bundle_command(&args[1..]),
+        Some("diff") => diff_command(&args[1..]),
+        Some("doctor") => doctor_command(&args[1..]),
+        Some("du") => du_command(&args[1..]),
+        Some("ls") => ls_command(&args[1..]),
+        Some("retire") => retire_command(&args[1..]),
+        Some("daemon") => daemon_command(&args[1..]),
+        Some("health") => health_command(&args[1..]),
+        Some("ctl") => ctl_command(&args[1..]),
+        Some("verify") => verify_command(&args[1..]),
+        Some("resume") => resume_command(&args[1..]),
+        Some("rollback") => rollback_command(&args[1..]),
+        Some("restore") => restore_command(&args[1..]),
+        Some("history") => history_command(&args[1..]),
+        Some("rename-show") => rename_show_command(&args[1..]),
+        Some("move-episodes") => move_episodes_command(&args[1..]),
+        Some("missing") => missing_command(&args[1..]),
+        Some("state") => state_command(&args[1..]),
+        _ => match run_command(&args) {
+            Ok(Outcome::Interrupted) => std::process::exit(130),
+            Ok(Outcome::Usage | Outcome::Finished) => {}
+            Err(e) => fail(&e),
+        },
+    }
 }
@@ -0,0 +1,87 @@
+//! Stream details for naming templates, read from `ffprobe -show_streams` output.
+
+pub const VARIABLES: &[&str] = &["videoCodec", "audioCodec", "audioLang", "audioChannels", "hdr", "resolution"];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MediaInfo {
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub audio_languages: Vec<String>,
+    pub audio_channels: Option<u64>,
+    /// `DV`, `HDR10` or `HLG`.
+    pub hdr: Option<String>,
+    pub height: Option<u64>,
+}
+
+impl MediaInfo {
+    pub fn probe(path: &std::path::Path) -> Result<Self, String> {
+        let output = std::process::Command::new("ffprobe")
+            .args(["-v", "error", "-print_format", "json", "-show_streams"])
+            .arg(path)
+            .output()
+            .map_err(|e| format!("unable to run ffprobe: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!("ffprobe failed on {:?}: {}", path, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        Self::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let probe: serde_json::Value = serde_json::from_str(json).map_err(|e| format!("invalid ffprobe output: {}", e))?;
+        let streams = probe["streams"].as_array().cloned().unwrap_or_default();
+        let of_type = |kind: &str| streams.iter().filter(move |s| s["codec_type"] == kind).collect::<Vec<_>>();
+
+        let video = of_type("video").into_iter().find(|s| s["disposition"]["attached_pic"] != 1);
+        let audio = of_type("audio");
+
+        let dolby_vision = video.is_some_and(|v| {
+            v["side_data_list"].as_array().is_some_and(|l| l.iter().any(|d| d["side_data_type"] == "DOVI configuration record"))
+        });
+        let hdr = match video.and_then(|v| v["color_transfer"].as_str()) {
+            _ if dolby_vision => Some("DV"),
+            Some("smpte2084") => Some("HDR10"),
+            Some("arib-std-b67") => Some("HLG"),
+            _ => None,
+        };
+
+        let mut audio_languages = vec![];
+        for language in audio.iter().filter_map(|a| a["tags"]["language"].as_str()) {
+            if !audio_languages.iter().any(|l| l == language) {
+                audio_languages.push(language.to_string());
+            }
+        }
+
+        Ok(Self {
+            video_codec: video.and_then(|v| v["codec_name"].as_str()).map(str::to_uppercase),
+            audio_codec: audio.first().and_then(|a| a["codec_name"].as_str()).map(str::to_uppercase),
+            audio_languages,
+            audio_channels: audio.first().and_then(|a| a["channels"].as_u64()),
+            hdr: hdr.map(str::to_string),
+            height: video.and_then(|v| v["height"].as_u64()),
+        })
+    }
+
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "videoCodec" => self.video_codec.clone(),
+            "audioCodec" => self.audio_codec.clone(),
+            "audioLang" => Some(self.audio_languages.join("+")).filter(|l| !l.is_empty()),
+            "audioChannels" => self.audio_channels.map(|c| match c {
+                1 => "1.0".to_string(),
+                2 => "2.0".to_string(),
+                c if c > 2 => format!("{}.1", c - 1),
+                c => c.to_string(),
+            }),
+            "hdr" => self.hdr.clone(),
+            "resolution" => self.height.map(|h| match h {
+                h if h > 1600 => "2160p".to_string(),
+                h if h > 900 => "1080p".to_string(),
+                h if h > 600 => "720p".to_string(),
+                h => format!("{}p", h),
+            }),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! An in-memory filesystem behind `Runner`, so planning and applying can be tested without
+//! touching the disk.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+use crate::Runner;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    Dir,
+    /// Hard links share the inode, and the contents along with it.
+    File { inode: u64 },
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    entries: RefCell<BTreeMap<std::path::PathBuf, Entry>>,
+    contents: RefCell<BTreeMap<u64, String>>,
+    last_inode: Cell<u64>,
+}
+
+impl MemoryFs {
+    /// Creates the file and its parent directories, returning its inode.
+    pub fn add_file(&self, path: &std::path::Path, contents: &str) -> u64 {
+        self.create_dir_all(path.parent().unwrap()).unwrap();
+
+        let inode = self.last_inode.get() + 1;
+        self.last_inode.set(inode);
+        self.contents.borrow_mut().insert(inode, contents.to_string());
+        self.entries.borrow_mut().insert(path.to_path_buf(), Entry::File { inode });
+
+        inode
+    }
+
+    /// Creates the file with a given inode, a hard link to any other file with the same one.
+    pub fn insert_file(&self, path: &std::path::Path, inode: u64, contents: &str) {
+        self.create_dir_all(path.parent().unwrap()).unwrap();
+
+        self.last_inode.set(self.last_inode.get().max(inode));
+        self.contents.borrow_mut().entry(inode).or_insert_with(|| contents.to_string());
+        self.entries.borrow_mut().insert(path.to_path_buf(), Entry::File { inode });
+    }
+
+    /// Every file, sorted.
+    pub fn files(&self) -> Vec<std::path::PathBuf> {
+        self.entries.borrow().iter().filter(|(_, e)| **e != Entry::Dir).map(|(p, _)| p.clone()).collect()
+    }
+
+    /// Every directory, sorted.
+    pub fn dirs(&self) -> Vec<std::path::PathBuf> {
+        self.entries.borrow().iter().filter(|(_, e)| **e == Entry::Dir).map(|(p, _)| p.clone()).collect()
+    }
+
+    fn entry(&self, path: &std::path::Path) -> Option<Entry> {
+        self.entries.borrow().get(path).cloned()
+    }
+}
+
+fn error(kind: std::io::ErrorKind, message: String) -> std::io::Error {
+    std::io::Error::new(kind, message)
+}
+
+/// Fails the way the real filesystem would on misuse, like linking over an existing file.
+impl Runner for MemoryFs {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        if self.entry(path) != Some(Entry::Dir) {
+            return Err(error(std::io::ErrorKind::NotADirectory, format!("{:?} isn't a directory", path)));
+        }
+        if !self.list_dir(path).is_empty() {
+            return Err(error(std::io::ErrorKind::DirectoryNotEmpty, format!("{:?} isn't empty", path)));
+        }
+        self.entries.borrow_mut().remove(path);
+        Ok(())
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        if !matches!(self.entry(path), Some(Entry::File { .. })) {
+            return Err(error(std::io::ErrorKind::NotFound, format!("{:?} isn't a file", path)));
+        }
+        self.entries.borrow_mut().remove(path);
+        Ok(())
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let missing = path.ancestors().filter(|d| !d.as_os_str().is_empty()).take_while(|d| self.entry(d) != Some(Entry::Dir)).collect::<Vec<_>>();
+        if let Some(file) = missing.iter().find(|d| self.entry(d).is_some()) {
+            return Err(error(std::io::ErrorKind::NotADirectory, format!("{:?} is a file", file)));
+        }
+        for dir in missing {
+            self.entries.borrow_mut().insert(dir.to_path_buf(), Entry::Dir);
+        }
+        Ok(())
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        let Some(Entry::File { inode }) = self.entry(original) else {
+            return Err(error(std::io::ErrorKind::NotFound, format!("{:?} isn't a file", original)));
+        };
+        if self.entry(link).is_some() {
+            return Err(error(std::io::ErrorKind::AlreadyExists, format!("{:?} already exists", link)));
+        }
+        if link.parent().and_then(|p| self.entry(p)) != Some(Entry::Dir) {
+            return Err(error(std::io::ErrorKind::NotFound, format!("{:?} has no parent directory", link)));
+        }
+        self.entries.borrow_mut().insert(link.to_path_buf(), Entry::File { inode });
+        Ok(())
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        match self.entry(path) {
+            Some(Entry::File { inode }) => { self.contents.borrow_mut().insert(inode, contents.to_string()); }
+            _ => { self.add_file(path, contents); }
+        }
+        Ok(())
+    }
+
+    fn exists(&self, path: &std::path::Path) -> bool {
+        self.entry(path).is_some()
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        self.entry(path) == Some(Entry::Dir)
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        self.entries.borrow().keys().filter(|p| p.parent() == Some(path)).cloned().collect()
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        match self.entry(path)? {
+            Entry::File { inode } => Some(inode),
+            Entry::Dir => None,
+        }
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        let inode = self.inode(path)?;
+        self.contents.borrow().get(&inode).cloned()
+    }
+}
@@ -0,0 +1,138 @@
+//! Network filesystems (rclone, NFS, SMB mounts) under the incoming or target directories: their
+//! operations get retried, and an empty-looking mount is taken as not mounted.
+
+use std::os::unix::fs::MetadataExt;
+
+/// Filesystem types whose operations can fail transiently.
+const NETWORK: [&str; 8] = ["nfs", "nfs4", "cifs", "smb3", "fuse.rclone", "fuse.sshfs", "9p", "fuse.mergerfs"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mount {
+    /// The device, or `host:/export` and the like for network filesystems.
+    pub source: String,
+    pub point: std::path::PathBuf,
+    pub fstype: String,
+}
+
+impl Mount {
+    pub fn is_network(&self) -> bool {
+        NETWORK.contains(&self.fstype.as_str())
+    }
+}
+
+/// `/proc/mounts` contents, with its octal escapes for spaces and the like undone.
+pub fn parse(contents: &str) -> Vec<Mount> {
+    let unescape = |field: &str| {
+        let mut out = String::new();
+        let mut rest = field;
+        while let Some(i) = rest.find('\\') {
+            out.push_str(&rest[..i]);
+            match rest.get(i + 1..i + 4).and_then(|o| u8::from_str_radix(o, 8).ok()) {
+                Some(c) => { out.push(c as char); rest = &rest[i + 4..]; }
+                None => { out.push('\\'); rest = &rest[i + 1..]; }
+            }
+        }
+        out + rest
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(' ').collect::<Vec<_>>();
+            Some(Mount { source: unescape(fields.first()?), point: unescape(fields.get(1)?).into(), fstype: fields.get(2)?.to_string() })
+        })
+        .collect()
+}
+
+pub fn mounts() -> Vec<Mount> {
+    std::fs::read_to_string("/proc/mounts").map(|c| parse(&c)).unwrap_or_default()
+}
+
+/// The mount a path is on, the deepest one containing it.
+pub fn mount_of<'m>(mounts: &'m [Mount], path: &std::path::Path) -> Option<&'m Mount> {
+    let path = std::path::absolute(path).unwrap_or(path.to_path_buf());
+    mounts.iter().filter(|m| path.starts_with(&m.point)).max_by_key(|m| m.point.components().count())
+}
+
+/// Which filesystem a directory is on, stable across reboots where possible.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Identity {
+    /// From `/dev/disk/by-uuid`.
+    Uuid(String),
+    /// The mount source, network filesystems get a new device number on every mount.
+    Network(String),
+    Device(u64),
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Identity::Uuid(uuid) => write!(f, "filesystem {}", uuid),
+            Identity::Network(source) => write!(f, "{}", source),
+            Identity::Device(device) => write!(f, "device {}:{}", libc::major(*device), libc::minor(*device)),
+        }
+    }
+}
+
+pub fn identity(mounts: &[Mount], dir: &std::path::Path) -> std::io::Result<Identity> {
+    let device = std::fs::metadata(dir)?.dev();
+
+    if let Some(mount) = mount_of(mounts, dir).filter(|m| m.is_network()) {
+        return Ok(Identity::Network(mount.source.clone()));
+    }
+
+    let uuid = std::fs::read_dir("/dev/disk/by-uuid")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .find(|entry| std::fs::metadata(entry.path()).is_ok_and(|m| m.rdev() == device))
+        .map(|entry| entry.file_name().to_string_lossy().to_string());
+
+    Ok(uuid.map_or(Identity::Device(device), Identity::Uuid))
+}
+
+/// Whether a directory that should hold `expected` files looks like its filesystem isn't mounted:
+/// missing, or empty.
+pub fn looks_unmounted(dir: &std::path::Path, expected: usize) -> Option<String> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries.count(),
+        Err(_) if expected == 0 => return None,
+        Err(e) => return Some(format!("{:?} isn't readable ({}), but {} links were made from or into it", dir, e, expected)),
+    };
+
+    if entries == 0 && expected > 0 {
+        Some(format!("{:?} is empty, but {} links were made from or into it", dir, expected))
+    } else {
+        None
+    }
+}
+
+/// Errors a flaky network filesystem gives that are worth trying again.
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    // EIO, ENOTCONN and ESTALE.
+    matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::NotConnected)
+        || matches!(error.raw_os_error(), Some(5 | 107 | 116))
+}
+
+const ATTEMPTS: u32 = 5;
+
+/// Runs the operation until it succeeds, doubling the wait between transient failures.
+pub fn retry<T>(mut operation: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    let mut delay = std::time::Duration::from_millis(100);
+
+    for _ in 1..ATTEMPTS {
+        match operation() {
+            Err(e) if is_transient(&e) => {
+                eprintln!("{}, retrying in {:?}", e, delay);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    operation()
+}
@@ -0,0 +1,113 @@
+//! Per-file messages from a run, buffered and printed grouped by phase and by show or movie, so
+//! the output stays readable however the work gets interleaved.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Remove,
+    Link,
+    Prune,
+    Extra,
+    Purge,
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Phase::Remove => write!(f, "removed"),
+            Phase::Link => write!(f, "linked"),
+            Phase::Prune => write!(f, "pruned"),
+            Phase::Extra => write!(f, "not harvester's"),
+            Phase::Purge => write!(f, "garbage"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub phase: Phase,
+    pub group: String,
+    pub text: String,
+    /// Goes to stderr.
+    pub error: bool,
+}
+
+/// Each group's messages, in order.
+pub type Groups = Vec<(String, Vec<Message>)>;
+
+#[derive(Debug, Default)]
+pub struct Output {
+    messages: std::sync::Mutex<Vec<Message>>,
+}
+
+/// What a path is about: the folder it's in, or the show's for season folders.
+pub fn group_of(path: &std::path::Path) -> String {
+    let is_season = |dir: &std::path::Path| {
+        dir.file_name().is_some_and(|n| n.to_string_lossy().starts_with("Season ") || n == "Specials")
+    };
+
+    let dir = path.parent().unwrap_or(path);
+    let dir = if is_season(dir) { dir.parent().unwrap_or(dir) } else { dir };
+
+    dir.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string())
+}
+
+impl Output {
+    pub fn message(&self, phase: Phase, path: &std::path::Path, text: String) {
+        self.push(Message { phase, group: group_of(path), text, error: false });
+    }
+
+    pub fn error(&self, phase: Phase, path: &std::path::Path, text: String) {
+        self.push(Message { phase, group: group_of(path), text, error: true });
+    }
+
+    fn push(&self, message: Message) {
+        self.messages.lock().unwrap().push(message);
+    }
+
+    /// The messages so far, by phase and then group, each in the order they came in.
+    pub fn sections(&self) -> Vec<(Phase, Groups)> {
+        let mut messages = self.messages.lock().unwrap().clone();
+        messages.sort_by(|a, b| (a.phase, &a.group).cmp(&(b.phase, &b.group)));
+
+        let mut sections: Vec<(Phase, Groups)> = vec![];
+        for message in messages {
+            match sections.last_mut() {
+                Some((phase, groups)) if *phase == message.phase => match groups.last_mut() {
+                    Some((group, list)) if *group == message.group => list.push(message),
+                    _ => groups.push((message.group.clone(), vec![message])),
+                },
+                _ => sections.push((message.phase, vec![(message.group.clone(), vec![message])])),
+            }
+        }
+
+        sections
+    }
+
+    /// Prints everything buffered so far and forgets it. Errors are printed either way, the rest
+    /// only when `verbose`, the run summary covers it otherwise.
+    pub fn flush(&self, verbose: bool) {
+        for (phase, groups) in self.sections() {
+            let groups = groups
+                .into_iter()
+                .map(|(group, messages)| (group, messages.into_iter().filter(|m| verbose || m.error).collect::<Vec<_>>()))
+                .filter(|(_, messages)| !messages.is_empty())
+                .collect::<Vec<_>>();
+            if groups.is_empty() {
+                continue;
+            }
+
+            println!("{}:", phase);
+            for (group, messages) in groups {
+                println!("  {}:", group);
+                for message in messages {
+                    match message.error {
+                        true => eprintln!("    {}", message.text),
+                        false => println!("    {}", message.text),
+                    }
+                }
+            }
+        }
+
+        self.messages.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,159 @@
+//! `.harvester.toml` files inside the incoming directory, overriding parsing and routing options
+//! for everything beneath them.
+
+use serde::Deserialize;
+
+use crate::analyzer::{Analyzer, Candidates, Rule, ScannedFile, Stat};
+use crate::config::{Config, CustomRule, LayoutProfile, PartPolicy, ShowMoviePolicy};
+use crate::timings::{Phase, Timings};
+
+pub const FILENAME: &str = ".harvester.toml";
+
+/// Options left out keep the value from the enclosing directory, or the global config.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectoryConfig {
+    pub rules: Option<Vec<Rule>>,
+    /// Tried before the enclosing directories' ones.
+    pub custom_rules: Vec<CustomRule>,
+    /// Takes precedence over `[[routes]]`, but not over per-show layouts.
+    pub layout: Option<LayoutProfile>,
+    pub show_movies: Option<ShowMoviePolicy>,
+    pub parts: Option<PartPolicy>,
+}
+
+#[derive(Debug, Default)]
+pub struct Overrides {
+    /// Shallowest first, so later matches take precedence.
+    dirs: Vec<(std::path::PathBuf, DirectoryConfig)>,
+}
+
+impl Overrides {
+    pub fn load(source_dir: &std::path::Path) -> Result<Self, String> {
+        let mut files = vec![];
+
+        for path in crate::find_all_files(source_dir).into_iter().filter(|p| is_override(p)) {
+            let contents = std::fs::read_to_string(&path).map_err(|e| format!("unable to read {:?}: {}", path, e))?;
+            files.push((path, contents));
+        }
+
+        Self::parse(files)
+    }
+
+    /// From the paths and contents of `.harvester.toml` files.
+    pub fn parse(files: Vec<(std::path::PathBuf, String)>) -> Result<Self, String> {
+        let mut dirs = vec![];
+
+        for (path, contents) in files {
+            let config = toml::from_str(&contents).map_err(|e| format!("invalid directory config {:?}: {}", path, e))?;
+            dirs.push((path.parent().unwrap().to_path_buf(), config));
+        }
+
+        Ok(Self::new(dirs))
+    }
+
+    pub fn new(mut dirs: Vec<(std::path::PathBuf, DirectoryConfig)>) -> Self {
+        dirs.sort_by_key(|(dir, _)| dir.components().count());
+        Self { dirs }
+    }
+
+    fn applicable<'s>(&'s self, path: &'s std::path::Path) -> impl DoubleEndedIterator<Item = &'s DirectoryConfig> + 's {
+        self.dirs.iter().filter(move |(dir, _)| path.starts_with(dir)).map(|(_, config)| config)
+    }
+
+    pub fn layout(&self, path: &std::path::Path) -> Option<LayoutProfile> {
+        self.applicable(path).rev().find_map(|c| c.layout)
+    }
+
+    pub fn show_movies(&self, path: &std::path::Path) -> Option<ShowMoviePolicy> {
+        self.applicable(path).rev().find_map(|c| c.show_movies)
+    }
+
+    /// The analyzer for files in `dir`, merging rules from every enclosing override.
+    fn analyzer(&self, config: &Config, dir: &std::path::Path) -> Result<Analyzer, String> {
+        let rules = self.applicable(dir).rev().find_map(|c| c.rules.as_deref()).unwrap_or(config.rules());
+        let custom = self
+            .applicable(dir)
+            .rev()
+            .flat_map(|c| c.custom_rules.iter())
+            .chain(config.custom_rules.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let parts = self.applicable(dir).rev().find_map(|c| c.parts).unwrap_or(config.parts);
+
+        Analyzer::from_rules(&custom, rules)
+            .map(|a| a.configure(config).with_parts(parts))
+            .map_err(|e| format!("invalid directory config {:?}: {}", dir.join(FILENAME), e))
+    }
+
+    /// The global analyzer and one per override directory, see `pick`, for files under `root`.
+    fn analyzers(&self, config: &Config, root: Option<&std::path::Path>) -> Result<(Analyzer, Vec<Analyzer>), String> {
+        let rooted = |analyzer: Analyzer| match root {
+            Some(root) => analyzer.with_root(root),
+            None => analyzer,
+        };
+        let base = rooted(Analyzer::from_config(config)?);
+        let analyzers = self.dirs.iter().map(|(dir, _)| self.analyzer(config, dir).map(rooted)).collect::<Result<Vec<_>, _>>()?;
+
+        Ok((base, analyzers))
+    }
+
+    fn pick<'s>(&self, analyzers: &'s (Analyzer, Vec<Analyzer>), f: &std::path::Path) -> &'s Analyzer {
+        self.dirs
+            .iter()
+            .rposition(|(dir, _)| f.starts_with(dir))
+            .map_or(&analyzers.0, |i| &analyzers.1[i])
+    }
+
+    /// Like `Analyzer::analyze_directory`, with each file parsed using the rules that apply to it.
+    pub fn analyze_directory(&self, config: &Config, path: &std::path::Path) -> Result<Vec<ScannedFile>, String> {
+        self.analyze_directory_timed(config, path, &Timings::default())
+    }
+
+    /// `analyze_directory`, adding to `timings` how long walking it and analyzing the files took.
+    pub fn analyze_directory_timed(&self, config: &Config, path: &std::path::Path, timings: &Timings) -> Result<Vec<ScannedFile>, String> {
+        let analyzers = self.analyzers(config, Some(path))?;
+
+        println!("scanning {:?}...", path);
+
+        let files = timings.time(Phase::Scan, || crate::analyzer::stat_files(crate::find_files(path, &config.ignore)), Vec::len);
+        let files = timings.time(Phase::Analyze, || crate::analyzer::analyze_stats(files, &|f| self.pick(&analyzers, f)), Vec::len);
+
+        println!("found {} files.", files.len());
+        Ok(files)
+    }
+
+    /// `analyze_directory` for files under `root` whose metadata is already at hand.
+    pub fn analyze_stats(&self, config: &Config, root: &std::path::Path, files: Vec<(std::path::PathBuf, Stat)>) -> Result<Vec<ScannedFile>, String> {
+        let analyzers = self.analyzers(config, Some(root))?;
+
+        Ok(crate::analyzer::analyze_stats(files, &|f| self.pick(&analyzers, f)))
+    }
+
+    /// `Analyzer::conflicts` for each video, with the rules that apply to it.
+    pub fn conflicts(&self, config: &Config, files: &[ScannedFile]) -> Result<Vec<(std::path::PathBuf, Candidates)>, String> {
+        let analyzers = self.analyzers(config, None)?;
+
+        Ok(files
+            .iter()
+            .filter(|f| crate::analyzer::is_video(&f.path))
+            .filter_map(|f| Some((f.path.clone(), self.pick(&analyzers, &f.path).conflicts(&f.path)?)))
+            .collect())
+    }
+
+    /// `Analyzer::explain` for each video, with the rules that apply to it.
+    pub fn explain(&self, config: &Config, files: &[ScannedFile]) -> Result<Vec<(std::path::PathBuf, String)>, String> {
+        let analyzers = self.analyzers(config, None)?;
+
+        Ok(files
+            .iter()
+            .filter(|f| crate::analyzer::is_video(&f.path))
+            .map(|f| (f.path.clone(), self.pick(&analyzers, &f.path).explain(&f.path)))
+            .collect())
+    }
+}
+
+pub fn is_override(path: &std::path::Path) -> bool {
+    path.file_name().is_some_and(|name| name == FILENAME)
+}
@@ -0,0 +1,405 @@
+//! Everything a run is going to change in the library, worked out up front so the dry run and the
+//! real one execute the exact same operations.
+
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::MetadataExt;
+
+use crate::config::LinkMode;
+use crate::output::{Output, Phase};
+use crate::{find_all_files_in, prune, subtitles, DryRunner, Layout, RealRunner, Runner, ScannedFile};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkKind {
+    Hardlink,
+    /// With the `.strm` file contents.
+    Strm(String),
+}
+
+impl LinkKind {
+    /// What the link takes on the target's filesystem, besides its directory entry.
+    pub fn bytes(&self) -> u64 {
+        match self {
+            LinkKind::Hardlink => 0,
+            LinkKind::Strm(contents) => contents.len() as u64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Create {
+    pub source: std::path::PathBuf,
+    pub link: std::path::PathBuf,
+    pub kind: LinkKind,
+}
+
+/// An operation of the plan that couldn't be done, by the path it was on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    pub path: std::path::PathBuf,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Links from previous runs, removed before creating the new ones.
+    pub deletes: Vec<std::path::PathBuf>,
+    /// Library files harvester doesn't own and leaves alone.
+    pub extra: Vec<std::path::PathBuf>,
+    pub creates: Vec<Create>,
+    /// Directories left empty once the rest is done, deepest first.
+    pub prunes: Vec<std::path::PathBuf>,
+}
+
+impl Plan {
+    /// Links that go away for good, not just to be recreated in the same place.
+    pub fn removed(&self) -> impl Iterator<Item = &std::path::PathBuf> {
+        self.deletes.iter().filter(|d| !self.creates.iter().any(|c| c.link == **d))
+    }
+
+    /// Links that weren't there before.
+    pub fn added(&self) -> impl Iterator<Item = &Create> {
+        self.creates.iter().filter(|c| !self.deletes.contains(&c.link))
+    }
+
+    /// Leaves out what `apply` didn't do, so what's reported and recorded afterwards is what
+    /// actually changed.
+    pub fn forget(&mut self, applied: &Applied) {
+        self.deletes.retain(|d| applied.is_done(d));
+        self.creates.retain(|c| applied.is_done(&c.link));
+        self.prunes.retain(|p| applied.is_done(p));
+    }
+
+    /// With absolute paths, for keeping it around (see `journal` and `backup`).
+    pub fn absolute(&self) -> Plan {
+        let absolute = |p: &std::path::PathBuf| std::path::absolute(p).unwrap_or(p.clone());
+        let paths = |paths: &[std::path::PathBuf]| paths.iter().map(absolute).collect();
+
+        Plan {
+            deletes: paths(&self.deletes),
+            extra: paths(&self.extra),
+            creates: self.creates.iter().map(|c| Create { source: absolute(&c.source), link: absolute(&c.link), kind: c.kind.clone() }).collect(),
+            prunes: paths(&self.prunes),
+        }
+    }
+
+    /// Leaves the library as it is apart from new links: links that would be recreated in place
+    /// are kept, and the rest of the removals are returned instead of done.
+    pub fn defer_deletions(&mut self) -> Vec<std::path::PathBuf> {
+        let deletes = std::mem::take(&mut self.deletes);
+        let (kept, deferred): (Vec<_>, Vec<_>) = deletes.into_iter().partition(|d| self.creates.iter().any(|c| c.link == *d));
+
+        self.creates.retain(|c| !kept.contains(&c.link));
+        self.prunes.clear();
+
+        deferred
+    }
+}
+
+/// The closest directory that already exists, to tell which filesystem a new path ends up on.
+fn existing_ancestor(path: &std::path::Path) -> Option<(&std::path::Path, std::fs::Metadata)> {
+    path.ancestors().find_map(|a| Some((a, std::fs::metadata(a).ok()?)))
+}
+
+/// What a plan takes from one target filesystem, net of what removing files only the library
+/// holds frees. Hard links need no space of their own, but the directories they go in do.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Needed {
+    /// The contents of `.strm` files.
+    pub bytes: i64,
+    /// One per `.strm` file and new directory.
+    pub inodes: i64,
+    /// Each taking a block on top of `bytes`.
+    pub directories: u64,
+}
+
+/// What each target filesystem needs, by a directory on it. Hard links across filesystems are
+/// errors, creating them would fail partway through.
+pub fn required(plan: &Plan) -> Result<Vec<(std::path::PathBuf, Needed)>, String> {
+    let mut needed = std::collections::BTreeMap::<u64, (std::path::PathBuf, Needed)>::new();
+    let mut directories = std::collections::HashSet::new();
+
+    for create in plan.creates.iter() {
+        let (dir, target) = existing_ancestor(&create.link).ok_or_else(|| format!("no parent of {:?} exists", create.link))?;
+        if create.kind == LinkKind::Hardlink {
+            let source = std::fs::metadata(&create.source).map_err(|e| format!("unable to read {:?}: {}", create.source, e))?;
+            if source.dev() != target.dev() {
+                return Err(format!("{:?} isn't on the same filesystem as {:?}, it can't be hard linked there", create.source, dir));
+            }
+        }
+
+        let entry = &mut needed.entry(target.dev()).or_insert_with(|| (dir.to_path_buf(), Needed::default())).1;
+        entry.bytes += create.kind.bytes() as i64;
+        entry.inodes += matches!(create.kind, LinkKind::Strm(_)) as i64;
+
+        let new = create.link.ancestors().skip(1).take_while(|a| *a != dir).filter(|a| directories.insert(a.to_path_buf())).count();
+        entry.inodes += new as i64;
+        entry.directories += new as u64;
+    }
+
+    for delete in plan.deletes.iter() {
+        let Ok(metadata) = std::fs::metadata(delete) else { continue; };
+        if let (Some((_, entry)), 1) = (needed.get_mut(&metadata.dev()), metadata.nlink()) {
+            entry.bytes -= metadata.len() as i64;
+            entry.inodes -= 1;
+        }
+    }
+
+    Ok(needed.into_values().collect())
+}
+
+/// What's left for unprivileged users on a filesystem.
+struct Free {
+    bytes: u64,
+    block_size: u64,
+    /// Zero for filesystems allocating inodes as they go (btrfs, zfs).
+    total_inodes: u64,
+    inodes: u64,
+}
+
+// The fields are narrower on 32-bit targets.
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &std::path::Path) -> Option<Free> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+    match unsafe { libc::statvfs(path.as_ptr(), &mut stat) } {
+        0 => Some(Free {
+            bytes: stat.f_bavail as u64 * stat.f_frsize as u64,
+            block_size: stat.f_bsize as u64,
+            total_inodes: stat.f_files as u64,
+            inodes: stat.f_favail as u64,
+        }),
+        _ => None,
+    }
+}
+
+/// Space left for unprivileged users on the filesystem holding `path`.
+pub fn free_space(path: &std::path::Path) -> Option<u64> {
+    statvfs(path).map(|free| free.bytes)
+}
+
+/// Checks the plan fits before anything is done, space and inodes, see `required`.
+pub fn preflight(plan: &Plan) -> Result<(), String> {
+    for (dir, needed) in required(plan)? {
+        let free = statvfs(&dir).ok_or_else(|| format!("unable to check the free space of {:?}", dir))?;
+        let bytes = needed.bytes + (needed.directories * free.block_size) as i64;
+        if bytes > 0 && bytes as u64 > free.bytes {
+            return Err(format!(
+                "{:?} needs {} for the new files but only has {} free",
+                dir, crate::report::human_size(bytes as u64), crate::report::human_size(free.bytes),
+            ));
+        }
+
+        if free.total_inodes > 0 && needed.inodes > 0 && needed.inodes as u64 > free.inodes {
+            return Err(format!("{:?} needs {} inodes for the new files and directories but only has {} free", dir, needed.inodes, free.inodes));
+        }
+    }
+
+    Ok(())
+}
+
+/// Marks a target directory as the library, see `check_sentinel`.
+pub const SENTINEL: &str = ".harvester-root";
+
+/// Removing links and pruning directories is only safe on the actual library, not on an empty
+/// mount point with the disk missing. Roots outside the target directory (users' targets and
+/// libraries given as absolute paths) can be mounts of their own, they need one too.
+pub fn check_sentinel(layout: &Layout, target_dir: &std::path::Path) -> Result<(), String> {
+    match layout.roots(target_dir).into_iter().find(|root| !root.join(SENTINEL).exists()) {
+        Some(root) => Err(format!("{:?} has no {} file, is it mounted? (harvester init creates it)", root, SENTINEL)),
+        None => Ok(()),
+    }
+}
+
+/// Creates the library folders and the sentinels for `harvester init`, returning the ones that
+/// weren't there yet.
+pub fn init(layout: &Layout, target_dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>, String> {
+    let mut created = vec![];
+
+    let libraries = &layout.config.libraries;
+    let roots = layout.roots(target_dir);
+    for dir in [target_dir.join(&libraries.shows), target_dir.join(&libraries.movies)].into_iter().chain(roots.iter().skip(1).cloned()) {
+        if !dir.is_dir() {
+            std::fs::create_dir_all(&dir).map_err(|e| format!("unable to create {:?}: {}", dir, e))?;
+            created.push(dir);
+        }
+    }
+
+    for sentinel in roots.iter().map(|root| root.join(SENTINEL)) {
+        if !sentinel.exists() {
+            std::fs::write(&sentinel, "").map_err(|e| format!("unable to create {:?}: {}", sentinel, e))?;
+            created.push(sentinel);
+        }
+    }
+
+    Ok(created)
+}
+
+fn is_owned_strm(fs: &dyn Runner, layout: &Layout, file: &std::path::Path) -> bool {
+    file.extension().is_some_and(|e| e == "strm")
+        && fs.read_to_string(file).is_some_and(|contents| layout.owns_strm(&contents))
+}
+
+/// `links` are the ones the library should end up with (usually `Layout::link_paths`), but existing
+/// links to any of `files` are considered harvester's own.
+pub fn plan(layout: &Layout, files: &[ScannedFile], target_dir: &std::path::Path, links: &[(&ScannedFile, std::path::PathBuf)]) -> Plan {
+    plan_in(&RealRunner {}, layout, files, target_dir, links)
+}
+
+/// `plan` for the library as `fs` sees it.
+pub fn plan_in(fs: &dyn Runner, layout: &Layout, files: &[ScannedFile], target_dir: &std::path::Path, links: &[(&ScannedFile, std::path::PathBuf)]) -> Plan {
+    let simulation = DryRunner::over(fs);
+    let mut plan = Plan::default();
+
+    let roots = layout.roots(target_dir).into_iter().filter(|r| fs.exists(r)).collect::<Vec<_>>();
+
+    let source_inodes = files.iter().map(|f| f.inode).collect::<std::collections::HashSet<_>>();
+    let sentinels = roots.iter().map(|r| r.join(SENTINEL)).collect::<Vec<_>>();
+    for file in roots.iter().flat_map(|r| find_all_files_in(fs, r, &layout.config.ignore)).filter(|f| !sentinels.contains(f)) {
+        // A dangling symlink, or removed since it was listed: nothing of ours to plan for.
+        let Some(inode) = fs.inode(&file) else { continue; };
+
+        if source_inodes.contains(&inode) || is_owned_strm(fs, layout, &file) {
+            plan.deletes.push(file);
+        } else {
+            plan.extra.push(file);
+        }
+    }
+
+    // Subtitles next to our links stay with them, and go away along with them.
+    let linked = links.iter().map(|(_, path)| path).collect::<std::collections::HashSet<_>>();
+    let extra = std::mem::take(&mut plan.extra);
+    for file in extra {
+        match plan.deletes.iter().find(|d| subtitles::is_sidecar(d, &file)) {
+            Some(video) if linked.contains(video) => {}
+            Some(_) => plan.deletes.push(file),
+            None => plan.extra.push(file),
+        }
+    }
+
+    for file in plan.deletes.iter() {
+        simulation.remove_file(file).unwrap();
+    }
+
+    for (file, path) in links.iter() {
+        if simulation.exists(path) {
+            continue;
+        }
+
+        let kind = match layout.config.link_mode {
+            LinkMode::Hardlink => LinkKind::Hardlink,
+            LinkMode::Strm => LinkKind::Strm(layout.strm_target(file)),
+        };
+
+        simulation.create_dir_all(path.parent().unwrap()).unwrap();
+        simulation.hard_link(&file.path, path).unwrap();
+        plan.creates.push(Create { source: file.path.clone(), link: path.clone(), kind });
+    }
+
+    for root in roots.iter() {
+        prune(&simulation, root, &mut plan.prunes);
+    }
+
+    plan
+}
+
+/// Whether `link` is already a hard link to `source`.
+pub(crate) fn is_linked(runner: &dyn Runner, source: &std::path::Path, link: &std::path::Path) -> bool {
+    runner.inode(link).is_some_and(|inode| runner.inode(source) == Some(inode))
+}
+
+/// What `apply` did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Applied {
+    pub failures: Vec<Failure>,
+    /// Operations never tried, the run was interrupted before them.
+    pub skipped: Vec<std::path::PathBuf>,
+}
+
+impl Applied {
+    /// Whether the operation on `path` went through.
+    pub fn is_done(&self, path: &std::path::Path) -> bool {
+        !self.failures.iter().any(|f| f.path == path) && !self.skipped.iter().any(|s| s == path)
+    }
+}
+
+/// Applies the plan as far as it can: operations that fail are reported and left out, and the
+/// rest goes ahead. What failed is still missing (or still there) on the next run, which plans it
+/// again. Stops starting new operations on Ctrl-C, see `interrupt`.
+pub fn apply(plan: &Plan, runner: &dyn Runner, output: &Output) -> Applied {
+    apply_until(plan, runner, output, &crate::interrupt::requested)
+}
+
+/// `apply`, skipping every operation after `stop` says so.
+pub fn apply_until(plan: &Plan, runner: &dyn Runner, output: &Output, stop: &dyn Fn() -> bool) -> Applied {
+    let mut applied = Applied::default();
+    let failure = |phase: Phase, path: &std::path::Path, error: String| {
+        output.error(phase, path, error.clone());
+        Failure { path: path.to_path_buf(), error }
+    };
+
+    for file in plan.deletes.iter() {
+        if stop() {
+            applied.skipped.push(file.clone());
+            continue;
+        }
+        output.message(Phase::Remove, file, format!("removing file {:?}", file));
+        if let Err(e) = runner.remove_file(file) {
+            applied.failures.push(failure(Phase::Remove, file, format!("unable to remove {:?}: {}", file, e)));
+        }
+    }
+    runner.sync();
+
+    for file in plan.extra.iter() {
+        output.error(Phase::Extra, file, format!("extra file found: {:?}", file));
+    }
+
+    for create in plan.creates.iter() {
+        if stop() {
+            applied.skipped.push(create.link.clone());
+            continue;
+        }
+        if let Err(e) = runner.create_dir_all(create.link.parent().unwrap()) {
+            applied.failures.push(failure(Phase::Link, &create.link, format!("unable to create the directory of {:?}: {}", create.link, e)));
+            continue;
+        }
+
+        let result = match &create.kind {
+            LinkKind::Hardlink => {
+                output.message(Phase::Link, &create.link, format!("creating hard link: {:?}", create.link));
+                match runner.hard_link(&create.source, &create.link) {
+                    // Someone else (another run, or Jellyfin) linked it in the meantime.
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && is_linked(runner, &create.source, &create.link) => Ok(()),
+                    result => result,
+                }
+            }
+            LinkKind::Strm(contents) => {
+                output.message(Phase::Link, &create.link, format!("creating strm file: {:?}", create.link));
+                runner.write_file(&create.link, contents)
+            }
+        };
+        if let Err(e) = result {
+            applied.failures.push(failure(Phase::Link, &create.link, format!("unable to create {:?}: {}", create.link, e)));
+        }
+    }
+    runner.sync();
+
+    // Directories still holding a file that wasn't removed stay, quietly.
+    for dir in plan.prunes.iter() {
+        if stop() {
+            applied.skipped.push(dir.clone());
+            continue;
+        }
+        if plan.deletes.iter().any(|d| d.starts_with(dir) && !applied.is_done(d)) {
+            continue;
+        }
+        output.message(Phase::Prune, dir, format!("removing directory {:?}", dir));
+        if let Err(e) = runner.remove_dir(dir) {
+            applied.failures.push(failure(Phase::Prune, dir, format!("unable to remove directory {:?}: {}", dir, e)));
+        }
+    }
+    runner.sync();
+
+    applied
+}
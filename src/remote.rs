@@ -0,0 +1,132 @@
+//! A remote incoming directory reached over SFTP, through OpenSSH's `sftp` in batch mode. It's
+//! listed and mirrored into the local incoming directory, links are planned against the mirror.
+
+use std::io::Write;
+
+use crate::config::RemoteConfig;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteFile {
+    /// Relative to the remote directory.
+    pub path: std::path::PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Double quoted for sftp's command parser.
+pub fn quote(path: &std::path::Path) -> String {
+    format!("\"{}\"", path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Runs the batch commands, returning their output. sftp echoes each command as `sftp> ...`.
+fn sftp(config: &RemoteConfig, batch: &str) -> Result<String, String> {
+    let mut command = std::process::Command::new("sftp");
+    command.args(["-q", "-b", "-"]);
+    if let Some(port) = config.port {
+        command.arg("-P").arg(port.to_string());
+    }
+
+    let mut child = command
+        .arg(&config.host)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("unable to run sftp: {}", e))?;
+
+    child.stdin.take().unwrap().write_all(batch.as_bytes()).map_err(|e| format!("unable to talk to sftp: {}", e))?;
+    let output = child.wait_with_output().map_err(|e| format!("unable to run sftp: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("sftp to {} failed: {}", config.host, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// `ls -ln` output, grouped by the directory each command listed.
+pub fn parse_listing(output: &str) -> Vec<(String, Vec<Entry>)> {
+    let line = regex::Regex::new(r"^([-dlcbps])\S*\s+\d+\s+\S+\s+\S+\s+(\d+)\s+\S+\s+\S+\s+\S+\s(.*)$").unwrap();
+    let mut listings: Vec<(String, Vec<Entry>)> = vec![];
+
+    for l in output.lines() {
+        if let Some(dir) = l.strip_prefix("sftp> ls -ln ") {
+            let dir = dir.trim();
+            let dir = dir.strip_prefix('"').and_then(|d| d.strip_suffix('"')).unwrap_or(dir);
+            let dir = dir.replace("\\\"", "\"").replace("\\\\", "\\");
+            listings.push((dir, vec![]));
+            continue;
+        }
+
+        let (Some(x), Some((_, entries))) = (line.captures(l), listings.last_mut()) else { continue; };
+        let kind = x.get(1).unwrap().as_str();
+        let name = x.get(3).unwrap().as_str();
+        // Some servers print the listed directory before each name.
+        let name = name.rsplit('/').next().unwrap_or(name).to_string();
+        if name == "." || name == ".." || !matches!(kind, "-" | "d") {
+            continue;
+        }
+
+        entries.push(Entry { name, is_dir: kind == "d", size: x.get(2).unwrap().as_str().parse().unwrap_or(0) });
+    }
+
+    listings
+}
+
+/// Every regular file under the remote directory, a level of directories per sftp session.
+pub fn list(config: &RemoteConfig) -> Result<Vec<RemoteFile>, String> {
+    let root = std::path::PathBuf::from(&config.path);
+    let mut files = vec![];
+    let mut level = vec![root.clone()];
+
+    while !level.is_empty() {
+        let batch = level.iter().map(|d| format!("ls -ln {}\n", quote(d))).collect::<String>();
+        level = vec![];
+
+        for (dir, entries) in parse_listing(&sftp(config, &batch)?) {
+            let dir = std::path::PathBuf::from(dir);
+            for entry in entries {
+                let path = dir.join(&entry.name);
+                if entry.is_dir {
+                    level.push(path);
+                } else if let Ok(relative) = path.strip_prefix(&root) {
+                    files.push(RemoteFile { path: relative.to_path_buf(), size: entry.size });
+                }
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Remote files the mirror doesn't have yet, or only has part of.
+pub fn missing<'r>(files: &'r [RemoteFile], mirror: &std::path::Path) -> Vec<&'r RemoteFile> {
+    files
+        .iter()
+        .filter(|f| std::fs::metadata(mirror.join(&f.path)).map_or(true, |m| m.len() < f.size))
+        .collect()
+}
+
+/// Downloads the files into the mirror, resuming partial ones.
+pub fn pull(config: &RemoteConfig, files: &[&RemoteFile], mirror: &std::path::Path) -> Result<(), String> {
+    let mut batch = String::new();
+
+    for file in files {
+        let local = mirror.join(&file.path);
+        if let Some(parent) = local.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("unable to create {:?}: {}", parent, e))?;
+        }
+
+        let remote = std::path::Path::new(&config.path).join(&file.path);
+        let get = if local.exists() { "reget" } else { "get" };
+        batch.push_str(&format!("{} -p {} {}\n", get, quote(&remote), quote(&local)));
+    }
+
+    sftp(config, &batch).map(|_| ())
+}
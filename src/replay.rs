@@ -0,0 +1,123 @@
+//! `--record` and `--replay`: the incoming and library trees saved to a file (names, sizes and
+//! inodes, with no contents besides directory configs and `.strm` files), and a run planned
+//! against them, so a bug can be reproduced without the files.
+
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::Stat;
+use crate::config::Config;
+use crate::memfs::MemoryFs;
+use crate::overrides::{self, Overrides};
+use crate::plan::Plan;
+use crate::{Layout, ScannedFile};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedFile {
+    pub path: std::path::PathBuf,
+    #[serde(flatten)]
+    pub stat: Stat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub incoming: std::path::PathBuf,
+    pub target: std::path::PathBuf,
+    pub dirs: Vec<std::path::PathBuf>,
+    pub files: Vec<RecordedFile>,
+}
+
+fn has_contents(path: &std::path::Path) -> bool {
+    overrides::is_override(path) || path.extension().is_some_and(|e| e == "strm")
+}
+
+fn walk(path: &std::path::Path, dirs: &mut Vec<std::path::PathBuf>, files: &mut Vec<RecordedFile>) {
+    let Ok(metadata) = std::fs::metadata(path) else { return; };
+
+    if metadata.is_dir() {
+        dirs.push(path.to_path_buf());
+        for entry in path.read_dir().into_iter().flatten().filter_map(Result::ok) {
+            walk(&entry.path(), dirs, files);
+        }
+    } else {
+        let contents = has_contents(path).then(|| std::fs::read_to_string(path).ok()).flatten();
+        files.push(RecordedFile { path: path.to_path_buf(), stat: Stat::from(&metadata), contents });
+    }
+}
+
+/// The incoming directory and the library roots (see `Layout::roots`) of the target.
+pub fn record(incoming: &std::path::Path, target: &std::path::Path, roots: &[std::path::PathBuf]) -> Recording {
+    let (mut dirs, mut files) = (vec![], vec![]);
+
+    for dir in [incoming.to_path_buf()].iter().chain(roots) {
+        walk(dir, &mut dirs, &mut files);
+    }
+
+    Recording { version: VERSION, incoming: incoming.to_path_buf(), target: target.to_path_buf(), dirs, files }
+}
+
+impl Recording {
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("unable to read {:?}: {}", path, e))?;
+        let recording: Self = serde_json::from_str(&contents).map_err(|e| format!("invalid recording {:?}: {}", path, e))?;
+
+        if recording.version > VERSION {
+            return Err(format!("recording version {} is newer than this harvester's ({})", recording.version, VERSION));
+        }
+
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(path, contents).map_err(|e| format!("unable to write {:?}: {}", path, e))
+    }
+
+    /// The recorded trees, in memory.
+    pub fn filesystem(&self) -> MemoryFs {
+        let fs = MemoryFs::default();
+
+        for dir in self.dirs.iter() {
+            crate::Runner::create_dir_all(&fs, dir).unwrap();
+        }
+        for file in self.files.iter() {
+            fs.insert_file(&file.path, file.stat.inode, file.contents.as_deref().unwrap_or(""));
+        }
+
+        fs
+    }
+
+    /// The incoming files, as scanning them would have found them.
+    pub fn incoming_files(&self) -> Vec<(std::path::PathBuf, Stat)> {
+        self.files.iter().filter(|f| f.path.starts_with(&self.incoming)).map(|f| (f.path.clone(), f.stat)).collect()
+    }
+
+    pub fn overrides(&self) -> Result<Overrides, String> {
+        let files = self
+            .files
+            .iter()
+            .filter(|f| f.path.starts_with(&self.incoming) && overrides::is_override(&f.path))
+            .map(|f| (f.path.clone(), f.contents.clone().unwrap_or_default()))
+            .collect();
+
+        Overrides::parse(files)
+    }
+
+    /// The run as it'd go on the recorded trees. The state isn't part of the recording, so
+    /// nothing is taken as a duplicate or tombstoned.
+    pub fn plan(&self, config: &Config) -> Result<(MemoryFs, Vec<ScannedFile>, Plan), String> {
+        let fs = self.filesystem();
+        let overrides = self.overrides()?;
+        let files = overrides.analyze_stats(config, &self.incoming, self.incoming_files())?;
+
+        let layout = Layout::new(config, &self.incoming).with_overrides(overrides);
+        let links = layout.link_paths(&files, &self.target);
+        let plan = crate::plan::plan_in(&fs, &layout, &files, &self.target, &links);
+
+        Ok((fs, files, plan))
+    }
+}
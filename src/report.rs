@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::os::unix::fs::MetadataExt;
+
+use crate::{MediaData, ScannedFile};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GarbageSummary {
+    pub extensions: BTreeMap<String, usize>,
+    pub size: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub garbage: BTreeMap<std::path::PathBuf, GarbageSummary>,
+    pub purged: Vec<std::path::PathBuf>,
+    pub linked: usize,
+    /// Linked sources with hard links other than the library one, and their total link count.
+    pub shared: Vec<(std::path::PathBuf, u64)>,
+    /// Sources that weren't linked because the same file is already in the library, and the source
+    /// it's linked from.
+    pub duplicates: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    pub seasons: Vec<crate::completion::Season>,
+    /// Videos left out because they look like failed downloads, and why.
+    pub broken: Vec<(std::path::PathBuf, &'static str)>,
+    /// What was added to and removed from the library, see `summarize`.
+    pub changes: Vec<String>,
+    /// Pairs of movie links that are probably the same movie, see `similar_movies`.
+    pub similar_movies: Vec<(std::path::PathBuf, std::path::PathBuf)>,
+    /// Library changes that failed, and why. The next run tries them again.
+    pub failures: Vec<(std::path::PathBuf, String)>,
+    /// What each incoming entry something was linked from brought in, see `imports`.
+    pub imports: Vec<crate::imports::Import>,
+}
+
+/// A linked movie, with its runtime and size.
+pub struct MovieFile<'a> {
+    pub media: &'a MediaData,
+    pub link: &'a std::path::Path,
+    pub duration: std::time::Duration,
+    pub size: u64,
+}
+
+/// Movies linked under a different title or year that are likely the same one parsed two ways:
+/// runtimes within two seconds and sizes within a tenth of each other.
+pub fn similar_movies(mut movies: Vec<MovieFile>) -> Vec<(std::path::PathBuf, std::path::PathBuf)> {
+    const RUNTIME: std::time::Duration = std::time::Duration::from_secs(2);
+
+    movies.sort_by_key(|m| m.duration);
+
+    let mut pairs = vec![];
+    for (i, a) in movies.iter().enumerate() {
+        for b in movies[i + 1..].iter().take_while(|b| b.duration - a.duration <= RUNTIME) {
+            let (small, large) = (a.size.min(b.size), a.size.max(b.size));
+            if a.media != b.media && large - small <= large / 10 {
+                pairs.push((a.link.to_path_buf(), b.link.to_path_buf()));
+            }
+        }
+    }
+
+    pairs
+}
+
+/// Episodes are listed by number, everything else is counted.
+#[derive(Debug, Default)]
+struct Changes {
+    heading: String,
+    added: Vec<Option<u32>>,
+    removed: Vec<Option<u32>>,
+}
+
+fn describe(verb: &str, items: &[Option<u32>]) -> Option<String> {
+    match items.iter().copied().collect::<Option<BTreeSet<_>>>() {
+        _ if items.is_empty() => None,
+        Some(episodes) if episodes.len() == 1 => Some(format!("{} episode {}", verb, episodes.first().unwrap())),
+        Some(episodes) => Some(format!("{} episodes {}", verb, episodes.iter().map(u32::to_string).collect::<Vec<_>>().join(", "))),
+        None => Some(format!("{} {}", verb, items.len())),
+    }
+}
+
+/// One line per show season (then specials and movies of the show), and one for movies:
+/// "Breaking Bad — Season 2: added episodes 3, 4".
+pub fn summarize(added: &[&MediaData], removed: &[&MediaData]) -> Vec<String> {
+    let mut changes = BTreeMap::<(bool, String, u32), Changes>::new();
+
+    for (media, is_added) in added.iter().map(|m| (m, true)).chain(removed.iter().map(|m| (m, false))) {
+        let (key, heading, episode) = match media {
+            MediaData::ShowEpisode { name, season, episode } => ((false, name.clone(), *season), format!("{} — Season {}", name, season), Some(*episode)),
+            MediaData::ShowSpecial { name, .. } => ((false, name.clone(), u32::MAX - 1), format!("{} — Specials", name), None),
+            MediaData::ShowMovie { name, .. } => ((false, name.clone(), u32::MAX), format!("{} — Movies", name), None),
+            MediaData::SportsEvent { league, season, .. } => (
+                (false, league.clone(), season.unwrap_or(0)),
+                season.map_or(league.clone(), |s| format!("{} — {}", league, s)),
+                None,
+            ),
+            MediaData::HomeVideo { year, month, .. } => ((true, "home videos".to_string(), year * 100 + month), format!("Home videos — {}-{:02}", year, month), None),
+            MediaData::Photo { year, month, .. } => ((true, "photos".to_string(), year * 100 + month), format!("Photos — {}-{:02}", year, month), None),
+            MediaData::Book { .. } => ((true, "books".to_string(), 0), "Books".to_string(), None),
+            MediaData::Comic { series, .. } => ((true, format!("comics {}", series), 0), format!("Comics — {}", series), None),
+            MediaData::Movie { .. } | MediaData::MoviePart { .. } | MediaData::Performance { .. } => ((true, String::new(), 0), "Movies".to_string(), None),
+            MediaData::Garbage => continue,
+        };
+
+        let entry = changes.entry(key).or_insert_with(|| Changes { heading, ..Default::default() });
+        match is_added {
+            true => entry.added.push(episode),
+            false => entry.removed.push(episode),
+        }
+    }
+
+    changes
+        .into_values()
+        .map(|c| {
+            let parts = [describe("added", &c.added), describe("removed", &c.removed)];
+            format!("{}: {}", c.heading, parts.into_iter().flatten().collect::<Vec<_>>().join("; "))
+        })
+        .collect()
+}
+
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+impl Report {
+    pub fn track_garbage(&mut self, files: &[ScannedFile]) {
+        for file in files.iter().filter(|f| f.metadata == Some(MediaData::Garbage)) {
+            let directory = file.path.parent().unwrap_or(std::path::Path::new("")).to_path_buf();
+            let extension = file
+                .path
+                .extension()
+                .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+
+            let summary = self.garbage.entry(directory).or_default();
+            *summary.extensions.entry(extension).or_default() += 1;
+            summary.size += file.size;
+        }
+    }
+
+    pub fn track_broken(&mut self, files: &[ScannedFile]) {
+        self.broken = files.iter().filter_map(|f| Some((f.path.clone(), f.broken?))).collect();
+    }
+
+    pub fn track_link_counts(&mut self, sources: &[&std::path::Path]) {
+        self.linked = sources.len();
+        self.shared = sources
+            .iter()
+            .filter_map(|source| std::fs::metadata(source).ok().map(|m| (source.to_path_buf(), m.nlink())))
+            .filter(|(_, links)| *links > 2)
+            .collect();
+    }
+
+    pub fn print(&self) {
+        if !self.failures.is_empty() {
+            println!("{} changes failed, the next run tries them again:", self.failures.len());
+            for (_, error) in self.failures.iter() {
+                println!("  {}", error);
+            }
+        }
+
+        if !self.changes.is_empty() {
+            println!("changes:");
+            for line in self.changes.iter() {
+                println!("  {}", line);
+            }
+        }
+
+        if !self.imports.is_empty() {
+            println!("torrents imported:");
+            for import in self.imports.iter() {
+                match import.missing.len() {
+                    0 => println!("  {}: {}", import.name, import.describe()),
+                    n => println!("  {}: {}, incomplete, {} videos not linked", import.name, import.describe(), n),
+                }
+            }
+        }
+
+        if !self.garbage.is_empty() {
+            println!("garbage:");
+            for (directory, summary) in self.garbage.iter() {
+                let count = summary.extensions.values().sum::<usize>();
+                let extensions = summary
+                    .extensions
+                    .iter()
+                    .map(|(e, n)| format!("{}: {}", e, n))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                println!("  {:?}: {} files, {} ({})", directory, count, human_size(summary.size), extensions);
+            }
+        }
+
+        if !self.broken.is_empty() {
+            println!("possibly failed downloads, not linked:");
+            for (path, reason) in self.broken.iter() {
+                println!("  {:?}: {}", path, reason);
+            }
+        }
+
+        if !self.purged.is_empty() {
+            println!("purged {} garbage files.", self.purged.len());
+        }
+
+        if !self.duplicates.is_empty() {
+            println!("already in the library under another name:");
+            for (source, original) in self.duplicates.iter() {
+                println!("  {:?}: same file as {:?}", source, original);
+            }
+        }
+
+        if !self.similar_movies.is_empty() {
+            println!("probably the same movie, parsed differently:");
+            for (a, b) in self.similar_movies.iter() {
+                println!("  {:?}: same runtime and size as {:?}", a, b);
+            }
+        }
+
+        if self.linked > 0 {
+            println!("{} files in the library, {} with other hard links.", self.linked, self.shared.len());
+            for (source, links) in self.shared.iter() {
+                println!("  {:?}: {} links", source, links);
+            }
+        }
+
+        if !self.seasons.is_empty() {
+            let complete = self.seasons.iter().filter(|s| s.is_complete()).count();
+            println!("{} of {} seasons complete.", complete, self.seasons.len());
+            for season in self.seasons.iter().filter(|s| !s.is_complete()) {
+                println!("  {}", season);
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::state::State;
+use crate::torrent::Torrent;
+use crate::{Analyzer, MediaData};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Thresholds {
+    pub ratio: Option<f64>,
+    /// Seconds spent seeding.
+    pub seeding_time: Option<u64>,
+}
+
+impl Thresholds {
+    pub fn exceeded(&self, torrent: &Torrent) -> bool {
+        self.ratio.is_some_and(|r| torrent.ratio >= r)
+            || self.seeding_time.is_some_and(|t| torrent.seeding_time >= t)
+    }
+}
+
+/// Every non-garbage file of the torrent has a link in the library. Clients report paths as they
+/// see them, the state has them canonicalized.
+pub fn fully_linked(torrent: &Torrent, analyzer: &Analyzer, state: &State) -> bool {
+    let mut media = torrent
+        .files
+        .iter()
+        .filter(|f| analyzer.analyze(f) != Some(MediaData::Garbage))
+        .peekable();
+
+    media.peek().is_some() && media.all(|f| state.is_linked(&std::fs::canonicalize(f).unwrap_or(f.clone())))
+}
+
+pub fn candidates<'a>(torrents: &'a [Torrent], analyzer: &Analyzer, state: &State, thresholds: Thresholds) -> Vec<&'a Torrent> {
+    torrents
+        .iter()
+        .filter(|t| thresholds.exceeded(t) && fully_linked(t, analyzer, state))
+        .collect()
+}
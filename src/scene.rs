@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+/// Remaps a range of scene numbered episodes (`S01E26-E37`) onto the library numbering, starting at
+/// `target` (`S02E01`) and counting up from there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SceneException {
+    season: u32,
+    first: u32,
+    last: u32,
+    target_season: u32,
+    target_episode: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct SceneExceptions {
+    shows: BTreeMap<String, Vec<SceneException>>,
+}
+
+struct EpisodeRange {
+    season: u32,
+    first: u32,
+    last_season: Option<u32>,
+    last: Option<u32>,
+}
+
+fn parse_episode(s: &str) -> Option<EpisodeRange> {
+    let re = regex::Regex::new(r"(?i)^s(\d+)e(\d+)(?:-(?:s(\d+))?e(\d+))?$").unwrap();
+    let x = re.captures(s.trim())?;
+    let number = |i: usize| x.get(i).map(|m| m.as_str().parse::<u32>().unwrap());
+
+    Some(EpisodeRange { season: number(1)?, first: number(2)?, last_season: number(3), last: number(4) })
+}
+
+impl SceneExceptions {
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read scene exceptions {:?}: {}", path, e))?;
+
+        Self::parse(&contents).map_err(|e| format!("invalid scene exceptions {:?}: {}", path, e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        Self::from_table(toml::from_str(contents).map_err(|e| e.to_string())?)
+    }
+
+    /// Per show, scene ranges and where they start in the library, like `S01E26-E37 = "S02E01"`.
+    pub fn from_table(table: BTreeMap<String, BTreeMap<String, String>>) -> Result<Self, String> {
+        let mut shows = BTreeMap::new();
+        for (show, mappings) in table {
+            let mut exceptions = vec![];
+            for (scene, target) in mappings {
+                let range = parse_episode(&scene)
+                    .ok_or_else(|| format!("{}: invalid scene episode {:?}", show, scene))?;
+                let target = parse_episode(&target)
+                    .filter(|t| t.last.is_none())
+                    .ok_or_else(|| format!("{}: invalid target episode {:?}", show, target))?;
+
+                if range.last_season.is_some_and(|s| s != range.season) {
+                    return Err(format!("{}: scene range {:?} spans multiple seasons", show, scene));
+                }
+
+                let last = range.last.unwrap_or(range.first);
+                if last < range.first {
+                    return Err(format!("{}: scene range {:?} is reversed", show, scene));
+                }
+
+                exceptions.push(SceneException {
+                    season: range.season,
+                    first: range.first,
+                    last,
+                    target_season: target.season,
+                    target_episode: target.first,
+                });
+            }
+
+            shows.insert(show.to_lowercase(), exceptions);
+        }
+
+        Ok(Self { shows })
+    }
+
+    pub fn remap(&self, name: &str, season: u32, episode: u32) -> (u32, u32) {
+        self.shows
+            .get(name)
+            .and_then(|exceptions| {
+                exceptions
+                    .iter()
+                    .find(|e| e.season == season && (e.first..=e.last).contains(&episode))
+            })
+            .map_or((season, episode), |e| (e.target_season, e.target_episode + episode - e.first))
+    }
+}
+
+/// A per-show numbering adjustment like `episode -= 12`, optionally limited to a parsed season
+/// (`season 2: episode += 12`).
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Offset {
+    when_season: Option<u32>,
+    season: bool,
+    delta: i32,
+}
+
+impl TryFrom<String> for Offset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let re = regex::Regex::new(r"^(?:season (\d+): *)?(season|episode) *([+-])= *(\d+)$").unwrap();
+        let x = re
+            .captures(s.trim())
+            .ok_or_else(|| format!("invalid offset {:?}, expected something like \"season 2: episode += 12\"", s))?;
+
+        let delta = x[4].parse::<i32>().map_err(|e| format!("invalid offset {:?}: {}", s, e))?;
+
+        Ok(Self {
+            when_season: x.get(1).map(|m| m.as_str().parse().unwrap()),
+            season: &x[2] == "season",
+            delta: if &x[3] == "-" { -delta } else { delta },
+        })
+    }
+}
+
+impl Offset {
+    /// Conditions are checked against the numbering before any offsets were applied.
+    pub fn apply_all(offsets: &[Offset], season: u32, episode: u32) -> (u32, u32) {
+        let (mut s, mut e) = (season, episode);
+
+        for offset in offsets.iter().filter(|o| o.when_season.is_none_or(|w| w == season)) {
+            if offset.season {
+                s = s.saturating_add_signed(offset.delta);
+            } else {
+                e = e.saturating_add_signed(offset.delta);
+            }
+        }
+
+        (s, e)
+    }
+}
@@ -0,0 +1,132 @@
+//! Cron-like windows for the destructive part of a run.
+
+/// Minute, hour, day of month, month and day of week, with `*`, lists, ranges and steps
+/// (`*/15 4-5 * * 1-5`). A window is every minute the expression matches.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Both day fields restricted means either can match, like cron does.
+    any_day: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    pub minute: u32,
+    pub hour: u32,
+    /// 1 to 31.
+    pub day: u32,
+    /// 1 to 12.
+    pub month: u32,
+    /// 0 is Sunday.
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    pub fn now() -> Self {
+        Self::at(crate::state::now())
+    }
+
+    /// From a unix timestamp.
+    pub fn at(timestamp: u64) -> Self {
+        let timestamp = timestamp as libc::time_t;
+        // SAFETY: localtime_r only writes to the tm it's given.
+        let tm = unsafe {
+            let mut tm = std::mem::zeroed::<libc::tm>();
+            libc::localtime_r(&timestamp, &mut tm);
+            tm
+        };
+
+        Self {
+            year: tm.tm_year + 1900,
+            minute: tm.tm_min as u32,
+            hour: tm.tm_hour as u32,
+            day: tm.tm_mday as u32,
+            month: tm.tm_mon as u32 + 1,
+            weekday: tm.tm_wday as u32,
+        }
+    }
+}
+
+impl std::fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{:02}-{:02} {:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute)
+    }
+}
+
+/// Bitset of the values a field matches.
+fn field(s: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse().ok()?, b.parse().ok()?),
+                None if step > 1 => (range.parse().ok()?, max),
+                None => (range.parse().ok()?, range.parse().ok()?),
+            },
+        };
+
+        if first < min || last > max || first > last {
+            return None;
+        }
+
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Some(set)
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let error = || format!("invalid schedule {:?}, expected something like \"* 4-5 * * *\"", s);
+
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else { return Err(error()); };
+
+        // 7 is Sunday too.
+        let weekdays = field(weekdays, 0, 7).ok_or_else(error)?;
+        let weekdays = (weekdays | weekdays >> 7) & 0x7f;
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59).ok_or_else(error)?,
+            hours: field(hours, 0, 23).ok_or_else(error)?,
+            days: field(days, 1, 31).ok_or_else(error)?,
+            months: field(months, 1, 12).ok_or_else(error)?,
+            weekdays,
+            any_day: days != "*" && weekdays != 0x7f,
+        })
+    }
+}
+
+impl Cron {
+    pub fn matches(&self, time: &LocalTime) -> bool {
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+
+        let day = has(self.days, time.day);
+        let weekday = has(self.weekdays, time.weekday);
+        let day = if self.any_day { day || weekday } else { day && weekday };
+
+        has(self.minutes, time.minute) && has(self.hours, time.hour) && has(self.months, time.month) && day
+    }
+}
+
+/// No windows configured means any time.
+pub fn allows(windows: &[Cron], time: &LocalTime) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.matches(time))
+}
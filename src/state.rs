@@ -0,0 +1,640 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::mounts::Identity;
+use crate::ScannedFile;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub source: std::path::PathBuf,
+    pub link: std::path::PathBuf,
+    pub inode: u64,
+    /// Unix timestamp of the first run that created this link.
+    pub linked_at: u64,
+    #[serde(default)]
+    pub size: u64,
+    /// Partial content hash of the source, see `fingerprint`.
+    #[serde(default)]
+    pub fingerprint: Option<u64>,
+}
+
+/// A source whose link was deliberately removed from the library, so it's not linked again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub source: std::path::PathBuf,
+    pub link: std::path::PathBuf,
+    pub at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    Seen,
+    /// The file was recognized as something other than last time (or for the first time).
+    Parsed { media: String },
+    Unrecognized { warning: String },
+    Linked { link: std::path::PathBuf },
+    Removed { link: std::path::PathBuf },
+    /// A linked source went away from the incoming directory.
+    Gone,
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Event::Seen => write!(f, "first seen"),
+            Event::Parsed { media } => write!(f, "parsed as {}", media),
+            Event::Unrecognized { warning } => write!(f, "not recognized: {}", warning),
+            Event::Linked { link } => write!(f, "linked to {:?}", link),
+            Event::Removed { link } => write!(f, "link {:?} removed", link),
+            Event::Gone => write!(f, "gone from the incoming directory"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub at: u64,
+    pub source: std::path::PathBuf,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub links: Vec<LinkRecord>,
+    /// Files that couldn't be recognized on the last run, so their warnings are only shown once.
+    pub warned: BTreeSet<std::path::PathBuf>,
+    /// What happened to each source over time, oldest first.
+    pub history: Vec<HistoryRecord>,
+    pub tombstones: Vec<Tombstone>,
+    /// Blocklisted files already reported, like `warned`.
+    pub blocked: BTreeSet<std::path::PathBuf>,
+    /// Sources Sonarr or Radarr were asked to import, so they're only asked once.
+    pub imported: BTreeSet<std::path::PathBuf>,
+    /// What each directory set up by `harvester init` was on, by absolute path.
+    pub filesystems: BTreeMap<std::path::PathBuf, Identity>,
+    /// Parsed show names and the name they're filed under, set by `harvester rename-show`.
+    pub aliases: BTreeMap<String, String>,
+    /// Episodes moved by `harvester move-episodes`, per show and in the scene exceptions format,
+    /// applied to the library numbering.
+    pub renumbered: BTreeMap<String, BTreeMap<String, String>>,
+    /// TMDB ids and collections found on earlier runs, which folder names carry.
+    pub tmdb: crate::tmdb::Lookups,
+    /// AniList titles by parsed name, which anime folders are named after.
+    pub anilist: BTreeMap<String, Option<crate::anilist::Titles>>,
+}
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// How long a gone source's history is kept, in seconds.
+pub const HISTORY_RETENTION: u64 = 90 * 24 * 60 * 60;
+
+/// Changes to the state file's layout that older files need rewritten for, as `MIGRATIONS[n]`
+/// taking a file from schema version `n` to `n + 1`. Files from before versions were recorded
+/// are version 0. New fields with a default don't need one.
+const MIGRATIONS: &[fn(&mut serde_json::Value)] = &[
+    // 0 to 1: only the version was added.
+    |_| {},
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// The schema version of a state file's contents.
+pub fn schema(value: &serde_json::Value) -> u32 {
+    value.get("schema").and_then(serde_json::Value::as_u64).map_or(0, |v| v as u32)
+}
+
+/// Brings a state file's contents up to `SCHEMA_VERSION`, returning the version it was at.
+/// Newer files are refused, rather than losing whatever the newer harvester put in them.
+pub fn migrate(value: &mut serde_json::Value) -> Result<u32, String> {
+    let from = schema(value);
+    if from > SCHEMA_VERSION {
+        return Err(format!("schema version {} is newer than this harvester's ({}), upgrade harvester to use it", from, SCHEMA_VERSION));
+    }
+
+    for migration in MIGRATIONS[from as usize..].iter() {
+        migration(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("schema".to_string(), SCHEMA_VERSION.into());
+    }
+
+    Ok(from)
+}
+
+/// `harvester state export` output, the whole state plus enough to tell where it came from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub exported_at: u64,
+    pub state: State,
+}
+
+/// Replaces the `from` prefix with `to`, for paths that moved to another machine.
+fn rebase(path: &mut std::path::PathBuf, rebases: &[(std::path::PathBuf, std::path::PathBuf)]) {
+    if let Some((rest, to)) = rebases.iter().find_map(|(from, to)| Some((path.strip_prefix(from).ok()?, to))) {
+        *path = match rest.as_os_str().is_empty() {
+            true => to.clone(),
+            false => to.join(rest),
+        };
+    }
+}
+
+const FINGERPRINT_CHUNK: u64 = 1 << 20;
+
+/// FNV-1a over the size and the first and last MiB of the file, enough to tell apart releases that
+/// happen to have the same size while staying cheap on large files.
+pub fn fingerprint(path: &std::path::Path) -> std::io::Result<(u64, u64)> {
+    use std::io::{Read, Seek};
+
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            hash ^= *b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+
+    feed(&size.to_le_bytes());
+
+    let mut buffer = vec![];
+    (&mut file).take(FINGERPRINT_CHUNK).read_to_end(&mut buffer)?;
+    feed(&buffer);
+
+    if size > 2 * FINGERPRINT_CHUNK {
+        buffer.clear();
+        file.seek(std::io::SeekFrom::End(-(FINGERPRINT_CHUNK as i64)))?;
+        file.read_to_end(&mut buffer)?;
+        feed(&buffer);
+    } else if size > FINGERPRINT_CHUNK {
+        buffer.clear();
+        file.read_to_end(&mut buffer)?;
+        feed(&buffer);
+    }
+
+    Ok((size, hash))
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl State {
+    pub fn default_dir() -> Option<std::path::PathBuf> {
+        let base = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".local").join("state"),
+        };
+
+        Some(base.join("harvester"))
+    }
+
+    /// Migrating it when it's from an older harvester, keeping a copy of the file as it was next
+    /// to it (`state.v0.json`) in case the new version has to be rolled back.
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("unable to read state {:?}: {}", path, e)),
+        };
+
+        let mut value = serde_json::from_str(&contents).map_err(|e| format!("invalid state {:?}: {}", path, e))?;
+        let from = migrate(&mut value).map_err(|e| format!("state {:?} has {}", path, e))?;
+        if from < SCHEMA_VERSION {
+            let backup = path.with_extension(format!("v{}.json", from));
+            if !backup.exists() {
+                std::fs::write(&backup, &contents).map_err(|e| format!("unable to back up state to {:?}: {}", backup, e))?;
+            }
+        }
+
+        serde_json::from_value(value).map_err(|e| format!("invalid state {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("unable to create {:?}: {}", parent, e))?;
+        }
+
+        let tmp = path.with_extension("json.tmp");
+        let mut value = serde_json::to_value(self).unwrap();
+        value.as_object_mut().unwrap().insert("schema".to_string(), SCHEMA_VERSION.into());
+        let contents = serde_json::to_string_pretty(&value).unwrap();
+        std::fs::write(&tmp, contents).map_err(|e| format!("unable to write state {:?}: {}", tmp, e))?;
+        std::fs::rename(&tmp, path).map_err(|e| format!("unable to write state {:?}: {}", path, e))
+    }
+
+    pub fn snapshot(&self, now: u64) -> Snapshot {
+        Snapshot { version: SNAPSHOT_VERSION, exported_at: now, state: self.clone() }
+    }
+
+    /// Merges a snapshot into this state, or replaces it. Records for the same source and link
+    /// are taken from the snapshot.
+    pub fn import(&mut self, snapshot: Snapshot, rebases: &[(std::path::PathBuf, std::path::PathBuf)], replace: bool) -> Result<(), String> {
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(format!("snapshot version {} is newer than this harvester's ({})", snapshot.version, SNAPSHOT_VERSION));
+        }
+
+        let mut imported = snapshot.state;
+        for record in imported.links.iter_mut() {
+            rebase(&mut record.source, rebases);
+            rebase(&mut record.link, rebases);
+        }
+        for record in imported.history.iter_mut() {
+            rebase(&mut record.source, rebases);
+            if let Event::Linked { link } | Event::Removed { link } = &mut record.event {
+                rebase(link, rebases);
+            }
+        }
+        imported.warned = imported.warned.into_iter().map(|mut p| { rebase(&mut p, rebases); p }).collect();
+        imported.blocked = imported.blocked.into_iter().map(|mut p| { rebase(&mut p, rebases); p }).collect();
+        for tombstone in imported.tombstones.iter_mut() {
+            rebase(&mut tombstone.source, rebases);
+            rebase(&mut tombstone.link, rebases);
+        }
+
+        // Filesystem identities only make sense on the machine they were recorded on.
+        if replace {
+            imported.filesystems = std::mem::take(&mut self.filesystems);
+            *self = imported;
+            return Ok(());
+        }
+
+        self.links.retain(|r| !imported.links.iter().any(|i| i.source == r.source && i.link == r.link));
+        self.links.extend(imported.links);
+        self.warned.extend(imported.warned);
+        self.blocked.extend(imported.blocked);
+        self.aliases.extend(imported.aliases);
+        for (show, moves) in imported.renumbered {
+            self.renumbered.entry(show).or_default().extend(moves);
+        }
+        for tombstone in imported.tombstones {
+            if !self.is_tombstoned(&tombstone.source) {
+                self.tombstones.push(tombstone);
+            }
+        }
+        for record in imported.history {
+            if !self.history.contains(&record) {
+                self.history.push(record);
+            }
+        }
+        self.history.sort_by_key(|r| r.at);
+
+        Ok(())
+    }
+
+    /// The directory's filesystem if it's not the one recorded at init.
+    pub fn changed_filesystem(&self, mounts: &[crate::mounts::Mount], dir: &std::path::Path) -> Option<(&Identity, Identity)> {
+        let absolute = std::path::absolute(dir).unwrap_or(dir.to_path_buf());
+        let recorded = self.filesystems.get(&absolute)?;
+        let current = crate::mounts::identity(mounts, dir).ok()?;
+
+        (*recorded != current).then_some((recorded, current))
+    }
+
+    /// Errors if any of the directories is on another filesystem than at init, a swapped disk
+    /// reusing the same path would have links made from or removed in the wrong data.
+    pub fn check_filesystems(&self, mounts: &[crate::mounts::Mount], dirs: &[&std::path::Path]) -> Result<(), String> {
+        let changed = dirs
+            .iter()
+            .filter_map(|dir| {
+                let (recorded, current) = self.changed_filesystem(mounts, dir)?;
+                Some(format!("{:?} is on {} now, but harvester init found it on {}", dir, current, recorded))
+            })
+            .collect::<Vec<_>>();
+
+        match changed.is_empty() {
+            true => Ok(()),
+            false => Err(changed.join("\n")),
+        }
+    }
+
+    /// Replaces the recorded links with the current ones, keeping the original link time of the
+    /// ones that were already there. New sources get fingerprinted.
+    pub fn record_links(&mut self, links: Vec<(std::path::PathBuf, std::path::PathBuf, u64)>, now: u64) {
+        self.links = links
+            .into_iter()
+            .map(|(source, link, inode)| {
+                let previous = self.links.iter().find(|r| r.source == source && r.link == link);
+                let linked_at = previous.map_or(now, |r| r.linked_at);
+
+                let (size, fingerprint) = match self.links.iter().find(|r| r.source == source && r.inode == inode && r.fingerprint.is_some()) {
+                    Some(r) => (r.size, r.fingerprint),
+                    None => fingerprint(&source).map_or((0, None), |(size, hash)| (size, Some(hash))),
+                };
+
+                LinkRecord { source, link, inode, linked_at, size, fingerprint }
+            })
+            .collect();
+    }
+
+    /// Appends what changed since the last run: new files, new parses, links created and removed
+    /// (by absolute link path), and linked sources that went away. Call it before `record_links`.
+    /// Timelines of sources gone for `HISTORY_RETENTION` are dropped.
+    pub fn record_history(&mut self, files: &[ScannedFile], added: &[(std::path::PathBuf, std::path::PathBuf)], removed: &[std::path::PathBuf], now: u64) {
+        let mut events = vec![];
+        let mut sources = std::collections::HashSet::new();
+
+        // The last parse of each source with a timeline, the latest record wins.
+        let mut parses = std::collections::HashMap::<&std::path::Path, Option<&Event>>::new();
+        for record in self.history.iter() {
+            let last = parses.entry(&record.source).or_default();
+            if matches!(record.event, Event::Parsed { .. } | Event::Unrecognized { .. }) {
+                *last = Some(&record.event);
+            }
+        }
+
+        for file in files.iter().filter(|f| f.metadata != Some(crate::MediaData::Garbage)) {
+            let source = std::fs::canonicalize(&file.path).unwrap_or(file.path.clone());
+            let last = parses.get(source.as_path());
+            if last.is_none() {
+                events.push((source.clone(), Event::Seen));
+            }
+
+            let parsed = match (&file.metadata, &file.warning) {
+                (Some(media), _) => Event::Parsed { media: media.to_string() },
+                (None, warning) => Event::Unrecognized { warning: warning.clone().unwrap_or_default() },
+            };
+            if last.copied().flatten() != Some(&parsed) {
+                events.push((source.clone(), parsed));
+            }
+
+            sources.insert(source);
+        }
+
+        events.extend(self.change_events(added, removed));
+
+        let gone = self.links.iter().map(|r| &r.source).filter(|s| !sources.contains(*s)).collect::<BTreeSet<_>>();
+        for source in gone {
+            events.push((source.clone(), Event::Gone));
+        }
+
+        self.history.extend(events.into_iter().map(|(source, event)| HistoryRecord { at: now, source, event }));
+        self.prune_history(&sources, now);
+    }
+
+    /// Drops the timelines of sources that aren't in the incoming directory (`present`) nor linked
+    /// anymore, and had nothing happen to them for `HISTORY_RETENTION`.
+    fn prune_history(&mut self, present: &std::collections::HashSet<std::path::PathBuf>, now: u64) {
+        let mut last = std::collections::HashMap::<&std::path::Path, u64>::new();
+        for record in self.history.iter() {
+            last.insert(&record.source, record.at);
+        }
+
+        let linked = self.links.iter().map(|r| r.source.as_path()).collect::<std::collections::HashSet<_>>();
+        let expired = last
+            .into_iter()
+            .filter(|(source, at)| now.saturating_sub(*at) > HISTORY_RETENTION && !linked.contains(source) && !present.contains(*source))
+            .map(|(source, _)| source.to_path_buf())
+            .collect::<std::collections::HashSet<_>>();
+
+        self.history.retain(|r| !expired.contains(&r.source));
+    }
+
+    /// Just the links created and removed, for changes made without a scan like `harvester resume`'s.
+    pub fn record_changes(&mut self, added: &[(std::path::PathBuf, std::path::PathBuf)], removed: &[std::path::PathBuf], now: u64) {
+        let events = self.change_events(added, removed);
+        self.history.extend(events.into_iter().map(|(source, event)| HistoryRecord { at: now, source, event }));
+    }
+
+    fn change_events(&self, added: &[(std::path::PathBuf, std::path::PathBuf)], removed: &[std::path::PathBuf]) -> Vec<(std::path::PathBuf, Event)> {
+        let mut events = vec![];
+        for (source, link) in added {
+            events.push((source.clone(), Event::Linked { link: link.clone() }));
+        }
+
+        for link in removed {
+            if let Some(record) = self.links.iter().find(|r| r.link == *link) {
+                events.push((record.source.clone(), Event::Removed { link: link.clone() }));
+            }
+        }
+
+        events
+    }
+
+    /// Sources whose path or any of their parses mention `query` (case insensitive), with their history.
+    pub fn history_of(&self, query: &str) -> Vec<(&std::path::Path, Vec<&HistoryRecord>)> {
+        let query = query.to_lowercase();
+        let mentions = |r: &HistoryRecord| match &r.event {
+            Event::Parsed { media } => media.to_lowercase().contains(&query),
+            _ => r.source.to_string_lossy().to_lowercase().contains(&query),
+        };
+
+        let sources = self.history.iter().filter(|r| mentions(r)).map(|r| r.source.as_path()).collect::<BTreeSet<_>>();
+        sources
+            .into_iter()
+            .map(|source| (source, self.history.iter().filter(|r| r.source == source).collect()))
+            .collect()
+    }
+
+    /// Recognized files with the same contents as a linked one with a different inode, along with
+    /// the record linked first. Files linked as a version of it on a previous run still are
+    /// duplicates, so their link stays put, files linked any other way aren't.
+    pub fn content_duplicates<'f>(&self, files: &'f [ScannedFile]) -> Vec<(&'f ScannedFile, &LinkRecord)> {
+        let mut duplicates = vec![];
+
+        for file in files.iter().filter(|f| f.metadata.as_ref().is_some_and(|m| *m != crate::MediaData::Garbage)) {
+            let same_size = self.links.iter().filter(|r| r.size == file.size && r.inode != file.inode && r.fingerprint.is_some());
+            let mut same_size = same_size.peekable();
+            if same_size.peek().is_none() {
+                continue;
+            }
+
+            let Ok((_, hash)) = fingerprint(&file.path) else { continue; };
+            let Some(original) = same_size.filter(|r| r.fingerprint == Some(hash)).min_by_key(|r| r.linked_at) else { continue; };
+
+            let source = std::fs::canonicalize(&file.path).unwrap_or(file.path.clone());
+            let version = crate::version_link(file, &original.link);
+            if self.links.iter().any(|r| r.source == source && r.link != version) {
+                continue;
+            }
+
+            duplicates.push((file, original));
+        }
+
+        duplicates
+    }
+
+    /// Files with warnings that weren't reported on a previous run.
+    pub fn new_warnings<'f>(&self, files: &'f [ScannedFile]) -> Vec<&'f ScannedFile> {
+        files.iter().filter(|f| f.warning.is_some() && !self.warned.contains(&f.path)).collect()
+    }
+
+    /// Forgets the ones that were fixed or went away.
+    pub fn record_warnings(&mut self, files: &[ScannedFile]) {
+        self.warned = files.iter().filter(|f| f.warning.is_some()).map(|f| f.path.clone()).collect();
+    }
+
+    /// Blocklisted files that weren't reported on a previous run. Forgets the ones that went away.
+    pub fn record_blocked(&mut self, blocked: Vec<std::path::PathBuf>) -> Vec<std::path::PathBuf> {
+        let new = blocked.iter().filter(|p| !self.blocked.contains(*p)).cloned().collect();
+        self.blocked = blocked.into_iter().collect();
+        new
+    }
+
+    /// The sources that weren't handed to Sonarr or Radarr yet, out of the canonical `sources`
+    /// that import through them. Forgets the ones that went away.
+    pub fn new_imports<'a>(&mut self, sources: &'a [std::path::PathBuf]) -> Vec<&'a std::path::PathBuf> {
+        self.imported.retain(|p| sources.contains(p));
+        sources.iter().filter(|p| !self.imported.contains(*p)).collect()
+    }
+
+    pub fn record_imported(&mut self, source: &std::path::Path) {
+        self.imported.insert(source.to_path_buf());
+    }
+
+    /// Recognized files whose inode is already linked from a different source, along with that
+    /// source. The one linked on a previous run wins, otherwise the first one scanned does.
+    pub fn duplicates(&self, files: &[ScannedFile]) -> Vec<(std::path::PathBuf, std::path::PathBuf)> {
+        let videos = files
+            .iter()
+            .filter(|f| f.metadata.as_ref().is_some_and(|m| *m != crate::MediaData::Garbage))
+            .map(|f| (f, std::fs::canonicalize(&f.path).unwrap_or(f.path.clone())))
+            .collect::<Vec<_>>();
+
+        let mut seen: std::collections::HashMap<u64, std::path::PathBuf> = std::collections::HashMap::new();
+        for (file, source) in videos.iter() {
+            if self.is_linked(source) {
+                seen.insert(file.inode, source.clone());
+            }
+        }
+
+        let mut duplicates = vec![];
+        for (file, source) in videos {
+            let original = seen.entry(file.inode).or_insert(source.clone());
+            if *original != source {
+                duplicates.push((file.path.clone(), original.clone()));
+            }
+        }
+
+        duplicates
+    }
+
+    /// Forgets the link and keeps its source from being linked again.
+    pub fn tombstone(&mut self, record: &LinkRecord, now: u64) {
+        self.links.retain(|r| r != record);
+        if !self.is_tombstoned(&record.source) {
+            self.tombstones.push(Tombstone { source: record.source.clone(), link: record.link.clone(), at: now });
+        }
+    }
+
+    pub fn is_tombstoned(&self, source: &std::path::Path) -> bool {
+        self.tombstones.iter().any(|t| t.source == source)
+    }
+
+    /// Tombstones the recorded links that are gone from the library while their source is still
+    /// among `sources`, returning the new tombstones.
+    pub fn tombstone_deleted(&mut self, sources: &std::collections::HashSet<std::path::PathBuf>, now: u64) -> Vec<Tombstone> {
+        let deleted = self
+            .links
+            .iter()
+            .filter(|r| sources.contains(&r.source) && std::fs::symlink_metadata(&r.link).is_err())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let before = self.tombstones.len();
+        for record in deleted.iter() {
+            self.tombstone(record, now);
+        }
+
+        self.tombstones[before..].to_vec()
+    }
+
+    /// Removes the tombstones whose source or link path mentions `query` (case insensitive), or
+    /// every one without a query, so they're linked again on the next run.
+    pub fn restore(&mut self, query: Option<&str>) -> Vec<Tombstone> {
+        let query = query.map(str::to_lowercase);
+        let matches = |t: &Tombstone| match &query {
+            Some(query) => [&t.source, &t.link].iter().any(|p| p.to_string_lossy().to_lowercase().contains(query)),
+            None => true,
+        };
+
+        let (restored, kept) = std::mem::take(&mut self.tombstones).into_iter().partition(matches);
+        self.tombstones = kept;
+        restored
+    }
+
+    /// Inconsistencies no run should leave behind, for `harvester state check`.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let mut links = BTreeMap::new();
+        for record in self.links.iter() {
+            if let Some(source) = links.insert(&record.link, &record.source) {
+                problems.push(format!("{:?} is recorded twice, linking {:?} and {:?}", record.link, source, record.source));
+            }
+        }
+        for tombstone in self.tombstones.iter().filter(|t| self.has_link(&t.source, &t.link)) {
+            problems.push(format!("{:?} is both linked and tombstoned", tombstone.link));
+        }
+
+        problems
+    }
+
+    pub fn has_link(&self, source: &std::path::Path, link: &std::path::Path) -> bool {
+        self.links.iter().any(|r| r.source == source && r.link == link)
+    }
+
+    pub fn is_linked(&self, source: &std::path::Path) -> bool {
+        self.links.iter().any(|r| r.source == source)
+    }
+
+    /// The name a show is filed under, if it was renamed.
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, alias)| alias.as_str())
+    }
+
+    /// Files the show under `new` from now on. `old` is either its parsed name or the one it was
+    /// renamed to before, in which case every parsed name filed under it moves along.
+    pub fn rename_show(&mut self, old: &str, new: &str) {
+        let mut renamed = false;
+        for alias in self.aliases.values_mut().filter(|a| a.eq_ignore_ascii_case(old)) {
+            *alias = new.to_string();
+            renamed = true;
+        }
+
+        if !renamed {
+            self.aliases.retain(|n, _| !n.eq_ignore_ascii_case(old));
+            self.aliases.insert(old.to_lowercase(), new.to_string());
+        }
+    }
+
+    /// The directories named `name` that recorded links are in, like a show's folder in each
+    /// library it's in.
+    pub fn link_dirs_named(&self, name: &str) -> BTreeSet<std::path::PathBuf> {
+        self.links
+            .iter()
+            .flat_map(|r| r.link.ancestors().skip(1).find(|a| a.file_name().is_some_and(|n| n == name)))
+            .map(std::path::Path::to_path_buf)
+            .collect()
+    }
+
+    /// Moves library episodes `first` to `last` of a show's `season` to `target_season`, starting
+    /// at its first episode.
+    pub fn renumber(&mut self, show: &str, season: u32, first: u32, last: u32, target_season: u32) {
+        let moves = self.renumbered.entry(show.to_lowercase()).or_default();
+        moves.insert(format!("S{:02}E{:02}-E{:02}", season, first, last), format!("S{:02}E01", target_season));
+    }
+
+    pub fn renumbering(&self) -> Result<crate::scene::SceneExceptions, String> {
+        crate::scene::SceneExceptions::from_table(self.renumbered.clone()).map_err(|e| format!("invalid moved episodes in the state: {}", e))
+    }
+
+    /// Points the records of links under `from` (or of the link at `from`) to `to`, once it was
+    /// moved there.
+    pub fn move_links(&mut self, from: &std::path::Path, to: &std::path::Path) {
+        let rebases = [(from.to_path_buf(), to.to_path_buf())];
+        for record in self.links.iter_mut() {
+            rebase(&mut record.link, &rebases);
+        }
+        for tombstone in self.tombstones.iter_mut() {
+            rebase(&mut tombstone.link, &rebases);
+        }
+    }
+}
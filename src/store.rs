@@ -0,0 +1,182 @@
+//! Where the state is kept between runs, behind `StateStore` so the backend can be picked in the
+//! config: a plain JSON file, or a SQLite database for those who want to query it. SQLite goes
+//! through the `sqlite3` command rather than a linked library, so minimal installs on routers and
+//! NAS packages don't need it unless they pick it. Everything reading or writing the state goes
+//! through this, so another backend only needs to implement it.
+
+use crate::state::State;
+
+/// `Send` so the daemon's control socket can read the state from its own thread.
+pub trait StateStore: Send {
+    /// An empty state when there's none yet.
+    fn load(&self) -> Result<State, String>;
+    fn save(&self, state: &State) -> Result<(), String>;
+    /// The schema version it's stored with, without migrating it, or none when there's no state yet.
+    fn schema(&self) -> Result<Option<u32>, String>;
+    /// The file (or directory) it's kept in, other files like the journal go next to it.
+    fn path(&self) -> &std::path::Path;
+}
+
+/// One pretty printed JSON file, replaced atomically on each save.
+pub struct JsonStore {
+    pub path: std::path::PathBuf,
+}
+
+impl StateStore for JsonStore {
+    fn load(&self) -> Result<State, String> {
+        State::load(&self.path)
+    }
+
+    fn save(&self, state: &State) -> Result<(), String> {
+        state.save(&self.path)
+    }
+
+    fn schema(&self) -> Result<Option<u32>, String> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("unable to read state {:?}: {}", self.path, e)),
+        };
+        let value = serde_json::from_str(&contents).map_err(|e| format!("invalid state {:?}: {}", self.path, e))?;
+
+        Ok(Some(crate::state::schema(&value)))
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+/// A SQLite database, through the `sqlite3` command. The state is kept whole as JSON in the
+/// `state` table, and `links` has a row per link to query, rewritten on each save:
+/// `sqlite3 state.sqlite "SELECT link FROM links WHERE source LIKE '%/Show/%'"`.
+pub struct SqliteStore {
+    pub path: std::path::PathBuf,
+}
+
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS state (id INTEGER PRIMARY KEY CHECK (id = 1), json TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS links (source TEXT NOT NULL, link TEXT NOT NULL, inode INTEGER NOT NULL, linked_at INTEGER NOT NULL, size INTEGER NOT NULL, fingerprint TEXT);
+";
+
+/// As an SQL string literal.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+impl SqliteStore {
+    /// Runs `sql` against the database, returning what it printed.
+    fn sqlite3(&self, sql: &str, read_only: bool) -> Result<String, String> {
+        use std::io::Write;
+
+        let mut command = std::process::Command::new("sqlite3");
+        command.arg("-bail").args(read_only.then_some("-readonly")).arg(&self.path);
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("unable to run sqlite3 for state {:?}: {}", self.path, e))?;
+
+        child.stdin.take().unwrap().write_all(sql.as_bytes()).map_err(|e| format!("unable to write to sqlite3: {}", e))?;
+        let output = child.wait_with_output().map_err(|e| format!("unable to run sqlite3: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("sqlite3 failed on state {:?}: {}", self.path, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// The state's JSON, none when there's no database yet.
+    fn contents(&self) -> Result<Option<String>, String> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        let json = self.sqlite3("SELECT json FROM state;", true)?;
+        Ok(Some(json.trim_end().to_string()).filter(|json| !json.is_empty()))
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn load(&self) -> Result<State, String> {
+        let Some(contents) = self.contents()? else { return Ok(State::default()); };
+
+        let mut value = serde_json::from_str(&contents).map_err(|e| format!("invalid state {:?}: {}", self.path, e))?;
+        let from = crate::state::migrate(&mut value).map_err(|e| format!("state {:?} has {}", self.path, e))?;
+        if from < crate::state::SCHEMA_VERSION {
+            let backup = self.path.with_extension(format!("v{}.sqlite", from));
+            if !backup.exists() {
+                std::fs::copy(&self.path, &backup).map_err(|e| format!("unable to back up state to {:?}: {}", backup, e))?;
+            }
+        }
+
+        serde_json::from_value(value).map_err(|e| format!("invalid state {:?}: {}", self.path, e))
+    }
+
+    /// In one transaction, a failed save leaves the previous state.
+    fn save(&self, state: &State) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("unable to create {:?}: {}", parent, e))?;
+        }
+
+        let mut value = serde_json::to_value(state).unwrap();
+        value.as_object_mut().unwrap().insert("schema".to_string(), crate::state::SCHEMA_VERSION.into());
+
+        let mut sql = format!("BEGIN;\n{}DELETE FROM state;\nDELETE FROM links;\n", SQLITE_SCHEMA);
+        sql += &format!("INSERT INTO state VALUES (1, {});\n", quote(&value.to_string()));
+        for r in state.links.iter() {
+            sql += &format!(
+                "INSERT INTO links VALUES ({}, {}, {}, {}, {}, {});\n",
+                quote(&r.source.to_string_lossy()),
+                quote(&r.link.to_string_lossy()),
+                r.inode,
+                r.linked_at,
+                r.size,
+                r.fingerprint.map_or("NULL".to_string(), |f| quote(&format!("{:016x}", f))),
+            );
+        }
+        sql += "COMMIT;\n";
+
+        self.sqlite3(&sql, false).map(|_| ())
+    }
+
+    fn schema(&self) -> Result<Option<u32>, String> {
+        let Some(contents) = self.contents()? else { return Ok(None); };
+        let value = serde_json::from_str(&contents).map_err(|e| format!("invalid state {:?}: {}", self.path, e))?;
+
+        Ok(Some(crate::state::schema(&value)))
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+/// Keeps it in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    pub path: std::path::PathBuf,
+    pub state: std::cell::RefCell<Option<State>>,
+}
+
+#[cfg(test)]
+impl StateStore for MemoryStore {
+    fn load(&self) -> Result<State, String> {
+        Ok(self.state.borrow().clone().unwrap_or_default())
+    }
+
+    fn save(&self, state: &State) -> Result<(), String> {
+        *self.state.borrow_mut() = Some(state.clone());
+        Ok(())
+    }
+
+    fn schema(&self) -> Result<Option<u32>, String> {
+        Ok(self.state.borrow().as_ref().map(|_| crate::state::SCHEMA_VERSION))
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
@@ -0,0 +1,217 @@
+//! Subtitles from OpenSubtitles for new links without them, saved next to the link the way
+//! Jellyfin picks them up (`episode 1.en.srt`).
+
+use serde::Deserialize;
+use std::io::{Read, Seek};
+
+use crate::config::SubtitlesConfig;
+use crate::MediaData;
+
+const API_URL: &str = "https://api.opensubtitles.com/api/v1";
+
+/// What Jellyfin reads as external subtitles.
+pub const EXTENSIONS: [&str; 5] = ["srt", "ass", "ssa", "sub", "vtt"];
+
+#[derive(Deserialize)]
+struct Login {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+    data: Vec<SearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    attributes: SubtitleAttributes,
+}
+
+#[derive(Deserialize)]
+struct SubtitleAttributes {
+    files: Vec<SubtitleFile>,
+}
+
+#[derive(Deserialize)]
+struct SubtitleFile {
+    file_id: u64,
+}
+
+#[derive(Deserialize)]
+struct Download {
+    link: String,
+}
+
+/// `X.en.srt` and the like next to `X.mkv`.
+pub fn is_sidecar(video: &std::path::Path, file: &std::path::Path) -> bool {
+    let (Some(video_stem), Some(name)) = (video.file_stem(), file.file_name()) else { return false; };
+
+    file.parent() == video.parent()
+        && file.extension().and_then(std::ffi::OsStr::to_str).is_some_and(|e| EXTENSIONS.contains(&e))
+        && name.to_string_lossy().starts_with(&format!("{}.", video_stem.to_string_lossy()))
+}
+
+pub fn subtitle_path(link: &std::path::Path, language: &str) -> std::path::PathBuf {
+    let stem = link.file_stem().unwrap().to_string_lossy();
+    link.with_file_name(format!("{}.{}.srt", stem, language))
+}
+
+/// Languages without a `<link>.<language>.*` subtitle next to the link.
+pub fn missing_languages<'l>(link: &std::path::Path, languages: &'l [String]) -> Vec<&'l str> {
+    let stem = link.file_stem().unwrap().to_string_lossy();
+    let siblings = link
+        .parent()
+        .and_then(|p| p.read_dir().ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| is_sidecar(link, p))
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .collect::<Vec<_>>();
+
+    languages
+        .iter()
+        .map(String::as_str)
+        .filter(|language| !siblings.iter().any(|s| s.starts_with(&format!("{}.{}.", stem, language))))
+        .collect()
+}
+
+/// The OpenSubtitles hash: the size plus the 64 bit words of the first and last 64 KiB.
+pub fn moviehash(path: &std::path::Path) -> std::io::Result<u64> {
+    const CHUNK: u64 = 64 * 1024;
+
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hash = size;
+    let mut sum = |buffer: &[u8]| {
+        for word in buffer.chunks_exact(8) {
+            hash = hash.wrapping_add(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+    };
+
+    let mut buffer = vec![];
+    (&mut file).take(CHUNK).read_to_end(&mut buffer)?;
+    sum(&buffer);
+
+    buffer.clear();
+    file.seek(std::io::SeekFrom::Start(size.saturating_sub(CHUNK)))?;
+    file.read_to_end(&mut buffer)?;
+    sum(&buffer);
+
+    Ok(hash)
+}
+
+/// Search parameters for a media item, besides the language and hash.
+pub fn query(media: &MediaData) -> Option<Vec<(&'static str, String)>> {
+    match media {
+        MediaData::ShowEpisode { name, season, episode } => Some(vec![
+            ("query", name.clone()),
+            ("season_number", season.to_string()),
+            ("episode_number", episode.to_string()),
+        ]),
+        MediaData::Movie { title, year } | MediaData::MoviePart { title, year, .. } => Some(
+            [("query", title.clone())].into_iter().chain(year.map(|y| ("year", y.to_string()))).collect(),
+        ),
+        MediaData::ShowMovie { name, title, year } | MediaData::Performance { performer: name, title, year } => Some(
+            [("query", format!("{} {}", name, title))].into_iter().chain(year.map(|y| ("year", y.to_string()))).collect(),
+        ),
+        MediaData::ShowSpecial { .. } | MediaData::SportsEvent { .. } | MediaData::HomeVideo { .. } | MediaData::Photo { .. } | MediaData::Book { .. } | MediaData::Comic { .. } | MediaData::Garbage => None,
+    }
+}
+
+pub struct OpenSubtitles<'a> {
+    config: &'a SubtitlesConfig,
+    token: Option<String>,
+}
+
+impl<'a> OpenSubtitles<'a> {
+    /// Logs in when there are credentials, downloads are limited without them.
+    pub fn new(config: &'a SubtitlesConfig) -> Result<Self, String> {
+        let token = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => {
+                let login: Login = ureq::post(&format!("{}/login", API_URL))
+                    .set("Api-Key", &config.api_key)
+                    .set("User-Agent", concat!("harvester v", env!("CARGO_PKG_VERSION")))
+                    .send_json(serde_json::json!({ "username": username, "password": password }))
+                    .map_err(|e| format!("opensubtitles login failed: {}", e))?
+                    .into_json()
+                    .map_err(|e| format!("invalid opensubtitles login response: {}", e))?;
+
+                Some(login.token)
+            }
+            _ => None,
+        };
+
+        Ok(Self { config, token })
+    }
+
+    fn request(&self, request: ureq::Request) -> ureq::Request {
+        let request = request
+            .set("Api-Key", &self.config.api_key)
+            .set("User-Agent", concat!("harvester v", env!("CARGO_PKG_VERSION")));
+
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    fn search(&self, media: &MediaData, hash: Option<u64>, language: &str) -> Result<Option<u64>, String> {
+        let Some(query) = query(media) else { return Ok(None); };
+
+        let mut request = self.request(ureq::get(&format!("{}/subtitles", API_URL))).query("languages", language);
+        for (key, value) in query.iter() {
+            request = request.query(key, value);
+        }
+        if let Some(hash) = hash {
+            request = request.query("moviehash", &format!("{:016x}", hash));
+        }
+
+        let results: SearchResults = request
+            .call()
+            .map_err(|e| format!("opensubtitles search failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("invalid opensubtitles search response: {}", e))?;
+
+        Ok(results.data.first().and_then(|r| r.attributes.files.first()).map(|f| f.file_id))
+    }
+
+    fn download(&self, file_id: u64) -> Result<String, String> {
+        let download: Download = self
+            .request(ureq::post(&format!("{}/download", API_URL)))
+            .send_json(serde_json::json!({ "file_id": file_id }))
+            .map_err(|e| format!("opensubtitles download failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("invalid opensubtitles download response: {}", e))?;
+
+        ureq::get(&download.link)
+            .call()
+            .map_err(|e| format!("opensubtitles download failed: {}", e))?
+            .into_string()
+            .map_err(|e| format!("opensubtitles download failed: {}", e))
+    }
+
+    /// Downloads a subtitle for each configured language the link doesn't have one in yet.
+    pub fn fetch(&self, media: &MediaData, source: &std::path::Path, link: &std::path::Path) -> Result<(), String> {
+        let missing = missing_languages(link, &self.config.languages);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let hash = moviehash(source).ok();
+        for language in missing {
+            match self.search(media, hash, language)? {
+                Some(file_id) => {
+                    let path = subtitle_path(link, language);
+                    println!("downloading subtitles: {:?}", path);
+                    let contents = self.download(file_id)?;
+                    std::fs::write(&path, contents).map_err(|e| format!("unable to write {:?}: {}", path, e))?;
+                }
+                None => println!("no {} subtitles found for {:?}", language, link),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Synthetic incoming trees for benchmarks and performance bug reports.
+
+/// Release style filenames covering each of the analyzer's rules, plus the usual garbage.
+pub fn filenames(shows: usize, episodes: usize, movies: usize) -> Vec<std::path::PathBuf> {
+    let mut files = vec![];
+
+    for show in 0..shows {
+        let directory = std::path::PathBuf::from(format!("Show {} Season 1", show));
+        for episode in 1..=episodes {
+            let name = match show % 4 {
+                0 => format!("Show.{}.S01E{:02}.1080p.WEB.x264-GRP.mkv", show, episode),
+                1 => format!("[Group] Show {} - {:02} [1080p].mkv", show, episode),
+                2 => format!("Show {} E{:02} 'Episode Title'.mkv", show, episode),
+                _ => format!("Show {} {:02}.mp4", show, episode),
+            };
+
+            files.push(directory.join(name));
+            files.push(directory.join(format!("Show {} - {:02}.srt", show, episode)));
+        }
+        files.push(directory.join("release.nfo"));
+    }
+
+    for movie in 0..movies {
+        let directory = std::path::PathBuf::from(format!("Movie {} 2001 1080p", movie));
+        files.push(directory.join(format!("Movie.{}.{}.1080p.BluRay.x264.mkv", movie, 1950 + movie % 70)));
+        files.push(directory.join("poster.jpg"));
+    }
+
+    files
+}
+
+/// Creates empty files for `filenames` under `root`, returning how many were created.
+pub fn generate_tree(root: &std::path::Path, shows: usize, episodes: usize, movies: usize) -> std::io::Result<usize> {
+    let files = filenames(shows, episodes, movies);
+
+    for file in files.iter() {
+        let path = root.join(file);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, "")?;
+    }
+
+    Ok(files.len())
+}
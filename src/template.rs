@@ -0,0 +1,277 @@
+//! Naming templates: `{variable}` placeholders, with `<...>` marking an optional section that's only
+//! rendered when every variable inside it has a value (`{title}< ({year})>`). Values go through
+//! the filters after the variable's name in order, like `{title|slug|truncate:60}`.
+
+use crate::config::NamingConfig;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Variable(String, Vec<Filter>),
+    Optional(Vec<Part>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Filter {
+    /// ASCII letters and digits, lowercased, with anything else between them as a single `-`.
+    Slug,
+    /// `pad2`, zeros on the left up to that many characters.
+    Pad(usize),
+    /// `truncate:60`, at most that many bytes (not characters, filesystems limit bytes) without
+    /// splitting a character. Separators left at the end of a cut value are dropped.
+    Truncate(usize),
+}
+
+impl Filter {
+    const AVAILABLE: &str = "slug, pad<width>, truncate:<bytes>";
+
+    fn parse(filter: &str) -> Result<Self, String> {
+        let number = |n: &str| n.parse::<usize>().ok().filter(|n| *n > 0);
+
+        match filter {
+            "slug" => Ok(Filter::Slug),
+            _ if filter.starts_with("pad") => number(&filter[3..]).map(Filter::Pad).ok_or_else(|| format!("pad needs a width, like pad2, not {:?}", filter)),
+            _ if filter.starts_with("truncate") => match filter["truncate".len()..].strip_prefix(':').and_then(number) {
+                Some(length) => Ok(Filter::Truncate(length)),
+                None => Err(format!("truncate needs a length, like truncate:60, not {:?}", filter)),
+            },
+            _ => Err(format!("unknown filter {:?}, available: {}", filter, Self::AVAILABLE)),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::Slug => value
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>()
+                .join("-")
+                .to_ascii_lowercase(),
+            Filter::Pad(width) => format!("{:0>width$}", value, width = width),
+            Filter::Truncate(length) => truncate(&value, *length),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TemplateError {
+    pub source: String,
+    pub offset: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let column = self.source[..self.offset].chars().count();
+
+        writeln!(f, "{} at offset {}", self.message, self.offset)?;
+        writeln!(f, "    {}", self.source)?;
+        write!(f, "    {}^", " ".repeat(column))
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, TemplateError> {
+        let error = |offset: usize, message: String| TemplateError { source: source.to_string(), offset, message };
+
+        let mut stack: Vec<(usize, Vec<Part>)> = vec![(0, vec![])];
+        let mut literal = String::new();
+        let mut chars = source.char_indices();
+
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(error(offset, "unclosed '{'".to_string())),
+                        }
+                    }
+
+                    let mut filters = name.split('|');
+                    let name = filters.next().unwrap().to_string();
+                    if !variables.contains(&name.as_str()) {
+                        let available = variables.iter().map(|v| format!("{{{}}}", v)).collect::<Vec<_>>().join(", ");
+                        return Err(error(offset + 1, format!("unknown variable {:?}, available: {}", name, available)));
+                    }
+
+                    let mut filter_offset = offset + 1 + name.len() + 1;
+                    let filters = filters
+                        .map(|filter| {
+                            let parsed = Filter::parse(filter).map_err(|message| error(filter_offset, message));
+                            filter_offset += filter.len() + 1;
+                            parsed
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    let parts = &mut stack.last_mut().unwrap().1;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Variable(name, filters));
+                }
+                '}' => return Err(error(offset, "unexpected '}'".to_string())),
+                '<' => {
+                    if !literal.is_empty() {
+                        stack.last_mut().unwrap().1.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    stack.push((offset, vec![]));
+                }
+                '>' => {
+                    if stack.len() == 1 {
+                        return Err(error(offset, "unexpected '>'".to_string()));
+                    }
+
+                    let (_, mut parts) = stack.pop().unwrap();
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    stack.last_mut().unwrap().1.push(Part::Optional(parts));
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if stack.len() > 1 {
+            return Err(error(stack.last().unwrap().0, "unclosed '<'".to_string()));
+        }
+
+        let mut parts = stack.pop().unwrap().1;
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { source: source.to_string(), parts })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn uses(&self, variable: &str) -> bool {
+        fn uses(parts: &[Part], variable: &str) -> bool {
+            parts.iter().any(|part| match part {
+                Part::Literal(_) => false,
+                Part::Variable(name, _) => name == variable,
+                Part::Optional(parts) => uses(parts, variable),
+            })
+        }
+
+        uses(&self.parts, variable)
+    }
+
+    fn filtered(filters: &[Filter], value: String) -> String {
+        filters.iter().fold(value, |value, filter| filter.apply(value))
+    }
+
+    fn render_parts(parts: &[Part], lookup: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+        let mut output = String::new();
+
+        for part in parts {
+            match part {
+                Part::Literal(text) => output.push_str(text),
+                Part::Variable(name, filters) => output.push_str(&Self::filtered(filters, lookup(name)?)),
+                Part::Optional(parts) => output.push_str(&Self::render_parts(parts, lookup).unwrap_or_default()),
+            }
+        }
+
+        Some(output)
+    }
+
+    /// Variables without a value outside of an optional section render as empty.
+    pub fn render(&self, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+        let mut output = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Variable(name, filters) => output.push_str(&Self::filtered(filters, lookup(name).unwrap_or_default())),
+                part => output.push_str(&Self::render_parts(std::slice::from_ref(part), lookup).unwrap_or_default()),
+            }
+        }
+
+        output
+    }
+}
+
+/// At most `length` bytes of `value` without splitting a character, with separators left at the
+/// end of a cut one dropped.
+pub fn truncate(value: &str, length: usize) -> String {
+    if value.len() <= length {
+        return value.to_string();
+    }
+
+    let end = (0..=length).rev().find(|i| value.is_char_boundary(*i)).unwrap();
+    value[..end].trim_end_matches([' ', '-', '.', '_']).to_string()
+}
+
+/// The compiled `[naming]` templates.
+#[derive(Clone, Debug)]
+pub struct Naming {
+    pub show_folder: Template,
+    pub season_folder: Template,
+    pub episode: Template,
+    pub movie_folder: Template,
+    pub movie: Template,
+}
+
+fn example_value(variable: &str) -> Option<String> {
+    match variable {
+        "name" => Some("show".to_string()),
+        "season" => Some("2".to_string()),
+        "episode" => Some("5".to_string()),
+        "last_episode" => Some("6".to_string()),
+        "group" => Some("group".to_string()),
+        "title" => Some("the matrix".to_string()),
+        "year" => Some("1999".to_string()),
+        "container" => Some("mkv".to_string()),
+        _ => None,
+    }
+}
+
+/// Variables for templates naming a single file, which can describe its streams.
+fn file_variables(variables: &[&'static str]) -> Vec<&'static str> {
+    #[cfg(feature = "mediainfo")]
+    return [variables, &["container"], crate::mediainfo::VARIABLES].concat();
+
+    #[cfg(not(feature = "mediainfo"))]
+    [variables, &["container"]].concat()
+}
+
+pub fn episode_variables() -> Vec<&'static str> {
+    file_variables(&["name", "season", "episode", "last_episode", "group"])
+}
+
+pub fn compile(key: &str, source: &str, default: &str, variables: &[&str]) -> Result<Template, String> {
+    Template::parse(source, variables).map_err(|e| {
+        let example = Template::parse(default, variables).unwrap().render(&example_value);
+        format!("invalid {} template, {}\n    for example {:?} renders as {:?}", key, e, default, example)
+    })
+}
+
+impl Naming {
+    pub fn compile(config: &NamingConfig) -> Result<Self, String> {
+        let defaults = NamingConfig::default();
+
+        Ok(Self {
+            show_folder: compile("naming.show_folder", &config.show_folder, &defaults.show_folder, &["name"])?,
+            season_folder: compile("naming.season_folder", &config.season_folder, &defaults.season_folder, &["name", "season"])?,
+            episode: compile("naming.episode", &config.episode, &defaults.episode, &episode_variables())?,
+            movie_folder: compile("naming.movie_folder", &config.movie_folder, &defaults.movie_folder, &["title", "year"])?,
+            movie: compile("naming.movie", &config.movie, &defaults.movie, &file_variables(&["title", "year"]))?,
+        })
+    }
+}
+
+impl Default for Naming {
+    fn default() -> Self {
+        Self::compile(&NamingConfig::default()).unwrap()
+    }
+}
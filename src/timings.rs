@@ -0,0 +1,137 @@
+//! `--timings`: how long each phase of a run took and how much it did, to tell on slow NAS
+//! hardware whether it's walking the incoming directory, parsing names or the filesystem
+//! operations that are worth tuning.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::Runner;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Walking the incoming directory and reading each file's metadata.
+    Scan,
+    /// Parsing names, and probing containers when that's enabled.
+    Analyze,
+    Plan,
+    /// Making links and `.strm` files, with their directories.
+    Link,
+    /// Removing links that are gone or changed, and purging garbage.
+    Cleanup,
+    /// Removing directories left empty.
+    Prune,
+}
+
+impl Phase {
+    fn unit(&self) -> &'static str {
+        match self {
+            Phase::Scan | Phase::Analyze => "files",
+            Phase::Plan => "operations",
+            Phase::Link => "links",
+            Phase::Cleanup => "files removed",
+            Phase::Prune => "directories removed",
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Phase::Scan => write!(f, "scan"),
+            Phase::Analyze => write!(f, "analyze"),
+            Phase::Plan => write!(f, "plan"),
+            Phase::Link => write!(f, "link"),
+            Phase::Cleanup => write!(f, "cleanup"),
+            Phase::Prune => write!(f, "prune"),
+        }
+    }
+}
+
+/// Wall time and operation count per phase, adding up when a phase happens more than once.
+#[derive(Debug, Default)]
+pub struct Timings {
+    phases: RefCell<BTreeMap<Phase, (Duration, usize)>>,
+}
+
+impl Timings {
+    pub fn add(&self, phase: Phase, elapsed: Duration, count: usize) {
+        let mut phases = self.phases.borrow_mut();
+        let entry = phases.entry(phase).or_default();
+        entry.0 += elapsed;
+        entry.1 += count;
+    }
+
+    /// Runs `f` as part of `phase`, doing `count` of its result operations.
+    pub fn time<T>(&self, phase: Phase, f: impl FnOnce() -> T, count: impl Fn(&T) -> usize) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.add(phase, started.elapsed(), count(&result));
+        result
+    }
+
+    pub fn get(&self, phase: Phase) -> Option<(Duration, usize)> {
+        self.phases.borrow().get(&phase).copied()
+    }
+
+    pub fn print(&self) {
+        println!("timings:");
+        for (phase, (elapsed, count)) in self.phases.borrow().iter() {
+            println!("  {:<8} {:>9.3}s  {} {}", phase.to_string(), elapsed.as_secs_f64(), count, phase.unit());
+        }
+    }
+}
+
+/// Adds the time `base` spends changing things to the phase each change belongs to.
+pub struct TimingRunner<'a> {
+    base: &'a dyn Runner,
+    timings: &'a Timings,
+}
+
+impl<'a> TimingRunner<'a> {
+    pub fn new(base: &'a dyn Runner, timings: &'a Timings) -> Self {
+        Self { base, timings }
+    }
+
+    /// Directories made for links count as time spent linking, but not as links.
+    fn timed(&self, phase: Phase, count: usize, f: impl FnOnce() -> std::io::Result<()>) -> std::io::Result<()> {
+        self.timings.time(phase, f, |r| if r.is_ok() { count } else { 0 })
+    }
+}
+
+impl Runner for TimingRunner<'_> {
+    fn remove_dir(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.timed(Phase::Prune, 1, || self.base.remove_dir(path))
+    }
+    fn remove_file(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.timed(Phase::Cleanup, 1, || self.base.remove_file(path))
+    }
+    fn create_dir_all(&self, path: &std::path::Path) -> std::io::Result<()> {
+        self.timed(Phase::Link, 0, || self.base.create_dir_all(path))
+    }
+    fn hard_link(&self, original: &std::path::Path, link: &std::path::Path) -> std::io::Result<()> {
+        self.timed(Phase::Link, 1, || self.base.hard_link(original, link))
+    }
+    fn write_file(&self, path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+        self.timed(Phase::Link, 1, || self.base.write_file(path, contents))
+    }
+
+    fn exists(&self, path: &std::path::Path) -> bool {
+        self.base.exists(path)
+    }
+    fn is_dir(&self, path: &std::path::Path) -> bool {
+        self.base.is_dir(path)
+    }
+    fn list_dir(&self, path: &std::path::Path) -> Vec<std::path::PathBuf> {
+        self.base.list_dir(path)
+    }
+    fn inode(&self, path: &std::path::Path) -> Option<u64> {
+        self.base.inode(path)
+    }
+    fn read_to_string(&self, path: &std::path::Path) -> Option<String> {
+        self.base.read_to_string(path)
+    }
+    fn sync(&self) {
+        self.base.sync();
+    }
+}
@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+const API_URL: &str = "https://api.themoviedb.org/3";
+
+#[derive(Deserialize)]
+struct SearchResults {
+    results: Vec<SearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct MovieDetails {
+    belongs_to_collection: Option<Collection>,
+}
+
+#[derive(Deserialize)]
+struct Collection {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ShowDetails {
+    seasons: Vec<SeasonSummary>,
+}
+
+#[derive(Deserialize)]
+struct SeasonSummary {
+    season_number: u32,
+    episode_count: u32,
+}
+
+#[derive(Deserialize)]
+struct ExternalIds {
+    tvdb_id: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShowId {
+    Tvdb(u64),
+    Tmdb(u64),
+}
+
+impl std::fmt::Display for ShowId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShowId::Tvdb(id) => write!(f, "[tvdbid-{}]", id),
+            ShowId::Tmdb(id) => write!(f, "[tmdbid-{}]", id),
+        }
+    }
+}
+
+/// What TMDB answered on earlier runs, kept in the state: folder names carry these ids, and
+/// they shouldn't change whenever TMDB can't be reached.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lookups {
+    /// By `movie_key`, `None` for movies TMDB doesn't have.
+    pub movies: BTreeMap<String, Option<u64>>,
+    /// By movie id, `None` for movies that aren't in one.
+    pub collections: BTreeMap<u64, Option<String>>,
+    pub shows: BTreeMap<String, Option<ShowId>>,
+}
+
+fn movie_key(title: &str, year: Option<u32>) -> String {
+    match year {
+        Some(year) => format!("{} ({})", title, year),
+        None => title.to_string(),
+    }
+}
+
+pub struct Tmdb {
+    api_key: String,
+    lookups: RefCell<Lookups>,
+    tv_ids: RefCell<HashMap<String, Option<u64>>>,
+    seasons: RefCell<HashMap<u64, Option<BTreeMap<u32, u32>>>>,
+    /// Lookups that failed with nothing known from earlier runs, see `errors`.
+    errors: RefCell<Vec<String>>,
+}
+
+impl Tmdb {
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            lookups: RefCell::new(Lookups::default()),
+            tv_ids: RefCell::new(HashMap::new()),
+            seasons: RefCell::new(HashMap::new()),
+            errors: RefCell::new(vec![]),
+        }
+    }
+
+    /// Starting from what earlier runs found out.
+    pub fn with_lookups(self, lookups: Lookups) -> Self {
+        *self.lookups.borrow_mut() = lookups;
+        self
+    }
+
+    pub fn lookups(&self) -> Lookups {
+        self.lookups.borrow().clone()
+    }
+
+    /// Lookups that couldn't be done, with names depending on them coming out without their id and
+    /// seasons without their episode counts. A run shouldn't link anything then, the folders would
+    /// be renamed back and forth.
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.borrow().clone()
+    }
+
+    fn failed<T>(&self, error: String) -> Option<T> {
+        self.errors.borrow_mut().push(error);
+        None
+    }
+
+    /// `None` when TMDB doesn't have it, errors are for when it can't tell.
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<Option<T>, String> {
+        let mut request = ureq::get(&format!("{}{}", API_URL, path)).query("api_key", &self.api_key);
+        for (key, value) in query {
+            request = request.query(key, value);
+        }
+
+        match request.call() {
+            Ok(response) => response.into_json().map(Some).map_err(|e| format!("invalid tmdb response for {}: {}", path, e)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(format!("tmdb request {} failed: {}", path, e)),
+        }
+    }
+
+    pub fn ping(&self) -> Result<(), String> {
+        ureq::get(&format!("{}/configuration", API_URL))
+            .query("api_key", &self.api_key)
+            .call()
+            .map_err(|e| format!("tmdb request failed: {}", e))?;
+
+        Ok(())
+    }
+
+    pub fn movie_id(&self, title: &str, year: Option<u32>) -> Option<u64> {
+        let key = movie_key(title, year);
+        if let Some(cached) = self.lookups.borrow().movies.get(&key) {
+            return *cached;
+        }
+
+        let year_string = year.map(|y| y.to_string());
+        let mut query = vec![("query", title)];
+        if let Some(y) = &year_string {
+            query.push(("year", y));
+        }
+
+        let id = match self.get::<SearchResults>("/search/movie", &query) {
+            Ok(results) => results.and_then(|r| r.results.first().map(|r| r.id)),
+            Err(e) => return self.failed(e),
+        };
+
+        self.lookups.borrow_mut().movies.insert(key, id);
+        id
+    }
+
+    pub fn movie_collection(&self, title: &str, year: Option<u32>) -> Option<String> {
+        let id = self.movie_id(title, year)?;
+        if let Some(cached) = self.lookups.borrow().collections.get(&id) {
+            return cached.clone();
+        }
+
+        let collection = match self.get::<MovieDetails>(&format!("/movie/{}", id), &[]) {
+            Ok(details) => details.and_then(|d| d.belongs_to_collection).map(|c| c.name),
+            Err(e) => return self.failed(e),
+        };
+
+        self.lookups.borrow_mut().collections.insert(id, collection.clone());
+        collection
+    }
+
+    fn tv_id(&self, name: &str) -> Result<Option<u64>, String> {
+        if let Some(cached) = self.tv_ids.borrow().get(name) {
+            return Ok(*cached);
+        }
+
+        let id = self.get::<SearchResults>("/search/tv", &[("query", name)])?.and_then(|r| r.results.first().map(|r| r.id));
+
+        self.tv_ids.borrow_mut().insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    pub fn show_id(&self, name: &str) -> Option<ShowId> {
+        if let Some(cached) = self.lookups.borrow().shows.get(name) {
+            return *cached;
+        }
+
+        let id = match self.tv_id(name) {
+            Ok(Some(id)) => match self.get::<ExternalIds>(&format!("/tv/{}/external_ids", id), &[]) {
+                Ok(external) => Some(external.and_then(|e| e.tvdb_id).map_or(ShowId::Tmdb(id), ShowId::Tvdb)),
+                Err(e) => return self.failed(e),
+            },
+            Ok(None) => None,
+            Err(e) => return self.failed(e),
+        };
+
+        self.lookups.borrow_mut().shows.insert(name.to_string(), id);
+        id
+    }
+
+    /// Episode count of each season, as far as TMDB knows (airing seasons include announced episodes).
+    pub fn season_episode_counts(&self, name: &str) -> Option<BTreeMap<u32, u32>> {
+        let id = self.tv_id(name).unwrap_or_else(|e| self.failed(e))?;
+        if let Some(cached) = self.seasons.borrow().get(&id) {
+            return cached.clone();
+        }
+
+        let seasons = match self.get::<ShowDetails>(&format!("/tv/{}", id), &[]) {
+            Ok(details) => details.map(|details| details.seasons.iter().map(|s| (s.season_number, s.episode_count)).collect()),
+            Err(e) => return self.failed(e),
+        };
+
+        self.seasons.borrow_mut().insert(id, seasons.clone());
+        seasons
+    }
+}
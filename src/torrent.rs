@@ -0,0 +1,380 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+
+use crate::config::{TorrentClientKind, TorrentConfig};
+
+#[derive(Clone, Debug)]
+pub struct Torrent {
+    pub hash: String,
+    pub name: String,
+    pub ratio: f64,
+    /// Seconds spent seeding.
+    pub seeding_time: u64,
+    pub files: Vec<std::path::PathBuf>,
+    /// Downloading or seeding, as opposed to stopped.
+    pub active: bool,
+}
+
+pub trait TorrentClient {
+    fn torrents(&self) -> Result<Vec<Torrent>, String>;
+    fn remove(&self, torrent: &Torrent, delete_data: bool) -> Result<(), String>;
+}
+
+pub fn connect(config: &TorrentConfig) -> Result<Box<dyn TorrentClient>, String> {
+    let url = config.url.trim_end_matches('/').to_string();
+
+    Ok(match config.client {
+        TorrentClientKind::Qbittorrent => Box::new(Qbittorrent::login(url, config)?),
+        TorrentClientKind::Transmission => Box::new(Transmission::new(url, config)),
+        TorrentClientKind::Deluge => Box::new(Deluge::login(url, config)?),
+    })
+}
+
+fn session_cookie(response: &ureq::Response) -> Option<String> {
+    response
+        .all("set-cookie")
+        .first()
+        .and_then(|c| c.split(';').next())
+        .map(|c| c.to_string())
+}
+
+fn local_path(config: &TorrentConfig, path: std::path::PathBuf) -> std::path::PathBuf {
+    for (remote, local) in config.paths.iter() {
+        if let Ok(rest) = path.strip_prefix(remote) {
+            return std::path::Path::new(local).join(rest);
+        }
+    }
+
+    path
+}
+
+pub struct Qbittorrent {
+    url: String,
+    cookie: String,
+    config: TorrentConfig,
+}
+
+#[derive(Deserialize)]
+struct QbittorrentTorrent {
+    hash: String,
+    name: String,
+    ratio: f64,
+    seeding_time: u64,
+    save_path: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct QbittorrentFile {
+    name: String,
+}
+
+impl Qbittorrent {
+    fn login(url: String, config: &TorrentConfig) -> Result<Self, String> {
+        let response = ureq::post(&format!("{}/api/v2/auth/login", url))
+            .set("Referer", &url)
+            .send_form(&[
+                ("username", config.username.as_deref().unwrap_or("")),
+                ("password", config.password.as_deref().unwrap_or("")),
+            ])
+            .map_err(|e| format!("qbittorrent login failed: {}", e))?;
+
+        let cookie = session_cookie(&response).ok_or("qbittorrent login rejected")?;
+
+        Ok(Self { url, cookie, config: config.clone() })
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T, String> {
+        let mut request = ureq::get(&format!("{}{}", self.url, path)).set("Cookie", &self.cookie);
+        for (key, value) in query {
+            request = request.query(key, value);
+        }
+
+        request
+            .call()
+            .map_err(|e| format!("qbittorrent request {} failed: {}", path, e))?
+            .into_json()
+            .map_err(|e| format!("invalid qbittorrent response for {}: {}", path, e))
+    }
+}
+
+impl TorrentClient for Qbittorrent {
+    fn torrents(&self) -> Result<Vec<Torrent>, String> {
+        let mut torrents = vec![];
+        for t in self.get::<Vec<QbittorrentTorrent>>("/api/v2/torrents/info", &[])? {
+            let files = self.get::<Vec<QbittorrentFile>>("/api/v2/torrents/files", &[("hash", &t.hash)])?;
+
+            torrents.push(Torrent {
+                files: files
+                    .into_iter()
+                    .map(|f| local_path(&self.config, std::path::Path::new(&t.save_path).join(f.name)))
+                    .collect(),
+                active: !matches!(t.state.as_str(), "pausedUP" | "stoppedUP" | "pausedDL" | "stoppedDL" | "error" | "missingFiles"),
+                hash: t.hash,
+                name: t.name,
+                ratio: t.ratio,
+                seeding_time: t.seeding_time,
+            });
+        }
+
+        Ok(torrents)
+    }
+
+    fn remove(&self, torrent: &Torrent, delete_data: bool) -> Result<(), String> {
+        ureq::post(&format!("{}/api/v2/torrents/delete", self.url))
+            .set("Cookie", &self.cookie)
+            .send_form(&[("hashes", &torrent.hash), ("deleteFiles", if delete_data { "true" } else { "false" })])
+            .map_err(|e| format!("qbittorrent delete failed: {}", e))?;
+
+        Ok(())
+    }
+}
+
+pub struct Transmission {
+    url: String,
+    session: std::cell::RefCell<String>,
+    config: TorrentConfig,
+}
+
+#[derive(Deserialize)]
+struct TransmissionResponse<T> {
+    result: String,
+    arguments: T,
+}
+
+#[derive(Deserialize)]
+struct TransmissionTorrents {
+    torrents: Vec<TransmissionTorrent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransmissionTorrent {
+    hash_string: String,
+    name: String,
+    upload_ratio: f64,
+    seconds_seeding: u64,
+    download_dir: String,
+    status: u32,
+    files: Vec<TransmissionFile>,
+}
+
+#[derive(Deserialize)]
+struct TransmissionFile {
+    name: String,
+}
+
+impl Transmission {
+    fn new(url: String, config: &TorrentConfig) -> Self {
+        Self { url, session: std::cell::RefCell::new(String::new()), config: config.clone() }
+    }
+
+    fn rpc<T: serde::de::DeserializeOwned>(&self, body: serde_json::Value) -> Result<T, String> {
+        let request = || {
+            let mut request = ureq::post(&format!("{}/transmission/rpc", self.url))
+                .set("X-Transmission-Session-Id", &self.session.borrow());
+            if let Some(username) = &self.config.username {
+                let credentials = format!("{}:{}", username, self.config.password.as_deref().unwrap_or(""));
+                request = request.set("Authorization", &format!("Basic {}", base64(credentials.as_bytes())));
+            }
+            request.send_json(body.clone()).map_err(Box::new)
+        };
+
+        let response = match request() {
+            Err(e) => match *e {
+                ureq::Error::Status(409, response) => {
+                    *self.session.borrow_mut() = response.header("X-Transmission-Session-Id").unwrap_or("").to_string();
+                    request()
+                }
+                e => Err(Box::new(e)),
+            },
+            ok => ok,
+        };
+
+        let response: TransmissionResponse<T> = response
+            .map_err(|e| format!("transmission request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("invalid transmission response: {}", e))?;
+
+        if response.result != "success" {
+            return Err(format!("transmission request failed: {}", response.result));
+        }
+
+        Ok(response.arguments)
+    }
+}
+
+impl TorrentClient for Transmission {
+    fn torrents(&self) -> Result<Vec<Torrent>, String> {
+        let response: TransmissionTorrents = self.rpc(serde_json::json!({
+            "method": "torrent-get",
+            "arguments": { "fields": ["hashString", "name", "uploadRatio", "secondsSeeding", "downloadDir", "status", "files"] },
+        }))?;
+
+        Ok(response
+            .torrents
+            .into_iter()
+            .map(|t| Torrent {
+                files: t
+                    .files
+                    .into_iter()
+                    .map(|f| local_path(&self.config, std::path::Path::new(&t.download_dir).join(f.name)))
+                    .collect(),
+                active: t.status != 0,
+                hash: t.hash_string,
+                name: t.name,
+                ratio: t.upload_ratio,
+                seeding_time: t.seconds_seeding,
+            })
+            .collect())
+    }
+
+    fn remove(&self, torrent: &Torrent, delete_data: bool) -> Result<(), String> {
+        self.rpc::<serde_json::Value>(serde_json::json!({
+            "method": "torrent-remove",
+            "arguments": { "ids": [torrent.hash], "delete-local-data": delete_data },
+        }))?;
+
+        Ok(())
+    }
+}
+
+pub struct Deluge {
+    url: String,
+    cookie: String,
+    config: TorrentConfig,
+}
+
+#[derive(Deserialize)]
+struct DelugeResponse<T> {
+    result: Option<T>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct DelugeTorrent {
+    name: String,
+    ratio: f64,
+    seeding_time: u64,
+    save_path: String,
+    state: String,
+    files: Vec<DelugeFile>,
+}
+
+#[derive(Deserialize)]
+struct DelugeFile {
+    path: String,
+}
+
+impl Deluge {
+    fn login(url: String, config: &TorrentConfig) -> Result<Self, String> {
+        let response = ureq::post(&format!("{}/json", url))
+            .send_json(serde_json::json!({
+                "method": "auth.login",
+                "params": [config.password.as_deref().unwrap_or("")],
+                "id": 1,
+            }))
+            .map_err(|e| format!("deluge login failed: {}", e))?;
+
+        let cookie = session_cookie(&response).ok_or("deluge login rejected")?;
+
+        Ok(Self { url, cookie, config: config.clone() })
+    }
+
+    fn rpc<T: serde::de::DeserializeOwned>(&self, method: &str, params: serde_json::Value) -> Result<T, String> {
+        let response: DelugeResponse<T> = ureq::post(&format!("{}/json", self.url))
+            .set("Cookie", &self.cookie)
+            .send_json(serde_json::json!({ "method": method, "params": params, "id": 1 }))
+            .map_err(|e| format!("deluge request {} failed: {}", method, e))?
+            .into_json()
+            .map_err(|e| format!("invalid deluge response for {}: {}", method, e))?;
+
+        match (response.result, response.error) {
+            (_, Some(error)) if !error.is_null() => Err(format!("deluge request {} failed: {}", method, error)),
+            (Some(result), _) => Ok(result),
+            (None, _) => Err(format!("deluge request {} returned nothing", method)),
+        }
+    }
+}
+
+impl TorrentClient for Deluge {
+    fn torrents(&self) -> Result<Vec<Torrent>, String> {
+        let torrents: std::collections::BTreeMap<String, DelugeTorrent> = self.rpc(
+            "core.get_torrents_status",
+            serde_json::json!([{}, ["name", "ratio", "seeding_time", "save_path", "state", "files"]]),
+        )?;
+
+        Ok(torrents
+            .into_iter()
+            .map(|(hash, t)| Torrent {
+                files: t
+                    .files
+                    .into_iter()
+                    .map(|f| local_path(&self.config, std::path::Path::new(&t.save_path).join(f.path)))
+                    .collect(),
+                active: !matches!(t.state.as_str(), "Paused" | "Error"),
+                hash,
+                name: t.name,
+                ratio: t.ratio,
+                seeding_time: t.seeding_time,
+            })
+            .collect())
+    }
+
+    fn remove(&self, torrent: &Torrent, delete_data: bool) -> Result<(), String> {
+        self.rpc::<bool>("core.remove_torrent", serde_json::json!([torrent.hash, delete_data]))?;
+
+        Ok(())
+    }
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::new();
+
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+/// Files that belong to a torrent the client is still downloading or seeding, canonicalized on
+/// both sides: clients report paths through whatever symlinks they were pointed at.
+pub struct SeedingIndex {
+    files: HashSet<std::path::PathBuf>,
+}
+
+impl SeedingIndex {
+    pub fn load(config: &TorrentConfig) -> Result<Self, String> {
+        let torrents = connect(config)?.torrents()?;
+
+        Ok(Self::new(&torrents))
+    }
+
+    pub fn new(torrents: &[Torrent]) -> Self {
+        let files = torrents
+            .iter()
+            .filter(|t| t.active)
+            .flat_map(|t| t.files.iter())
+            .map(|f| std::fs::canonicalize(f).unwrap_or(f.clone()))
+            .collect();
+
+        Self { files }
+    }
+
+    pub fn is_seeding(&self, path: &std::path::Path) -> bool {
+        let path = std::fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+        self.files.contains(&path)
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use crate::config::{Config, TrackerConfig};
+use crate::MediaData;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackerKind {
+    Trakt,
+    Simkl,
+}
+
+impl std::fmt::Display for TrackerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrackerKind::Trakt => write!(f, "trakt"),
+            TrackerKind::Simkl => write!(f, "simkl"),
+        }
+    }
+}
+
+pub struct Tracker<'a> {
+    pub kind: TrackerKind,
+    config: &'a TrackerConfig,
+}
+
+/// Movies and episodes grouped the way both trakt and simkl expect them.
+pub fn collection_body(items: &[&MediaData], list: Option<&str>) -> serde_json::Value {
+    let mut movies = vec![];
+    let mut shows: BTreeMap<&str, BTreeMap<u32, Vec<u32>>> = BTreeMap::new();
+
+    for item in items {
+        match item {
+            // A split movie is collected once.
+            MediaData::Movie { title, year } | MediaData::MoviePart { title, year, part: 1 } => {
+                let mut movie = serde_json::json!({ "title": title, "year": year });
+                if let Some(list) = list {
+                    movie["to"] = list.into();
+                }
+                movies.push(movie);
+            }
+            MediaData::ShowEpisode { name, season, episode } => {
+                shows.entry(name).or_default().entry(*season).or_default().push(*episode);
+            }
+            _ => {}
+        }
+    }
+
+    let shows = shows
+        .into_iter()
+        .map(|(name, seasons)| {
+            let seasons = seasons
+                .into_iter()
+                .map(|(number, episodes)| serde_json::json!({
+                    "number": number,
+                    "episodes": episodes.into_iter().map(|e| serde_json::json!({ "number": e })).collect::<Vec<_>>(),
+                }))
+                .collect::<Vec<_>>();
+
+            let mut show = serde_json::json!({ "title": name, "seasons": seasons });
+            if let Some(list) = list {
+                show["to"] = list.into();
+            }
+            show
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({ "movies": movies, "shows": shows })
+}
+
+impl<'a> Tracker<'a> {
+    pub fn all(config: &'a Config) -> Vec<Self> {
+        let trakt = config.trakt.as_ref().map(|c| Tracker { kind: TrackerKind::Trakt, config: c });
+        let simkl = config.simkl.as_ref().map(|c| Tracker { kind: TrackerKind::Simkl, config: c });
+
+        trakt.into_iter().chain(simkl).collect()
+    }
+
+    pub fn ping(&self) -> Result<(), String> {
+        let request = match self.kind {
+            TrackerKind::Trakt => ureq::get("https://api.trakt.tv/users/settings")
+                .set("trakt-api-version", "2")
+                .set("trakt-api-key", &self.config.client_id),
+            TrackerKind::Simkl => ureq::get("https://api.simkl.com/users/settings")
+                .set("simkl-api-key", &self.config.client_id),
+        };
+
+        request
+            .set("Authorization", &format!("Bearer {}", self.config.access_token))
+            .call()
+            .map_err(|e| format!("{} request failed: {}", self.kind, e))?;
+
+        Ok(())
+    }
+
+    pub fn add_to_collection(&self, items: &[&MediaData]) -> Result<(), String> {
+        let request = match self.kind {
+            TrackerKind::Trakt => ureq::post("https://api.trakt.tv/sync/collection")
+                .set("trakt-api-version", "2")
+                .set("trakt-api-key", &self.config.client_id),
+            TrackerKind::Simkl => ureq::post("https://api.simkl.com/sync/add-to-list")
+                .set("simkl-api-key", &self.config.client_id),
+        };
+
+        let list = match self.kind {
+            TrackerKind::Trakt => None,
+            TrackerKind::Simkl => Some("plantowatch"),
+        };
+
+        request
+            .set("Authorization", &format!("Bearer {}", self.config.access_token))
+            .send_json(collection_body(items, list))
+            .map_err(|e| format!("{} export failed: {}", self.kind, e))?;
+
+        Ok(())
+    }
+}
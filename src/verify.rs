@@ -0,0 +1,89 @@
+//! `harvester verify`: checks the links the state says should be in the library are still there,
+//! and recreates the ones deleted while their source is still around.
+
+use std::os::unix::fs::MetadataExt;
+
+use crate::config::{DeletedLinkPolicy, LinkMode};
+use crate::state::{LinkRecord, State};
+use crate::Runner;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The link was deleted, the source is still there.
+    Missing,
+    /// The source went away, the next run cleans up after it.
+    SourceGone,
+    /// Something else is at the link's path.
+    Replaced,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::SourceGone => write!(f, "source gone"),
+            Problem::Replaced => write!(f, "replaced"),
+        }
+    }
+}
+
+pub fn problem(record: &LinkRecord, link_mode: LinkMode) -> Option<Problem> {
+    let source = std::fs::metadata(&record.source).ok();
+    let link = std::fs::symlink_metadata(&record.link).ok();
+
+    match (source, link) {
+        (None, _) => Some(Problem::SourceGone),
+        (Some(_), None) => Some(Problem::Missing),
+        // `.strm` files are their own inode.
+        (Some(source), Some(link)) if link_mode == LinkMode::Hardlink && source.ino() != link.ino() => Some(Problem::Replaced),
+        _ => None,
+    }
+}
+
+pub fn verify(state: &State, link_mode: LinkMode) -> Vec<(&LinkRecord, Problem)> {
+    state.links.iter().filter_map(|r| Some((r, problem(r, link_mode)?))).collect()
+}
+
+/// What `repair` did about a missing link.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Repair {
+    Recreated(std::path::PathBuf),
+    Tombstoned(std::path::PathBuf),
+    /// `.strm` contents aren't in the state, a regular run writes them again.
+    Skipped(std::path::PathBuf),
+    /// Recreating it didn't work, with why.
+    Failed(std::path::PathBuf, String),
+}
+
+/// Recreates missing hard links, or tombstones them when deleted links are to be respected.
+/// Tombstoned links are no longer in the state's links.
+pub fn repair(state: &mut State, link_mode: LinkMode, policy: DeletedLinkPolicy, runner: &dyn Runner, now: u64) -> Vec<Repair> {
+    let missing = verify(state, link_mode)
+        .into_iter()
+        .filter(|(_, p)| *p == Problem::Missing)
+        .map(|(r, _)| r.clone())
+        .collect::<Vec<_>>();
+
+    let mut repairs = vec![];
+    for record in missing {
+        match (policy, link_mode) {
+            (DeletedLinkPolicy::Tombstone, _) => {
+                state.tombstone(&record, now);
+                repairs.push(Repair::Tombstoned(record.link));
+            }
+            (DeletedLinkPolicy::Recreate, LinkMode::Hardlink) => {
+                let parent = record.link.parent().map_or(Ok(()), |parent| runner.create_dir_all(parent));
+                match parent.and_then(|()| runner.hard_link(&record.source, &record.link)) {
+                    Ok(()) => repairs.push(Repair::Recreated(record.link)),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && crate::plan::is_linked(runner, &record.source, &record.link) => {
+                        repairs.push(Repair::Recreated(record.link))
+                    }
+                    Err(e) => repairs.push(Repair::Failed(record.link, e.to_string())),
+                }
+            }
+            (DeletedLinkPolicy::Recreate, LinkMode::Strm) => repairs.push(Repair::Skipped(record.link)),
+        }
+    }
+
+    repairs
+}